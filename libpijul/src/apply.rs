@@ -41,11 +41,25 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::error::Error for ApplyE
 
 #[derive(Error)]
 pub enum LocalApplyError<T: GraphTxnT + TreeTxnT> {
-    DependencyMissing { hash: crate::pristine::Hash },
-    ChangeAlreadyOnChannel { hash: crate::pristine::Hash },
+    DependencyMissing {
+        hash: crate::pristine::Hash,
+    },
+    ChangeAlreadyOnChannel {
+        hash: crate::pristine::Hash,
+    },
     Txn(#[from] TxnErr<T::GraphError>),
     Tree(#[from] TreeErr<T::TreeError>),
-    Block { block: Position<ChangeId> },
+    Block {
+        block: Position<ChangeId>,
+    },
+    /// A [`LocalApplyError::Block`] error while applying the hunk of
+    /// change `hash` (with internal id `change`) touching `path`.
+    BlockInChange {
+        block: Position<ChangeId>,
+        change: ChangeId,
+        hash: crate::pristine::Hash,
+        path: String,
+    },
     InvalidChange,
     Corruption,
     MakeChange(#[from] crate::change::MakeChangeError<T>),
@@ -63,6 +77,19 @@ impl<T: GraphTxnT + TreeTxnT> std::fmt::Debug for LocalApplyError<T> {
             LocalApplyError::Txn(e) => std::fmt::Debug::fmt(e, fmt),
             LocalApplyError::Tree(e) => std::fmt::Debug::fmt(e, fmt),
             LocalApplyError::Block { block } => write!(fmt, "Block error: {:?}", block),
+            LocalApplyError::BlockInChange {
+                block,
+                change,
+                hash,
+                path,
+            } => write!(
+                fmt,
+                "Block error: {:?}, while applying change {} ({:?}) to {:?}",
+                block,
+                hash.to_base32(),
+                change,
+                path
+            ),
             LocalApplyError::InvalidChange => write!(fmt, "Invalid change"),
             LocalApplyError::Corruption => write!(fmt, "Corruption"),
             LocalApplyError::MakeChange(e) => std::fmt::Debug::fmt(e, fmt),
@@ -82,6 +109,19 @@ impl<T: GraphTxnT + TreeTxnT> std::fmt::Display for LocalApplyError<T> {
             LocalApplyError::Txn(e) => std::fmt::Display::fmt(e, fmt),
             LocalApplyError::Tree(e) => std::fmt::Display::fmt(e, fmt),
             LocalApplyError::Block { block } => write!(fmt, "Block error: {:?}", block),
+            LocalApplyError::BlockInChange {
+                block,
+                change,
+                hash,
+                path,
+            } => write!(
+                fmt,
+                "Block error: {:?}, while applying change {} ({:?}) to {:?}",
+                block,
+                hash.to_base32(),
+                change,
+                path
+            ),
             LocalApplyError::InvalidChange => write!(fmt, "Invalid change"),
             LocalApplyError::Corruption => write!(fmt, "Corruption"),
             LocalApplyError::MakeChange(e) => std::fmt::Display::fmt(e, fmt),
@@ -114,6 +154,20 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> From<crate::pristine::TreeEr
 }
 
 impl<T: GraphTxnT + TreeTxnT> LocalApplyError<T> {
+    /// Add the change being applied and the path of the current hunk
+    /// to a [`LocalApplyError::Block`] error.
+    fn in_change(self, change: ChangeId, hash: &Hash, path: &str) -> Self {
+        match self {
+            LocalApplyError::Block { block } => LocalApplyError::BlockInChange {
+                block,
+                change,
+                hash: *hash,
+                path: path.to_string(),
+            },
+            e => e,
+        }
+    }
+
     fn from_missing(err: MissingError<T::GraphError>) -> Self {
         match err {
             MissingError::Txn(e) => LocalApplyError::Txn(TxnErr(e)),
@@ -166,6 +220,28 @@ pub fn apply_change_ws<T: MutTxnT, P: ChangeStore>(
 ) -> Result<(u64, Merkle), ApplyError<P::Error, T>> {
     debug!("apply_change {:?}", hash.to_base32());
     workspace.clear();
+    let (internal, change) = register_remote_change(changes, txn, channel, hash)?;
+    Ok(apply_change_to_channel(
+        txn,
+        channel,
+        &mut |h| changes.knows(h, hash).unwrap(),
+        internal,
+        &hash,
+        &change,
+        workspace,
+    )
+    .map_err(ApplyError::LocalChange)?)
+}
+
+/// Load change `hash`, check that its dependencies are on `channel`,
+/// and register it in `txn` if it isn't known yet. Returns the
+/// internal id of the change.
+fn register_remote_change<T: MutTxnT, P: ChangeStore>(
+    changes: &P,
+    txn: &mut T,
+    channel: &T::Channel,
+    hash: &Hash,
+) -> Result<(ChangeId, Change), ApplyError<P::Error, T>> {
     let change = changes.get_change(&hash).map_err(ApplyError::Changestore)?;
 
     for hash in change.dependencies.iter() {
@@ -190,16 +266,59 @@ pub fn apply_change_ws<T: MutTxnT, P: ChangeStore>(
         internal
     };
     debug!("internal = {:?}", internal);
-    Ok(apply_change_to_channel(
-        txn,
-        channel,
-        &mut |h| changes.knows(h, hash).unwrap(),
-        internal,
-        &hash,
-        &change,
-        workspace,
-    )
-    .map_err(ApplyError::LocalChange)?)
+    Ok((internal, change))
+}
+
+/// Apply the changes in `hashes` to `channel`, in this order, like
+/// [apply_change_ws] would, but repair the contexts (zombies and
+/// cyclic paths) only once, after the last change, on all the inodes
+/// touched by the changes. This is faster when applying many
+/// changes, for example when importing a repository.
+///
+/// The dependencies of each change must be on `channel` or earlier
+/// in `hashes`. If an error occurs, the changes applied before the
+/// error stay applied, but their contexts aren't repaired.
+pub fn apply_changes_ws<T: MutTxnT, P: ChangeStore>(
+    changes: &P,
+    txn: &mut T,
+    channel: &mut T::Channel,
+    hashes: &[Hash],
+    workspace: &mut Workspace,
+) -> Result<Vec<(u64, Merkle)>, ApplyError<P::Error, T>> {
+    let mut result = Vec::with_capacity(hashes.len());
+    let mut inodes = HashSet::new();
+    for hash in hashes {
+        debug!("apply_changes {:?}", hash.to_base32());
+        workspace.clear();
+        let (internal, change) = register_remote_change(changes, txn, channel, hash)?;
+        let (applied, touched) = apply_change_to_channel_without_repair(
+            txn,
+            channel,
+            &mut |h| changes.knows(h, hash).unwrap(),
+            internal,
+            hash,
+            &change,
+            workspace,
+        )
+        .map_err(ApplyError::LocalChange)?;
+        result.push(applied);
+        inodes.extend(touched);
+    }
+    for i in inodes {
+        repair_zombies(txn, T::graph_mut(channel), i).map_err(ApplyError::LocalChange)?;
+    }
+    repair_cyclic_paths(txn, T::graph_mut(channel), workspace).map_err(ApplyError::LocalChange)?;
+    Ok(result)
+}
+
+/// Same as [apply_changes_ws], but allocates its own workspace.
+pub fn apply_changes<T: MutTxnT, P: ChangeStore>(
+    changes: &P,
+    txn: &mut T,
+    channel: &mut T::Channel,
+    hashes: &[Hash],
+) -> Result<Vec<(u64, Merkle)>, ApplyError<P::Error, T>> {
+    apply_changes_ws(changes, txn, channel, hashes, &mut Workspace::new())
 }
 
 pub fn apply_change_rec_ws<T: TxnT + MutTxnT, P: ChangeStore>(
@@ -313,6 +432,38 @@ pub fn apply_change_rec<T: MutTxnT, P: ChangeStore>(
     )
 }
 
+/// Predict the inodes touched by applying change `hash` to `channel`,
+/// without applying it: these are the inodes of all the atoms of the
+/// change, including the inodes the change creates. The inodes that
+/// will need a context repair after applying are not included, since
+/// finding them requires modifying the graph.
+///
+/// If the change is already on `channel`, applying it would not touch
+/// anything, and the result is empty.
+pub fn touched_inodes_for<T: ChannelTxnT + TreeTxnT, P: ChangeStore>(
+    txn: &T,
+    channel: &T::Channel,
+    changes: &P,
+    hash: &Hash,
+) -> Result<HashSet<Position<ChangeId>>, ApplyError<P::Error, T>> {
+    let mut inodes = HashSet::new();
+    // This is the id the change has, or will have once applied.
+    let change_id = make_changeid(txn, hash)?;
+    if txn
+        .get_changeset(txn.changes(channel), &change_id)?
+        .is_some()
+    {
+        return Ok(inodes);
+    }
+    let change = changes.get_change(hash).map_err(ApplyError::Changestore)?;
+    for atom in change.changes.iter().flat_map(|r| r.iter()) {
+        let inode = internal_pos(txn, &atom.inode(), change_id)
+            .map_err(|e| ApplyError::LocalChange(e.into()))?;
+        inodes.insert(inode);
+    }
+    Ok(inodes)
+}
+
 fn apply_change_to_channel<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool>(
     txn: &mut T,
     channel: &mut T::Channel,
@@ -321,6 +472,69 @@ fn apply_change_to_channel<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool
     hash: &Hash,
     change: &Change,
     ws: &mut Workspace,
+) -> Result<(u64, Merkle), LocalApplyError<T>> {
+    let (n, merkle) = put_change_atoms(txn, channel, changes, change_id, hash, change, ws)?;
+
+    let mut inodes = clean_obsolete_pseudo_edges(txn, T::graph_mut(channel), ws, change_id)?;
+    collect_missing_contexts(txn, txn.graph(channel), ws, &change, change_id, &mut inodes)?;
+    for i in inodes {
+        repair_zombies(txn, T::graph_mut(channel), i)?;
+    }
+
+    detect_folder_conflict_resolutions(
+        txn,
+        T::graph_mut(channel),
+        &mut ws.missing_context,
+        change_id,
+        change,
+    )
+    .map_err(LocalApplyError::from_missing)?;
+
+    repair_cyclic_paths(txn, T::graph_mut(channel), ws)?;
+    info!("done applying change");
+    Ok((n, merkle))
+}
+
+/// Same as [apply_change_to_channel], but instead of repairing the
+/// zombies, return the inodes to repair. The files whose paths may
+/// need a repair are accumulated in `ws`, and repaired by the next
+/// call to [repair_cyclic_paths].
+fn apply_change_to_channel_without_repair<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool>(
+    txn: &mut T,
+    channel: &mut T::Channel,
+    changes: &mut F,
+    change_id: ChangeId,
+    hash: &Hash,
+    change: &Change,
+    ws: &mut Workspace,
+) -> Result<((u64, Merkle), HashSet<Position<ChangeId>>), LocalApplyError<T>> {
+    let applied = put_change_atoms(txn, channel, changes, change_id, hash, change, ws)?;
+
+    let mut inodes = clean_obsolete_pseudo_edges(txn, T::graph_mut(channel), ws, change_id)?;
+    collect_missing_contexts(txn, txn.graph(channel), ws, &change, change_id, &mut inodes)?;
+
+    detect_folder_conflict_resolutions(
+        txn,
+        T::graph_mut(channel),
+        &mut ws.missing_context,
+        change_id,
+        change,
+    )
+    .map_err(LocalApplyError::from_missing)?;
+    info!("done applying change, repair deferred");
+    Ok((applied, inodes))
+}
+
+/// Add change `change_id` to `channel`, and insert its vertices and
+/// edges into the graph.
+fn put_change_atoms<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool>(
+    txn: &mut T,
+    channel: &mut T::Channel,
+    changes: &mut F,
+    change_id: ChangeId,
+    hash: &Hash,
+    change: &Change,
+    ws: &mut Workspace,
 ) -> Result<(u64, Merkle), LocalApplyError<T>> {
     ws.assert_empty();
     let n = txn.apply_counter(channel);
@@ -333,9 +547,9 @@ fn apply_change_to_channel<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool
         };
     debug!("apply change to channel");
     let now = std::time::Instant::now();
-    for change_ in change.changes.iter() {
-        debug!("Applying {:?} (1)", change_);
-        for change_ in change_.iter() {
+    for hunk in change.changes.iter() {
+        debug!("Applying {:?} (1)", hunk);
+        for change_ in hunk.iter() {
             match *change_ {
                 Atom::NewVertex(ref n) => put_newvertex(
                     txn,
@@ -345,7 +559,8 @@ fn apply_change_to_channel<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool
                     ws,
                     change_id,
                     n,
-                )?,
+                )
+                .map_err(|e| e.in_change(change_id, hash, hunk.path()))?,
                 Atom::EdgeMap(ref n) => {
                     for edge in n.edges.iter() {
                         if !edge.flag.contains(EdgeFlags::DELETED) {
@@ -358,16 +573,17 @@ fn apply_change_to_channel<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool
                                 edge,
                                 |_, _| true,
                                 |h| change.knows(h),
-                            )?;
+                            )
+                            .map_err(|e| e.in_change(change_id, hash, hunk.path()))?;
                         }
                     }
                 }
             }
         }
     }
-    for change_ in change.changes.iter() {
-        debug!("Applying {:?} (2)", change_);
-        for change_ in change_.iter() {
+    for hunk in change.changes.iter() {
+        debug!("Applying {:?} (2)", hunk);
+        for change_ in hunk.iter() {
             if let Atom::EdgeMap(ref n) = *change_ {
                 for edge in n.edges.iter() {
                     if edge.flag.contains(EdgeFlags::DELETED) {
@@ -380,31 +596,20 @@ fn apply_change_to_channel<T: ChannelMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool
                             edge,
                             |_, _| true,
                             |h| change.knows(h),
-                        )?;
+                        )
+                        .map_err(|e| e.in_change(change_id, hash, hunk.path()))?;
                     }
                 }
             }
         }
     }
     crate::TIMERS.lock().unwrap().apply += now.elapsed();
-
-    let mut inodes = clean_obsolete_pseudo_edges(txn, T::graph_mut(channel), ws, change_id)?;
-    collect_missing_contexts(txn, txn.graph(channel), ws, &change, change_id, &mut inodes)?;
-    for i in inodes {
-        repair_zombies(txn, T::graph_mut(channel), i)?;
+    // The cache isn't used past this point, and the graph is about
+    // to be modified without splitting blocks.
+    if let Some(ref mut cache) = ws.block_cache {
+        cache.clear()
     }
 
-    detect_folder_conflict_resolutions(
-        txn,
-        T::graph_mut(channel),
-        &mut ws.missing_context,
-        change_id,
-        change,
-    )
-    .map_err(LocalApplyError::from_missing)?;
-
-    repair_cyclic_paths(txn, T::graph_mut(channel), ws)?;
-    info!("done applying change");
     Ok((n, merkle))
 }
 
@@ -526,12 +731,111 @@ pub struct Workspace {
     adjbuf: Vec<SerializedEdge>,
     alive_folder: HashMap<Vertex<ChangeId>, bool>,
     folder_stack: Vec<(Vertex<ChangeId>, bool)>,
+    block_cache: Option<BlockCache>,
+}
+
+/// The results of `find_block` and `find_block_end` since the last
+/// time the graph was modified. When full, the cache is emptied
+/// rather than evicting entries one by one.
+#[derive(Default)]
+struct BlockCache {
+    capacity: usize,
+    start: HashMap<Position<ChangeId>, Vertex<ChangeId>>,
+    end: HashMap<Position<ChangeId>, Vertex<ChangeId>>,
+}
+
+impl BlockCache {
+    fn insert(
+        map: &mut HashMap<Position<ChangeId>, Vertex<ChangeId>>,
+        capacity: usize,
+        p: Position<ChangeId>,
+        v: Vertex<ChangeId>,
+    ) {
+        if map.len() >= capacity {
+            map.clear()
+        }
+        map.insert(p, v);
+    }
+
+    fn clear(&mut self) {
+        self.start.clear();
+        self.end.clear();
+    }
 }
 
 impl Workspace {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// A workspace remembering up to `capacity` block lookups
+    /// (in each direction) while applying a change, which speeds up
+    /// changes referencing many positions in the same blocks.
+    pub fn with_block_cache(capacity: usize) -> Self {
+        Workspace {
+            block_cache: Some(BlockCache {
+                capacity,
+                ..BlockCache::default()
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// Same as [`GraphTxnT::find_block`], using the block cache if
+    /// there is one.
+    pub(crate) fn find_block<T: GraphTxnT>(
+        &mut self,
+        txn: &T,
+        graph: &T::Graph,
+        p: Position<ChangeId>,
+    ) -> Result<Vertex<ChangeId>, BlockError<T::GraphError>> {
+        if let Some(ref mut cache) = self.block_cache {
+            if let Some(v) = cache.start.get(&p) {
+                return Ok(*v);
+            }
+            let v = *txn.find_block(graph, p)?;
+            BlockCache::insert(&mut cache.start, cache.capacity, p, v);
+            Ok(v)
+        } else {
+            Ok(*txn.find_block(graph, p)?)
+        }
+    }
+
+    /// Same as [`GraphTxnT::find_block_end`], using the block cache
+    /// if there is one.
+    pub(crate) fn find_block_end<T: GraphTxnT>(
+        &mut self,
+        txn: &T,
+        graph: &T::Graph,
+        p: Position<ChangeId>,
+    ) -> Result<Vertex<ChangeId>, BlockError<T::GraphError>> {
+        if let Some(ref mut cache) = self.block_cache {
+            if let Some(v) = cache.end.get(&p) {
+                return Ok(*v);
+            }
+            let v = *txn.find_block_end(graph, p)?;
+            BlockCache::insert(&mut cache.end, cache.capacity, p, v);
+            Ok(v)
+        } else {
+            Ok(*txn.find_block_end(graph, p)?)
+        }
+    }
+
+    /// Same as [`GraphMutTxnT::split_block`], but also invalidates
+    /// the block cache. All block splits during an application must
+    /// go through this method.
+    pub(crate) fn split_block<T: GraphMutTxnT>(
+        &mut self,
+        txn: &mut T,
+        graph: &mut T::Graph,
+        key: &Vertex<ChangeId>,
+        pos: ChangePosition,
+    ) -> Result<(), TxnErr<T::GraphError>> {
+        if let Some(ref mut cache) = self.block_cache {
+            cache.clear()
+        }
+        txn.split_block(graph, key, pos, &mut self.adjbuf)
+    }
     fn clear(&mut self) {
         self.children.clear();
         self.parents.clear();
@@ -544,6 +848,9 @@ impl Workspace {
         self.adjbuf.clear();
         self.alive_folder.clear();
         self.folder_stack.clear();
+        if let Some(ref mut cache) = self.block_cache {
+            cache.clear()
+        }
     }
     fn assert_empty(&self) {
         assert!(self.children.is_empty());
@@ -560,6 +867,48 @@ impl Workspace {
     }
 }
 
+/// A pool of workspaces, to avoid allocating a new one for each
+/// application when applying changes concurrently (for example on
+/// different channels). At most `max` workspaces are kept.
+pub struct WorkspacePool {
+    pool: parking_lot::Mutex<Vec<Workspace>>,
+    max: usize,
+}
+
+impl WorkspacePool {
+    pub fn new(max: usize) -> Self {
+        WorkspacePool {
+            pool: parking_lot::Mutex::new(Vec::new()),
+            max,
+        }
+    }
+
+    /// Take a workspace from the pool, or allocate a new one if the
+    /// pool is empty.
+    pub fn acquire(&self) -> Workspace {
+        self.pool.lock().pop().unwrap_or_default()
+    }
+
+    /// Clear a workspace and return it to the pool, or drop it if the
+    /// pool is already full.
+    pub fn release(&self, mut ws: Workspace) {
+        ws.clear();
+        let mut pool = self.pool.lock();
+        if pool.len() < self.max {
+            pool.push(ws)
+        }
+    }
+
+    /// Number of workspaces currently available in the pool.
+    pub fn len(&self) -> usize {
+        self.pool.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.lock().is_empty()
+    }
+}
+
 struct StackElt {
     vertex: Vertex<ChangeId>,
     last_alive: Vertex<ChangeId>,
@@ -1200,3 +1549,55 @@ pub fn apply_root_change<R: rand::Rng, T: MutTxnT, P: ChangeStore>(
     let (n, merkle) = apply_change(store, txn, &mut channel.write(), &h)?;
     Ok(Some((h, n, merkle)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::record_all;
+    use crate::working_copy::WorkingCopy;
+    use crate::*;
+    use std::io::Write;
+
+    #[test]
+    fn workspace_pool() -> Result<(), anyhow::Error> {
+        env_logger::try_init().unwrap_or(());
+
+        let repo = working_copy::memory::Memory::new();
+        let changes = changestore::memory::Memory::new();
+        repo.add_file("file", b"a\nb\nc\n".to_vec());
+
+        let env = pristine::sanakirja::Pristine::new_anon()?;
+        let txn = env.arc_txn_begin().unwrap();
+        let channel = txn.write().open_or_create_channel("main").unwrap();
+        txn.write().add_file("file", 0)?;
+        let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+        repo.write_file("file", Inode::ROOT)
+            .unwrap()
+            .write_all(b"a\nx\nc\n")?;
+        let h1 = record_all(&repo, &changes, &txn, &channel, "")?;
+
+        let pool = WorkspacePool::new(1);
+        let env2 = pristine::sanakirja::Pristine::new_anon()?;
+        let mut txn2 = env2.mut_txn_begin().unwrap();
+        let channel2 = txn2.open_or_create_channel("main").unwrap();
+
+        let mut ws = pool.acquire();
+        txn2.apply_change_ws(&changes, &mut channel2.write(), &h0, &mut ws)?;
+        pool.release(ws);
+        assert_eq!(pool.len(), 1);
+
+        let mut ws = pool.acquire();
+        assert!(pool.is_empty());
+        ws.assert_empty();
+        txn2.apply_change_ws(&changes, &mut channel2.write(), &h1, &mut ws)?;
+        pool.release(ws);
+        // The pool is bounded.
+        pool.release(pool.acquire());
+        pool.release(Workspace::new());
+        assert_eq!(pool.len(), 1);
+
+        assert!(txn2.has_change(&channel2, &h0)?.is_some());
+        assert!(txn2.has_change(&channel2, &h1)?.is_some());
+        Ok(())
+    }
+}
@@ -64,7 +64,7 @@ where
         if target.end > n.to.end {
             assert!(!n.flag.contains(EdgeFlags::FOLDER));
             ws.missing_context.graphs.split(inode, target, n.to.end);
-            txn.split_block(graph, &target, n.to.end, &mut ws.adjbuf)?;
+            ws.split_block(txn, graph, &target, n.to.end)?;
             target.end = n.to.end
         }
 
@@ -96,8 +96,8 @@ where
         }
 
         source = target;
-        target = *txn
-            .find_block(graph, target.end_pos())
+        target = ws
+            .find_block(txn, graph, target.end_pos())
             .map_err(LocalApplyError::from)?;
         assert_ne!(source, target);
 
@@ -188,12 +188,13 @@ pub(crate) fn find_source_vertex<T: GraphMutTxnT + TreeTxnT>(
     ws: &mut super::Workspace,
 ) -> Result<Vertex<ChangeId>, LocalApplyError<T>> {
     debug!("find_source_vertex");
-    let mut source = *txn.find_block_end(&channel, internal_pos(txn, &from, change)?)?;
+    let from_pos = internal_pos(txn, &from, change)?;
+    let mut source = ws.find_block_end(txn, channel, from_pos)?;
     debug!("source = {:?}", source);
     if source.start < from.pos && source.end > from.pos {
         assert!(!flag.contains(EdgeFlags::FOLDER));
         ws.missing_context.graphs.split(inode, source, from.pos);
-        txn.split_block(channel, &source, from.pos, &mut ws.adjbuf)?;
+        ws.split_block(txn, channel, &source, from.pos)?;
         source.end = from.pos;
     }
     Ok(source)
@@ -210,12 +211,12 @@ pub(crate) fn find_target_vertex<T: GraphMutTxnT + TreeTxnT>(
 ) -> Result<Vertex<ChangeId>, LocalApplyError<T>> {
     let to_pos = internal_pos(txn, &to.start_pos(), change)?;
     debug!("find_target_vertex, to = {:?}", to);
-    let mut target = *txn.find_block(channel, to_pos)?;
+    let mut target = ws.find_block(txn, channel, to_pos)?;
     debug!("target = {:?}", target);
     if target.start < to.start {
         assert!(!flag.contains(EdgeFlags::FOLDER));
         ws.missing_context.graphs.split(inode, target, to.start);
-        txn.split_block(channel, &target, to.start, &mut ws.adjbuf)?;
+        ws.split_block(txn, channel, &target, to.start)?;
         target.start = to.start;
     }
     Ok(target)
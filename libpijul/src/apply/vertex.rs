@@ -82,7 +82,7 @@ fn put_up_context<T: GraphMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool>(
         Vertex::ROOT
     } else {
         debug!("put_up_context {:?}", up);
-        let k = *txn.find_block_end(graph, up)?;
+        let k = ws.find_block_end(txn, graph, up)?;
         assert_eq!(k.change, up.change);
         assert!(k.start <= up.pos);
         debug!("k = {:?}", k);
@@ -90,7 +90,7 @@ fn put_up_context<T: GraphMutTxnT + TreeTxnT, F: FnMut(&Hash) -> bool>(
             // The missing context "graphs" are only used at the
             // DELETION stage, check that:
             assert!(ws.missing_context.graphs.0.is_empty());
-            txn.split_block(graph, &k, up.pos, &mut ws.adjbuf)?
+            ws.split_block(txn, graph, &k, up.pos)?
         }
         Vertex {
             change: k.change,
@@ -132,14 +132,14 @@ fn put_down_context<T: GraphMutTxnT + TreeTxnT>(
     ws: &mut Workspace,
     down: Position<ChangeId>,
 ) -> Result<bool, LocalApplyError<T>> {
-    let k = *txn.find_block(&graph, down)?;
+    let k = ws.find_block(txn, graph, down)?;
     assert_eq!(k.change, down.change);
     assert!(k.end >= down.pos);
     if k.start < down.pos && k.end > down.pos {
         // The missing context "graphs" are only used at the
         // DELETION stage, check that:
         assert!(ws.missing_context.graphs.0.is_empty());
-        txn.split_block(graph, &k, down.pos, &mut ws.adjbuf)?
+        ws.split_block(txn, graph, &k, down.pos)?
     }
     let down_vertex = Vertex {
         change: k.change,
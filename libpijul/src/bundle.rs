@@ -0,0 +1,171 @@
+//! Bundles of changes, for moving changes between repositories
+//! without a server, for instance through a file or a pipe.
+//!
+//! A bundle is the concatenation of change files, each one prefixed
+//! by its length in bytes, as a big-endian `u64`.
+use crate::apply::{apply_change_ws, ApplyError, Workspace};
+use crate::change::{Change, ChangeError};
+use crate::changestore::{dependency_closure, topological_sort, ChangeStore};
+use crate::pristine::*;
+use crate::HashMap;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+pub enum BundleError<C: std::error::Error, T: GraphTxnT + TreeTxnT> {
+    Io(std::io::Error),
+    ChangeNotFound { hash: Hash },
+    Change(ChangeError),
+    Changestore(C),
+    Apply(ApplyError<C, T>),
+}
+
+impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Debug for BundleError<C, T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BundleError::Io(e) => std::fmt::Debug::fmt(e, fmt),
+            BundleError::ChangeNotFound { hash } => {
+                write!(fmt, "Change not found: {:?}", hash)
+            }
+            BundleError::Change(e) => std::fmt::Debug::fmt(e, fmt),
+            BundleError::Changestore(e) => std::fmt::Debug::fmt(e, fmt),
+            BundleError::Apply(e) => std::fmt::Debug::fmt(e, fmt),
+        }
+    }
+}
+
+impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Display for BundleError<C, T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BundleError::Io(e) => write!(fmt, "Bundle I/O error: {}", e),
+            BundleError::ChangeNotFound { hash } => {
+                write!(fmt, "Change not found: {}", hash.to_base32())
+            }
+            BundleError::Change(e) => std::fmt::Display::fmt(e, fmt),
+            BundleError::Changestore(e) => std::fmt::Display::fmt(e, fmt),
+            BundleError::Apply(e) => std::fmt::Display::fmt(e, fmt),
+        }
+    }
+}
+
+impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::error::Error for BundleError<C, T> {}
+
+impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> From<TxnErr<T::GraphError>>
+    for BundleError<C, T>
+{
+    fn from(err: TxnErr<T::GraphError>) -> Self {
+        BundleError::Apply(err.into())
+    }
+}
+
+/// Read the next change file of a bundle, or `None` at the end of
+/// the bundle.
+fn read_frame<R: Read>(r: &mut R) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let mut len = [0; 8];
+    let mut n = 0;
+    while n < len.len() {
+        match r.read(&mut len[n..]) {
+            Ok(0) if n == 0 => return Ok(None),
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(k) => n += k,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let len = (&len[..]).read_u64::<BigEndian>()?;
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Some(bytes))
+}
+
+/// Read a bundle from `r`, save its changes into `store`, and apply
+/// them to `channel`, each one after its dependencies. Changes of the
+/// bundle already on `channel` are saved but not applied again, and
+/// dependencies outside the bundle must already be on `channel`.
+///
+/// Returns the hashes of the changes applied, in the order in which
+/// they were applied.
+pub fn apply_bundle<R: Read, T: MutTxnT, P: ChangeStore>(
+    mut r: R,
+    store: &P,
+    txn: &mut T,
+    channel: &mut T::Channel,
+) -> Result<Vec<Hash>, BundleError<P::Error, T>> {
+    let mut dependencies = HashMap::default();
+    let mut hashes = Vec::new();
+    while let Some(bytes) = read_frame(&mut r).map_err(BundleError::Io)? {
+        let change = Change::deserialize_from(&bytes[..], None).map_err(BundleError::Change)?;
+        let hash = change
+            .hash()
+            .map_err(|e| BundleError::Change(ChangeError::from(e)))?;
+        debug!("apply_bundle: read {:?}", hash.to_base32());
+        store
+            .write_change_bytes(&hash, &bytes)
+            .map_err(BundleError::Changestore)?;
+        if dependencies
+            .insert(hash, change.hashed.dependencies)
+            .is_none()
+        {
+            hashes.push(hash)
+        }
+    }
+
+    // Only sort the bundle, the other dependencies are already on
+    // the channel if the bundle can be applied.
+    let order = topological_sort(&hashes, |h| {
+        Ok::<_, BundleError<P::Error, T>>(
+            dependencies
+                .get(h)
+                .into_iter()
+                .flatten()
+                .filter(|d| dependencies.contains_key(*d))
+                .cloned()
+                .collect(),
+        )
+    })?;
+
+    let mut ws = Workspace::new();
+    let mut applied = Vec::new();
+    for hash in order {
+        if let Some(int) = txn.get_internal(&hash.into())? {
+            if txn.get_changeset(txn.changes(channel), int)?.is_some() {
+                continue;
+            }
+        }
+        apply_change_ws(store, txn, channel, &hash, &mut ws).map_err(BundleError::Apply)?;
+        applied.push(hash)
+    }
+    Ok(applied)
+}
+
+/// Write the changes in `hashes`, along with all their dependencies,
+/// as a bundle to `w`, each change after its dependencies. The
+/// changes must be known to `txn`.
+///
+/// Returns the number of changes written.
+pub fn export_bundle<W: Write, T: TxnT, P: ChangeStore>(
+    mut w: W,
+    store: &P,
+    txn: &T,
+    hashes: &[Hash],
+) -> Result<usize, BundleError<P::Error, T>> {
+    for hash in hashes {
+        if txn.get_internal(&hash.into())?.is_none() {
+            return Err(BundleError::ChangeNotFound { hash: *hash });
+        }
+    }
+    let closure = dependency_closure(store, hashes).map_err(BundleError::Changestore)?;
+    for hash in closure.iter() {
+        debug!("export_bundle: writing {:?}", hash.to_base32());
+        let bytes = store
+            .read_change_bytes(hash)
+            .map_err(BundleError::Changestore)?;
+        w.write_u64::<BigEndian>(bytes.len() as u64)
+            .map_err(BundleError::Io)?;
+        w.write_all(&bytes).map_err(BundleError::Io)?;
+    }
+    w.flush().map_err(BundleError::Io)?;
+    Ok(closure.len())
+}
@@ -137,6 +137,25 @@ pub struct ChangeHeader_<Author> {
 /// (but not the actual contents of a change).
 pub type ChangeHeader = ChangeHeader_<Author>;
 
+impl<A> ChangeHeader_<A> {
+    /// Append `text` to the description, as a new paragraph. Blank
+    /// text is ignored.
+    pub fn append_description(&mut self, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        match self.description {
+            Some(ref mut d) if !d.trim().is_empty() => {
+                d.truncate(d.trim_end().len());
+                d.push_str("\n\n");
+                d.push_str(text)
+            }
+            _ => self.description = Some(text.to_string()),
+        }
+    }
+}
+
 impl Default for ChangeHeader {
     fn default() -> Self {
         ChangeHeader {
@@ -284,6 +303,97 @@ pub fn full_dependencies<T: ChannelTxnT + DepsTxnT<DepsError = <T as GraphTxnT>:
     Ok((deps, Vec::new()))
 }
 
+/// Remove the atoms of `change` that have no effect: edge maps
+/// without any edge, and empty vertices (`start == end`) that no
+/// other atom of the change uses as context. Hunks left without any
+/// atom are dropped, and replacements that lost one side become
+/// edits. Returns the number of atoms removed.
+///
+/// Name and inode vertices (file additions, moves, roots…) are empty
+/// by construction and are never pruned.
+pub fn prune_empty_atoms(change: &mut Change) -> usize {
+    let mut referenced = HashSet::new();
+    for atom in change.changes.iter().flat_map(|h| h.iter()) {
+        if let Atom::NewVertex(ref n) = atom {
+            for c in n.up_context.iter().chain(n.down_context.iter()) {
+                if c.change.is_none() {
+                    referenced.insert(c.pos);
+                }
+            }
+        }
+    }
+    let is_empty = |atom: &Atom<Option<Hash>>| match atom {
+        Atom::NewVertex(n) => {
+            n.start == n.end && !referenced.contains(&n.start) && !referenced.contains(&n.end)
+        }
+        Atom::EdgeMap(e) => e.edges.is_empty(),
+    };
+    let mut pruned = 0;
+    let hunks = std::mem::take(&mut change.hashed.changes);
+    for mut hunk in hunks {
+        let hunk = match hunk {
+            Hunk::Edit { change: ref c, .. }
+            | Hunk::SolveOrderConflict { change: ref c, .. }
+            | Hunk::UnsolveOrderConflict { change: ref c, .. }
+            | Hunk::ResurrectZombies { change: ref c, .. }
+                if is_empty(c) =>
+            {
+                pruned += 1;
+                continue;
+            }
+            Hunk::Replacement {
+                change: c,
+                replacement,
+                local,
+                encoding,
+            } => match (is_empty(&c), is_empty(&replacement)) {
+                (true, true) => {
+                    pruned += 2;
+                    continue;
+                }
+                (true, false) => {
+                    pruned += 1;
+                    Hunk::Edit {
+                        change: replacement,
+                        local,
+                        encoding,
+                    }
+                }
+                (false, true) => {
+                    pruned += 1;
+                    Hunk::Edit {
+                        change: c,
+                        local,
+                        encoding,
+                    }
+                }
+                (false, false) => Hunk::Replacement {
+                    change: c,
+                    replacement,
+                    local,
+                    encoding,
+                },
+            },
+            Hunk::FileAdd {
+                ref mut contents, ..
+            }
+            | Hunk::FileDel {
+                ref mut contents, ..
+            }
+            | Hunk::FileUndel {
+                ref mut contents, ..
+            } if matches!(contents, Some(c) if is_empty(c)) => {
+                pruned += 1;
+                *contents = None;
+                hunk
+            }
+            hunk => hunk,
+        };
+        change.hashed.changes.push(hunk)
+    }
+    pruned
+}
+
 fn add_zombie_deps_from<T: GraphTxnT>(
     txn: &T,
     channel: &T::Graph,
@@ -1322,6 +1432,8 @@ pub enum MakeChangeError<T: GraphTxnT> {
     Graph(#[from] TxnErr<<T as GraphTxnT>::GraphError>),
     #[error("Invalid change")]
     InvalidChange,
+    #[error("Dependency {} is not on the channel", .hash.to_base32())]
+    DependencyNotOnChannel { hash: Hash },
 }
 
 impl<T: GraphTxnT> std::fmt::Debug for MakeChangeError<T> {
@@ -1329,6 +1441,10 @@ impl<T: GraphTxnT> std::fmt::Debug for MakeChangeError<T> {
         match self {
             MakeChangeError::Graph(e) => std::fmt::Debug::fmt(e, fmt),
             MakeChangeError::InvalidChange => std::fmt::Debug::fmt("InvalidChange", fmt),
+            MakeChangeError::DependencyNotOnChannel { hash } => fmt
+                .debug_struct("DependencyNotOnChannel")
+                .field("hash", hash)
+                .finish(),
         }
     }
 }
@@ -1368,6 +1484,49 @@ impl LocalChange<Hunk<Option<Hash>, Local>, Author> {
         })
     }
 
+    /// Like [`make_change`](Self::make_change), but the change also
+    /// depends on `extra_dependencies`, on top of the dependencies
+    /// computed from its hunks. This is useful to reproduce a change
+    /// with a known set of dependencies, and implements the
+    /// `extra_dependencies` field of the repository configuration.
+    /// Each extra dependency must be on `channel`.
+    pub fn make_change_with_dependencies<
+        T: ChannelTxnT + DepsTxnT<DepsError = <T as GraphTxnT>::GraphError>,
+    >(
+        txn: &T,
+        channel: &ChannelRef<T>,
+        changes: Vec<Hunk<Option<Hash>, Local>>,
+        contents: Vec<u8>,
+        header: ChangeHeader,
+        metadata: Vec<u8>,
+        extra_dependencies: &[Hash],
+    ) -> Result<Self, MakeChangeError<T>> {
+        {
+            let channel = channel.read();
+            for hash in extra_dependencies {
+                let on_channel = if let Some(int) = txn.get_internal(&hash.into())? {
+                    txn.get_changeset(txn.changes(&channel), int)?.is_some()
+                } else {
+                    false
+                };
+                if !on_channel {
+                    return Err(MakeChangeError::DependencyNotOnChannel { hash: *hash });
+                }
+            }
+        }
+        let mut change = Self::make_change(txn, channel, changes, contents, header, metadata)?;
+        for hash in extra_dependencies {
+            if !change.hashed.dependencies.contains(hash) {
+                change.hashed.dependencies.push(*hash)
+            }
+        }
+        change
+            .hashed
+            .extra_known
+            .retain(|h| !extra_dependencies.contains(h));
+        Ok(change)
+    }
+
     pub fn new() -> Self {
         LocalChange {
             offsets: Offsets::default(),
@@ -1621,14 +1780,23 @@ impl Change {
     /// Deserialise a change from the file given as input `file`.
     #[cfg(feature = "zstd")]
     pub fn deserialize(file: &str, hash: Option<&Hash>) -> Result<Self, ChangeError> {
-        use std::io::Read;
-        let mut r = std::fs::File::open(file).map_err(|err| {
+        let r = std::fs::File::open(file).map_err(|err| {
             if let Some(h) = hash {
                 ChangeError::IoHash { err, hash: *h }
             } else {
                 ChangeError::Io(err)
             }
         })?;
+        Self::deserialize_from(r, hash)
+    }
+
+    /// Deserialise a change from reader `r`, for example the bytes
+    /// of a change file.
+    #[cfg(feature = "zstd")]
+    pub fn deserialize_from<R: std::io::Read>(
+        mut r: R,
+        hash: Option<&Hash>,
+    ) -> Result<Self, ChangeError> {
         let mut buf = vec![0u8; Self::OFFSETS_SIZE as usize];
         r.read_exact(&mut buf)?;
         let offsets: Offsets = bincode::deserialize(&buf)?;
@@ -1706,3 +1874,54 @@ impl Change {
         Ok(hasher.finish())
     }
 }
+
+#[derive(Debug, Error)]
+pub enum ChangeSignatureError {
+    #[error(transparent)]
+    Key(#[from] crate::key::KeyError),
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+}
+
+/// Sign the hash of `change` with `key`, and store the signature in
+/// the unhashed part of the change, under the `"signature"` key.
+pub fn sign_change(
+    change: &mut Change,
+    key: &crate::key::SKey,
+) -> Result<(), ChangeSignatureError> {
+    let hash = change.hash()?;
+    let signature = serde_json::Value::String(key.sign_raw(&hash.to_bytes())?);
+    match change.unhashed {
+        Some(serde_json::Value::Object(ref mut obj)) => {
+            obj.insert("signature".to_string(), signature);
+        }
+        _ => change.unhashed = Some(serde_json::json!({ "signature": signature })),
+    }
+    Ok(())
+}
+
+/// Check the signature stored in the unhashed part of `change`
+/// against `pubkey`. Returns `false` if the change isn't signed, or
+/// if the signature doesn't match.
+pub fn verify_change(
+    change: &Change,
+    pubkey: &crate::key::PublicKey,
+) -> Result<bool, ChangeSignatureError> {
+    let signature = if let Some(sig) = change
+        .unhashed
+        .as_ref()
+        .and_then(|u| u.get("signature"))
+        .and_then(|s| s.as_str())
+    {
+        sig
+    } else {
+        return Ok(false);
+    };
+    let hash = change.hash()?;
+    let pkey = pubkey.load()?;
+    match pkey.verify(&hash.to_bytes(), signature, &change.header.timestamp) {
+        Ok(()) => Ok(true),
+        Err(crate::key::KeyError::Dalek(_)) | Err(crate::key::KeyError::Encoding(_)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -133,12 +133,11 @@ impl<H, L> From<Hunk<H, L>> for super::Hunk<H, L> {
 impl Change {
     /// Deserialise a change from the file given as input `file`.
     #[cfg(feature = "zstd")]
-    pub(super) fn deserialize_noenc(
+    pub(super) fn deserialize_noenc<R: std::io::Read>(
         offsets: Offsets,
-        mut r: std::fs::File,
+        mut r: R,
         hash: Option<&Hash>,
     ) -> Result<Self, ChangeError> {
-        use std::io::Read;
         let mut buf = vec![0u8; (offsets.unhashed_off - Self::OFFSETS_SIZE) as usize];
         r.read_exact(&mut buf)?;
 
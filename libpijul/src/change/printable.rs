@@ -607,7 +607,7 @@ pub fn write_names<W: std::io::Write>(w: &mut W, names: &[String]) -> Result<(),
 pub fn get_encoding(contents: &[u8]) -> Option<Encoding> {
     let mut detector = chardetng::EncodingDetector::new();
     detector.feed(contents, true);
-    if let Some(e) = crate::get_valid_encoding(&detector, None, true, &contents) {
+    if let Some(e) = crate::get_valid_encoding(&detector, None, true, &contents, None) {
         Some(Encoding(e))
     } else {
         None
@@ -55,6 +55,118 @@ pub fn pop_filename(changes_dir: &mut PathBuf) {
     changes_dir.pop();
 }
 
+/// The ways change and tag files can be laid out in the changes
+/// directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutVersion {
+    /// All files directly in the changes directory, named after their
+    /// full hash.
+    Flat,
+    /// Files in subdirectories named after the first two characters
+    /// of their hash. This is the layout used by [`FileSystem`].
+    TwoCharPrefix,
+}
+
+impl LayoutVersion {
+    fn push(&self, changes_dir: &mut PathBuf, h32: &str, ext: &str) {
+        match self {
+            LayoutVersion::Flat => changes_dir.push(h32),
+            LayoutVersion::TwoCharPrefix => {
+                let (a, b) = h32.split_at(2);
+                changes_dir.push(a);
+                changes_dir.push(b);
+            }
+        }
+        changes_dir.set_extension(ext);
+    }
+
+    /// List the change and tag files laid out according to `self`,
+    /// as `(path, base32 hash, extension)`.
+    fn list(&self, changes_dir: &Path) -> Result<Vec<(PathBuf, String, String)>, Error> {
+        let mut dirs = Vec::new();
+        match self {
+            LayoutVersion::Flat => dirs.push((changes_dir.to_path_buf(), String::new())),
+            LayoutVersion::TwoCharPrefix => {
+                for dir in std::fs::read_dir(changes_dir)? {
+                    let dir = dir?;
+                    if !dir.file_type()?.is_dir() {
+                        continue;
+                    }
+                    if let Some(prefix) = dir.file_name().to_str() {
+                        if prefix.len() == 2 {
+                            dirs.push((dir.path(), prefix.to_string()))
+                        }
+                    }
+                }
+            }
+        }
+        let mut result = Vec::new();
+        for (dir, prefix) in dirs {
+            for file in std::fs::read_dir(&dir)? {
+                let file = file?;
+                if !file.file_type()?.is_file() {
+                    continue;
+                }
+                let path = file.path();
+                let ext = match path.extension().and_then(|e| e.to_str()) {
+                    Some(ext @ ("change" | "tag")) => ext.to_string(),
+                    _ => continue,
+                };
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    let h32 = format!("{}{}", prefix, stem);
+                    result.push((path, h32, ext))
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Move the change and tag files of the repository at `root` from
+/// layout `from` to layout `to`, checking that each file matches its
+/// hash before moving it. Files whose name isn't a hash are left
+/// untouched. Returns the number of files moved.
+pub fn migrate_changestore_layout(
+    root: &Path,
+    from: LayoutVersion,
+    to: LayoutVersion,
+) -> Result<usize, Error> {
+    let changes_dir = root.join(crate::DOT_DIR).join("changes");
+    if from == to {
+        return Ok(0);
+    }
+    let mut moved = 0;
+    for (path, h32, ext) in from.list(&changes_dir)? {
+        if ext == "change" {
+            let hash = if let Some(hash) = Hash::from_base32(h32.as_bytes()) {
+                hash
+            } else {
+                continue;
+            };
+            Change::check_from_buffer(&std::fs::read(&path)?, &hash)?;
+        } else {
+            let state = if let Some(state) = Merkle::from_base32(h32.as_bytes()) {
+                state
+            } else {
+                continue;
+            };
+            crate::tag::OpenTagFile::open(&path, &state)?;
+        }
+        let mut new_path = changes_dir.clone();
+        to.push(&mut new_path, &h32, &ext);
+        debug!("migrating {:?} to {:?}", path, new_path);
+        std::fs::create_dir_all(new_path.parent().unwrap())?;
+        std::fs::rename(&path, &new_path)?;
+        if from == LayoutVersion::TwoCharPrefix {
+            // Fails silently if there are still files with the same
+            // 2-letter prefix.
+            std::fs::remove_dir(path.parent().unwrap()).unwrap_or(());
+        }
+        moved += 1
+    }
+    Ok(moved)
+}
+
 impl FileSystem {
     pub fn filename(&self, hash: &Hash) -> PathBuf {
         let mut path = self.changes_dir.clone();
@@ -260,4 +372,38 @@ impl ChangeStore for FileSystem {
         debug!("file_name = {:?}", file_name);
         Ok(Change::deserialize(&file_name, Some(h))?)
     }
+    fn read_change_bytes(&self, hash: &Hash) -> Result<Vec<u8>, Self::Error> {
+        Ok(std::fs::read(&self.filename(hash))?)
+    }
+    fn write_change_bytes(&self, hash: &Hash, bytes: &[u8]) -> Result<(), Self::Error> {
+        Ok(self.save_from_buf(bytes, hash, None)?)
+    }
+    fn iter_changes(&self) -> Result<Vec<Hash>, Self::Error> {
+        let mut result = Vec::new();
+        for dir in std::fs::read_dir(&self.changes_dir)? {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+            let prefix = dir.file_name();
+            let prefix = if let Some(prefix) = prefix.to_str() {
+                prefix
+            } else {
+                continue;
+            };
+            for file in std::fs::read_dir(dir.path())? {
+                let path = file?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("change") {
+                    continue;
+                }
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    let h32 = format!("{}{}", prefix, stem);
+                    if let Some(h) = Hash::from_base32(h32.as_bytes()) {
+                        result.push(h)
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
 }
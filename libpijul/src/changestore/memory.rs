@@ -123,4 +123,7 @@ impl ChangeStore for Memory {
             Err(Error::ChangeNotFound { hash: *h })
         }
     }
+    fn iter_changes(&self) -> Result<Vec<Hash>, Self::Error> {
+        Ok(self.changes.read().unwrap().keys().cloned().collect())
+    }
 }
@@ -84,6 +84,43 @@ pub trait ChangeStore {
     ) -> Result<Hash, E>;
     fn del_change(&self, h: &Hash) -> Result<bool, Self::Error>;
     fn get_change(&self, h: &Hash) -> Result<Change, Self::Error>;
+    /// List the hashes of all the changes in this store, in no
+    /// particular order. Stores that can't list their changes return
+    /// an error of kind [`std::io::ErrorKind::Unsupported`].
+    fn iter_changes(&self) -> Result<Vec<Hash>, Self::Error> {
+        Err(ChangeError::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "This change store cannot list its changes",
+        ))
+        .into())
+    }
+    /// The serialized bytes of change `hash`, in the format of
+    /// change files, for example to send them over the network
+    /// without parsing them.
+    #[cfg(feature = "zstd")]
+    fn read_change_bytes(&self, hash: &Hash) -> Result<Vec<u8>, Self::Error> {
+        let mut change = self.get_change(hash)?;
+        let mut bytes = Vec::new();
+        change.serialize(&mut bytes, |_, _| Ok::<_, Self::Error>(()))?;
+        Ok(bytes)
+    }
+    /// Save change `hash` from its serialized bytes (as returned by
+    /// [`ChangeStore::read_change_bytes`]), after checking that they
+    /// hash to `hash`.
+    #[cfg(feature = "zstd")]
+    fn write_change_bytes(&self, hash: &Hash, bytes: &[u8]) -> Result<(), Self::Error> {
+        Change::check_from_buffer(bytes, hash)?;
+        let mut change = Change::deserialize_from(bytes, Some(hash))?;
+        let h = self.save_change(&mut change, |_, _| Ok::<_, Self::Error>(()))?;
+        if h != *hash {
+            return Err(ChangeError::ChangeHashMismatch {
+                claimed: *hash,
+                computed: h,
+            }
+            .into());
+        }
+        Ok(())
+    }
     fn get_file_meta<'a, F: Fn(ChangeId) -> Option<Hash>>(
         &self,
         hash: F,
@@ -95,6 +132,49 @@ pub trait ChangeStore {
     }
 }
 
+/// Compute the transitive closure of the dependencies of `roots`,
+/// including `roots` themselves. The result is topologically sorted:
+/// each change comes after all its dependencies, and the roots come
+/// last.
+pub fn dependency_closure<P: ChangeStore>(
+    changes: &P,
+    roots: &[Hash],
+) -> Result<Vec<Hash>, P::Error> {
+    topological_sort(roots, |h| changes.get_dependencies(h))
+}
+
+/// Same as [`dependency_closure`], with the dependencies of each
+/// change given by `dependencies`.
+pub(crate) fn topological_sort<E, F: FnMut(&Hash) -> Result<Vec<Hash>, E>>(
+    roots: &[Hash],
+    mut dependencies: F,
+) -> Result<Vec<Hash>, E> {
+    let mut result = Vec::new();
+    let mut visited = crate::HashSet::default();
+    // The boolean is `true` if the dependencies of the change have
+    // already been pushed onto the stack.
+    let mut stack: Vec<(Hash, bool)> = roots.iter().rev().map(|h| (*h, false)).collect();
+    while let Some((h, deps_pushed)) = stack.pop() {
+        if deps_pushed {
+            result.push(h);
+            continue;
+        }
+        if !visited.insert(h) {
+            continue;
+        }
+        stack.push((h, true));
+        for d in dependencies(&h)?.into_iter().rev() {
+            if let Hash::None = d {
+                continue;
+            }
+            if !visited.contains(&d) {
+                stack.push((d, false))
+            }
+        }
+    }
+    Ok(result)
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct FileMetadata<'a> {
     pub metadata: InodeMetadata,
@@ -142,3 +222,218 @@ impl crate::change::Atom<Option<Hash>> {
         h
     }
 }
+
+/// List the changes of `channel` (most recent first) having an
+/// author whose name, full name, email or public key contains
+/// `author_query`,
+/// stopping after `limit` matches if `limit` is not `None`.
+pub fn changes_by_author<T: crate::pristine::ChannelTxnT, P: ChangeStore>(
+    txn: &T,
+    channel: &T::Channel,
+    changes: &P,
+    author_query: &str,
+    limit: Option<usize>,
+) -> Result<Vec<Hash>, crate::output::FileError<P::Error, T>> {
+    use crate::pristine::GraphTxnT;
+    let mut result = Vec::new();
+    for x in crate::pristine::changeid_rev_log(txn, channel, None)? {
+        if let Some(limit) = limit {
+            if result.len() >= limit {
+                break;
+            }
+        }
+        let (_, p) = x?;
+        let h: Hash = if let Some(h) = txn.get_external(&p.a)? {
+            h.into()
+        } else {
+            return Err(crate::output::FileError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Change {:?} has no hash", p.a),
+            )));
+        };
+        let header = changes
+            .get_header(&h)
+            .map_err(crate::output::FileError::Changestore)?;
+        let matches = header.authors.iter().any(|a| {
+            ["name", "full_name", "email", "key"]
+                .iter()
+                .filter_map(|k| a.0.get(*k))
+                .any(|v| v.contains(author_query))
+        });
+        if matches {
+            result.push(h)
+        }
+    }
+    Ok(result)
+}
+
+/// Iterate over the changes of `channel`, most recent first, starting
+/// at position `from` (or at the end of the log if `from` is `None`),
+/// along with the state after each change and its header. Headers are
+/// only loaded from `changes` as the iterator advances.
+pub fn log_with_headers<'a, T: crate::pristine::ChannelTxnT, P: ChangeStore>(
+    txn: &'a T,
+    channel: &'a T::Channel,
+    changes: &'a P,
+    from: Option<u64>,
+) -> impl Iterator<
+    Item = Result<(Hash, crate::Merkle, ChangeHeader), crate::output::FileError<P::Error, T>>,
+> + 'a {
+    use crate::pristine::GraphTxnT;
+    let (log, err) = match crate::pristine::changeid_rev_log(txn, channel, from.map(|f| f.into())) {
+        Ok(log) => (Some(log), None),
+        Err(e) => (None, Some(Err(e.into()))),
+    };
+    err.into_iter().chain(log.into_iter().flatten().map(
+        move |x| -> Result<_, crate::output::FileError<P::Error, T>> {
+            let (_, p) = x?;
+            let h: Hash = txn.get_external(&p.a)?.unwrap().into();
+            let header = changes
+                .get_header(&h)
+                .map_err(crate::output::FileError::Changestore)?;
+            Ok((h, (&p.b).into(), header))
+        },
+    ))
+}
+
+#[derive(Debug, Error)]
+pub enum CopyError<S: std::error::Error + 'static, D: std::error::Error + 'static> {
+    #[error(transparent)]
+    Source(S),
+    #[error(transparent)]
+    Destination(D),
+    #[error("Change {:?} was saved with a different hash: {:?}", expected, got)]
+    HashMismatch { expected: Hash, got: Hash },
+}
+
+/// Copy change `hash` from `src` to `dst`, checking that its hash is
+/// preserved.
+pub fn copy_change<S: ChangeStore, D: ChangeStore>(
+    src: &S,
+    dst: &D,
+    hash: &Hash,
+) -> Result<(), CopyError<S::Error, D::Error>> {
+    let mut change = src.get_change(hash).map_err(CopyError::Source)?;
+    let got = dst
+        .save_change(&mut change, |_, _| Ok::<_, D::Error>(()))
+        .map_err(CopyError::Destination)?;
+    if got != *hash {
+        return Err(CopyError::HashMismatch {
+            expected: *hash,
+            got,
+        });
+    }
+    Ok(())
+}
+
+/// Copy all the changes of `src` to `dst` with [`copy_change`],
+/// returning the list of copied changes.
+pub fn copy_all<S: ChangeStore, D: ChangeStore>(
+    src: &S,
+    dst: &D,
+) -> Result<Vec<Hash>, CopyError<S::Error, D::Error>> {
+    let hashes = src.iter_changes().map_err(CopyError::Source)?;
+    for h in hashes.iter() {
+        copy_change(src, dst, h)?
+    }
+    Ok(hashes)
+}
+
+/// Check that the change stored under `hash` actually hashes to
+/// `hash`. A change whose bytes cannot be parsed (for example because
+/// its file is truncated) doesn't verify either, but errors reading
+/// these bytes from the store are returned.
+#[cfg(feature = "zstd")]
+pub fn verify_change_file<P: ChangeStore>(store: &P, hash: &Hash) -> Result<bool, P::Error> {
+    let bytes = store.read_change_bytes(hash)?;
+    if bytes.len() < Change::OFFSETS_SIZE as usize {
+        debug!("verify_change_file {:?}: truncated", hash);
+        return Ok(false);
+    }
+    if let Err(e) = Change::check_from_buffer(&bytes, hash) {
+        debug!("verify_change_file {:?}: {:?}", hash, e);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Verify all the changes of `store` with [`verify_change_file`],
+/// returning the list of changes that don't verify.
+#[cfg(feature = "zstd")]
+pub fn verify_all<P: ChangeStore>(store: &P) -> Result<Vec<Hash>, P::Error> {
+    let mut mismatched = Vec::new();
+    for h in store.iter_changes()? {
+        if !verify_change_file(store, &h)? {
+            mismatched.push(h)
+        }
+    }
+    Ok(mismatched)
+}
+
+/// Replace the header of change `old` with `new_header`, and save the
+/// result as a new change, returning it along with its hash. Since the
+/// hash changes, any signature of the old change is removed.
+///
+/// `old` is neither deleted from `store` nor replaced on any channel,
+/// this is left to the caller.
+pub fn amend_change<P: ChangeStore>(
+    store: &P,
+    old: &Hash,
+    new_header: ChangeHeader,
+) -> Result<(Change, Hash), P::Error> {
+    let mut change = store.get_change(old)?;
+    change.hashed.header = new_header;
+    if let Some(serde_json::Value::Object(ref mut unhashed)) = change.unhashed {
+        unhashed.remove("signature");
+    }
+    let hash = store.save_change(&mut change, |_, _| Ok::<_, P::Error>(()))?;
+    Ok((change, hash))
+}
+
+/// List the changes of `store` that aren't on `channel`, sorted by
+/// hash.
+pub fn unapplied_changes<T: crate::pristine::ChannelTxnT, P: ChangeStore>(
+    txn: &T,
+    channel: &T::Channel,
+    store: &P,
+) -> Result<Vec<Hash>, crate::output::FileError<P::Error, T>> {
+    use crate::pristine::{ChannelTxnT, GraphTxnT};
+    let mut result = Vec::new();
+    for h in store
+        .iter_changes()
+        .map_err(crate::output::FileError::Changestore)?
+    {
+        let applied = if let Some(id) = txn.get_internal(&h.into())? {
+            txn.get_changeset(txn.changes(channel), id)?.is_some()
+        } else {
+            false
+        };
+        if !applied {
+            result.push(h)
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// Count the changes of `channel` by (year, month) of their
+/// timestamp.
+pub fn change_activity<T: crate::pristine::ChannelTxnT, P: ChangeStore>(
+    txn: &T,
+    channel: &T::Channel,
+    changes: &P,
+) -> Result<std::collections::BTreeMap<(i32, u32), usize>, crate::output::FileError<P::Error, T>> {
+    use crate::pristine::{GraphTxnT, L64};
+    use chrono::Datelike;
+    let mut result = std::collections::BTreeMap::new();
+    for x in crate::pristine::changeid_log(txn, channel, L64(0))? {
+        let (_, p) = x?;
+        let h: Hash = txn.get_external(&p.a)?.unwrap().into();
+        let header = changes
+            .get_header(&h)
+            .map_err(crate::output::FileError::Changestore)?;
+        let date = header.timestamp;
+        *result.entry((date.year(), date.month())).or_insert(0) += 1
+    }
+    Ok(result)
+}
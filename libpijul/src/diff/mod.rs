@@ -158,9 +158,10 @@ impl Recorded {
         b: &[u8],
         encoding: &Option<Encoding>,
         separator: &regex::bytes::Regex,
+        style: crate::vertex_buffer::ConflictMarkerStyle,
     ) -> Result<(), DiffError<P::Error, T>> {
         self.largest_file = self.largest_file.max(b.len() as u64);
-        let mut d = vertex_buffer::Diff::new(inode, path.clone(), a);
+        let mut d = vertex_buffer::Diff::new(inode, path.clone(), a).with_style(style);
         output_graph(changes, txn, channel, &mut d, a, &mut self.redundant)?;
         let txn = txn.read();
         let channel = channel.read();
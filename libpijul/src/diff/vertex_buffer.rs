@@ -14,6 +14,7 @@ pub(super) struct Diff {
     conflict_stack: Vec<Conflict>,
     pub conflict_ends: Vec<ConflictEnds>,
     pub cyclic_conflict_bytes: Vec<(usize, usize)>,
+    pub style: vertex_buffer::ConflictMarkerStyle,
 }
 
 #[derive(Debug, Clone)]
@@ -79,8 +80,15 @@ impl Diff {
                 conflict_type: ConflictType::Root,
             }],
             cyclic_conflict_bytes: Vec::new(),
+            style: vertex_buffer::ConflictMarkerStyle::default(),
         }
     }
+
+    /// Expect the conflict markers of the working copy in `style`.
+    pub fn with_style(mut self, style: vertex_buffer::ConflictMarkerStyle) -> Self {
+        self.style = style;
+        self
+    }
 }
 
 impl Diff {
@@ -239,7 +247,7 @@ impl vertex_buffer::VertexBuffer for Diff {
         }
         let pos = self.contents_a.len();
         use std::io::Write;
-        write!(self.contents_a, "{} {}", marker, id)?;
+        write!(self.contents_a, "{} {}", self.style.marker(marker), id)?;
         match sides {
             Some((changes, sides)) => {
                 for side in sides {
@@ -0,0 +1,64 @@
+//! Plain-text renderings of pristine values for user interfaces.
+//!
+//! Nothing here depends on a terminal: callers that want colors can
+//! use [`StateStatus`] to choose them.
+use crate::pristine::*;
+
+/// Number of base32 characters in the short form of a state.
+pub const SHORT_STATE_LEN: usize = 10;
+
+/// Render `m` in base32, either in full or in its short form (a
+/// prefix of the full form).
+pub fn render_state(m: &Merkle, short: bool) -> String {
+    let mut s = m.to_base32();
+    if short {
+        s.truncate(SHORT_STATE_LEN)
+    }
+    s
+}
+
+/// How a state relates to a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateStatus {
+    /// The channel never was in that state.
+    Unknown,
+    /// The channel was in that state at some point.
+    Known,
+    /// The channel was in that state, and it is tagged.
+    Tagged,
+}
+
+/// Find whether `channel` was in state `m`, and if so, whether that
+/// state is tagged.
+pub fn state_status<T: ChannelTxnT>(
+    txn: &T,
+    channel: &T::Channel,
+    m: &Merkle,
+) -> Result<StateStatus, TxnErr<T::GraphError>> {
+    if let Some(n) = txn.channel_has_state(txn.states(channel), &m.into())? {
+        if txn.is_tagged(txn.tags(channel), n.into())? {
+            Ok(StateStatus::Tagged)
+        } else {
+            Ok(StateStatus::Known)
+        }
+    } else {
+        Ok(StateStatus::Unknown)
+    }
+}
+
+/// Same as [`render_state`], but annotates the result with the
+/// [`StateStatus`] of `m` in `channel`, for example
+/// `"MKTVTOM4ZR (tagged)"`. Known, untagged states are not annotated.
+pub fn render_channel_state<T: ChannelTxnT>(
+    txn: &T,
+    channel: &T::Channel,
+    m: &Merkle,
+    short: bool,
+) -> Result<String, TxnErr<T::GraphError>> {
+    let s = render_state(m, short);
+    Ok(match state_status(txn, channel, m)? {
+        StateStatus::Unknown => format!("{} (not in channel)", s),
+        StateStatus::Known => s,
+        StateStatus::Tagged => format!("{} (tagged)", s),
+    })
+}
@@ -1060,6 +1060,43 @@ pub fn find_path<T: ChannelTxnT, C: ChangeStore>(
     Ok(Some((path.join("/"), all_alive)))
 }
 
+/// The checksum of the file at `path`, as stored when recording
+/// with checksums, if it is still valid in `channel`, i.e. if no
+/// change touched the file since it was computed.
+pub fn file_checksum<
+    T: ChannelTxnT
+        + TreeTxnT<TreeError = <T as GraphTxnT>::GraphError>
+        + DepsTxnT<DepsError = <T as GraphTxnT>::GraphError>,
+>(
+    txn: &T,
+    channel: &T::Channel,
+    path: &str,
+) -> Result<Option<[u8; 32]>, TxnErr<T::GraphError>> {
+    let pos = if let (true, Some(pos)) = get_vertex(txn, path).map_err(|e| TxnErr(e.0))? {
+        pos
+    } else {
+        return Ok(None);
+    };
+    let checksum = if let Some(checksum) = txn.get_file_checksum(&pos)? {
+        *checksum
+    } else {
+        return Ok(None);
+    };
+    if txn.id(channel) != Some(&checksum.channel) {
+        return Ok(None);
+    }
+    let (timestamp, change) = get_latest_touch(txn, channel, &pos)?;
+    debug!(
+        "file_checksum {:?}: {:?} {:?} {:?}",
+        path, checksum, timestamp, change
+    );
+    if change == checksum.change && timestamp == u64::from(checksum.timestamp) {
+        Ok(Some(checksum.blake3))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn get_latest_touch<'a, T: ChannelTxnT + DepsTxnT<DepsError = <T as GraphTxnT>::GraphError>>(
     txn: &T,
     channel: &T::Channel,
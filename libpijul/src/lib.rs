@@ -16,9 +16,12 @@ extern crate quickcheck;
 
 pub mod alive;
 mod apply;
+#[cfg(feature = "zstd")]
+pub mod bundle;
 pub mod change;
 pub mod changestore;
 mod diff;
+pub mod display;
 pub mod fs;
 mod missing_context;
 pub mod output;
@@ -63,17 +66,19 @@ pub enum RemoteError {
 }
 
 pub use crate::apply::Workspace as ApplyWorkspace;
+pub use crate::apply::WorkspacePool as ApplyWorkspacePool;
 pub use crate::apply::{apply_change_arc, ApplyError, LocalApplyError};
 pub use crate::diff::DEFAULT_SEPARATOR;
 pub use crate::fs::{FsError, WorkingCopyIterator};
-pub use crate::output::{Archive, Conflict};
+pub use crate::output::{Archive, Conflict, ConflictKind, ConflictReport};
 pub use crate::pristine::{
     ArcTxn, Base32, ChangeId, ChannelMutTxnT, ChannelRef, ChannelTxnT, DepsTxnT, EdgeFlags,
     GraphTxnT, Hash, Inode, Merkle, MutTxnT, OwnedPathId, RemoteRef, TreeTxnT, TxnT, Vertex,
 };
 pub use crate::record::Builder as RecordBuilder;
 pub use crate::record::{Algorithm, InodeUpdate};
-pub use crate::unrecord::UnrecordError;
+pub use crate::text_encoding::Encoding;
+pub use crate::unrecord::{unapply_change, UnrecordError};
 
 // Making hashmaps deterministic (for testing)
 #[cfg(feature = "deterministic_hash")]
@@ -88,6 +93,18 @@ impl MutTxnTExt for pristine::sanakirja::MutTxn<()> {}
 impl TxnTExt for pristine::sanakirja::MutTxn<()> {}
 impl TxnTExt for pristine::sanakirja::Txn {}
 
+/// The number of threads to use by default, falling back to 1 if the
+/// available parallelism can't be detected.
+///
+/// ```
+/// assert!(libpijul::default_parallelism() >= 1);
+/// ```
+pub fn default_parallelism() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
 pub fn commit<T: pristine::MutTxnT>(
     txn: std::sync::Arc<std::sync::RwLock<T>>,
 ) -> Result<(), T::GraphError> {
@@ -713,12 +730,23 @@ pub fn get_timers() -> Timers {
     TIMERS.lock().unwrap().clone()
 }
 
+/// The encoding of `buffer`: `hint` if `buffer` is valid in that
+/// encoding, else the guess of `enc` if it is confident and valid.
 pub(crate) fn get_valid_encoding(
     enc: &chardetng::EncodingDetector,
     tld: Option<&[u8]>,
     allow_utf8: bool,
     buffer: &[u8],
+    hint: Option<&Encoding>,
 ) -> Option<&'static encoding_rs::Encoding> {
+    if let Some(hint) = hint {
+        if let (s, e, false) = hint.0.decode(buffer) {
+            if e.encode(&s).0 == buffer {
+                return Some(e);
+            }
+        }
+        debug!("{:?} is not valid for this file", hint.label());
+    }
     if let (encoding, true) = enc.guess_assess(tld, allow_utf8) {
         if let (s, e, false) = encoding.decode(buffer) {
             if encoding.encode(&s).0 == buffer {
@@ -309,3 +309,76 @@ pub(crate) fn archive<
     }
     Ok(conflicts)
 }
+
+/// An archive discarding everything written to it, used to collect
+/// conflicts without producing any file.
+struct NullArchive;
+
+impl Archive for NullArchive {
+    type File = std::io::Sink;
+    type Error = std::io::Error;
+    fn create_file(&mut self, _: &str, _: u64, _: u16) -> Self::File {
+        std::io::sink()
+    }
+    fn create_dir(&mut self, _: &str, _: u64, _: u16) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn close_file(&mut self, _: Self::File) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[derive(Error)]
+pub enum ConflictsAfterApplyError<P: std::error::Error + 'static, T: GraphTxnT + TreeTxnT> {
+    #[error(transparent)]
+    Txn(#[from] TxnErr<T::GraphError>),
+    #[error(transparent)]
+    Apply(#[from] crate::apply::ApplyError<P, T>),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError<P, T, std::io::Error>),
+}
+
+impl<P: std::error::Error + 'static, T: GraphTxnT + TreeTxnT> std::fmt::Debug
+    for ConflictsAfterApplyError<P, T>
+{
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConflictsAfterApplyError::Txn(e) => std::fmt::Debug::fmt(e, fmt),
+            ConflictsAfterApplyError::Apply(e) => std::fmt::Debug::fmt(e, fmt),
+            ConflictsAfterApplyError::Archive(e) => std::fmt::Debug::fmt(e, fmt),
+        }
+    }
+}
+
+/// Apply `hash` (and its missing dependencies) to `channel` in
+/// `txn`, and return all the conflicts `channel` has afterwards,
+/// including the ones it already had. This is a way to preview the
+/// conflicts a change would create.
+///
+/// This does modify the channel, but only in `txn`, which is taken by
+/// value and dropped without being committed: nothing is written to
+/// the pristine. `txn` should therefore be a fresh transaction,
+/// `channel` must have been loaded from it, and `channel` shouldn't be
+/// used afterwards.
+pub fn conflicts_after_apply<T: MutTxnT, P: ChangeStore>(
+    changes: &P,
+    txn: T,
+    channel: &ChannelRef<T>,
+    hash: &Hash,
+) -> Result<Vec<Conflict>, ConflictsAfterApplyError<P::Error, T>> {
+    let txn = ArcTxn::new(txn);
+    crate::apply::apply_change_rec(
+        changes,
+        &mut *txn.write(),
+        &mut *channel.write(),
+        hash,
+        false,
+    )?;
+    Ok(archive(
+        changes,
+        &txn,
+        channel,
+        &mut std::iter::empty(),
+        &mut NullArchive,
+    )?)
+}
@@ -16,6 +16,7 @@ pub enum OutputError<
 > {
     WorkingCopy(W),
     Pristine(#[from] PristineOutputError<ChangestoreError, T>),
+    NotEnoughSpace { needed: u64, available: u64 },
 }
 
 impl<C: std::error::Error, T: GraphTxnT + TreeTxnT, W: std::error::Error + Send> std::fmt::Debug
@@ -25,6 +26,11 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT, W: std::error::Error + Send>
         match self {
             OutputError::WorkingCopy(e) => std::fmt::Debug::fmt(e, fmt),
             OutputError::Pristine(e) => std::fmt::Debug::fmt(e, fmt),
+            OutputError::NotEnoughSpace { needed, available } => fmt
+                .debug_struct("NotEnoughSpace")
+                .field("needed", needed)
+                .field("available", available)
+                .finish(),
         }
     }
 }
@@ -36,6 +42,11 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT, W: std::error::Error + Send>
         match self {
             OutputError::WorkingCopy(e) => std::fmt::Display::fmt(e, fmt),
             OutputError::Pristine(e) => std::fmt::Display::fmt(e, fmt),
+            OutputError::NotEnoughSpace { needed, available } => write!(
+                fmt,
+                "Not enough space to output the working copy: {} bytes needed, {} bytes available",
+                needed, available
+            ),
         }
     }
 }
@@ -47,6 +58,7 @@ pub enum PristineOutputError<ChangestoreError: std::error::Error, T: GraphTxnT +
     Changestore(ChangestoreError),
     Io(#[from] std::io::Error),
     Fs(#[from] crate::fs::FsError<T>),
+    Block(#[from] BlockError<T::GraphError>),
 }
 
 impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Debug for PristineOutputError<C, T> {
@@ -57,6 +69,7 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Debug for Pristine
             PristineOutputError::Changestore(e) => std::fmt::Debug::fmt(e, fmt),
             PristineOutputError::Io(e) => std::fmt::Debug::fmt(e, fmt),
             PristineOutputError::Fs(e) => std::fmt::Debug::fmt(e, fmt),
+            PristineOutputError::Block(e) => std::fmt::Debug::fmt(e, fmt),
         }
     }
 }
@@ -71,6 +84,7 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Display
             PristineOutputError::Changestore(e) => std::fmt::Display::fmt(e, fmt),
             PristineOutputError::Io(e) => std::fmt::Display::fmt(e, fmt),
             PristineOutputError::Fs(e) => std::fmt::Display::fmt(e, fmt),
+            PristineOutputError::Block(e) => std::fmt::Display::fmt(e, fmt),
         }
     }
 }
@@ -91,6 +105,14 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT, W: std::error::Error + Send>
     }
 }
 
+impl<C: std::error::Error, T: GraphTxnT + TreeTxnT, W: std::error::Error + Send>
+    From<BlockError<T::GraphError>> for OutputError<C, T, W>
+{
+    fn from(e: BlockError<T::GraphError>) -> Self {
+        OutputError::Pristine(e.into())
+    }
+}
+
 #[derive(Error)]
 pub enum FileError<ChangestoreError: std::error::Error + std::fmt::Debug + 'static, T: GraphTxnT> {
     #[error(transparent)]
@@ -345,3 +367,304 @@ pub fn output_file<
     crate::alive::output_graph(changes, txn, channel, out, &mut graph, &mut forward)?;
     Ok(())
 }
+
+/// Compute and store the checksums of the files touched by change
+/// `hash`, as they are output from `channel` (including conflict
+/// markers). This is meant to be called right after applying a
+/// recorded change, and the checksums are then returned by
+/// [`crate::fs::file_checksum`] until another change touches the
+/// files. Returns the number of checksums stored.
+pub fn record_file_checksums<
+    T: ChannelTxnT + DepsMutTxnT<DepsError = <T as GraphTxnT>::GraphError>,
+    C: crate::changestore::ChangeStore,
+>(
+    changes: &C,
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    hash: &Hash,
+) -> Result<usize, FileError<C::Error, T>> {
+    let mut files = Vec::new();
+    {
+        let txn = txn.read();
+        let channel = channel.read();
+        let (id, channel_id) = match (txn.get_internal(&hash.into())?, txn.id(&*channel)) {
+            (Some(id), Some(channel_id)) => (*id, *channel_id),
+            _ => return Ok(0),
+        };
+        for x in txn.iter_rev_touched(&id)? {
+            let (id_, pos) = x?;
+            if *id_ > id {
+                break;
+            }
+            if !is_file(&*txn, txn.graph(&*channel), pos)? {
+                continue;
+            }
+            let (timestamp, change) = crate::fs::get_latest_touch(&*txn, &*channel, pos)?;
+            files.push((
+                *pos,
+                FileChecksum {
+                    change,
+                    timestamp: timestamp.into(),
+                    channel: channel_id,
+                    blake3: [0; 32],
+                },
+            ))
+        }
+    }
+    for (pos, checksum) in files.iter_mut() {
+        let mut hasher = blake3::Hasher::new();
+        output_file_streaming(
+            changes,
+            txn,
+            channel,
+            *pos,
+            "",
+            &mut hasher,
+            &mut Vec::new(),
+        )?;
+        checksum.blake3 = *hasher.finalize().as_bytes();
+    }
+    let mut txn = txn.write();
+    for (pos, checksum) in files.iter() {
+        txn.put_file_checksum(pos, Some(checksum))?;
+    }
+    Ok(files.len())
+}
+
+/// Whether `pos` is the inode vertex of an alive file (as opposed to
+/// a directory).
+fn is_file<T: GraphTxnT>(
+    txn: &T,
+    graph: &T::Graph,
+    pos: &Position<ChangeId>,
+) -> Result<bool, TxnErr<T::GraphError>> {
+    let v = pos.inode_vertex();
+    if v.is_root() || !is_alive(txn, graph, &v)? {
+        return Ok(false);
+    }
+    for e in iter_adjacent(
+        txn,
+        graph,
+        v,
+        EdgeFlags::empty(),
+        EdgeFlags::all() - EdgeFlags::DELETED - EdgeFlags::PARENT,
+    )? {
+        if e?.flag().contains(EdgeFlags::FOLDER) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Output the file at `v0` to `w`, with conflict markers, and push
+/// its conflicts to `conflicts`.
+///
+/// The graph of the file still needs to be retrieved entirely before
+/// anything is written, since conflicts are detected on the whole
+/// graph. However, lines are written to `w` (through a buffer of
+/// bounded size) as they are produced, so the contents of the file
+/// are never held in memory.
+pub fn output_file_streaming<
+    T: ChannelTxnT,
+    C: crate::changestore::ChangeStore,
+    W: std::io::Write,
+>(
+    changes: &C,
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    v0: Position<ChangeId>,
+    path: &str,
+    w: W,
+    conflicts: &mut Vec<Conflict>,
+) -> Result<(), FileError<C::Error, T>> {
+    let mut graph = {
+        let txn = txn.read();
+        let channel = channel.read();
+        crate::alive::retrieve(&*txn, txn.graph(&*channel), v0, false)?
+    };
+    let mut w =
+        crate::vertex_buffer::ConflictsWriter::new(std::io::BufWriter::new(w), path, v0, conflicts);
+    crate::alive::output_graph(changes, txn, channel, &mut w, &mut graph, &mut Vec::new())?;
+    use std::io::Write;
+    w.w.flush()?;
+    Ok(())
+}
+
+/// Output all the files of `channel` into memory, as a map from paths
+/// to contents. Contrarily to
+/// [`output_repository_no_pending`](fn.output_repository_no_pending.html),
+/// this never touches the channel (redundant edges are not deleted)
+/// nor the inodes and tree tables, and is meant for inspection.
+pub fn output_read_only<T: ChannelTxnT, C: crate::changestore::ChangeStore>(
+    changes: &C,
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+) -> Result<(std::collections::BTreeMap<String, Vec<u8>>, Vec<Conflict>), FileError<C::Error, T>> {
+    let mut files = std::collections::BTreeMap::new();
+    let mut conflicts = Vec::new();
+    let mut stack = vec![(Position::ROOT, String::new())];
+    while let Some((pos, path)) = stack.pop() {
+        let mut children = Vec::new();
+        {
+            let txn = txn.read();
+            let channel = channel.read();
+            for x in crate::fs::iter_graph_children(&*txn, changes, txn.graph(&*channel), pos)
+                .map_err(TxnErr)?
+            {
+                let (key, _, meta, name) = x.map_err(TxnErr)?;
+                let mut child_path = path.clone();
+                path::push(&mut child_path, &name);
+                children.push((key, meta, child_path))
+            }
+        }
+        for (key, meta, path) in children {
+            if meta.is_dir() {
+                stack.push((key, path));
+                continue;
+            }
+            let mut buf = Vec::new();
+            output_file_streaming(changes, txn, channel, key, &path, &mut buf, &mut conflicts)?;
+            files.insert(path, buf);
+        }
+    }
+    Ok((files, conflicts))
+}
+
+/// How a path differs between two channels, see [`diff_channels`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathDiffKind {
+    /// The path is only in the second channel.
+    Added,
+    /// The path is only in the first channel.
+    Removed,
+    /// The path is in both channels, with different contents.
+    Modified,
+}
+
+/// A file whose output differs between two channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathDiff {
+    pub path: String,
+    pub kind: PathDiffKind,
+}
+
+/// The files whose output differs between channels `a` and `b`,
+/// sorted by path. The files of both channels are output in memory
+/// with [`output_read_only`], so nothing is written to disk and
+/// neither channel is modified. Conflicts are compared as they would
+/// be output, markers included.
+pub fn diff_channels<T: ChannelTxnT, P: crate::changestore::ChangeStore>(
+    txn: &ArcTxn<T>,
+    a: &ChannelRef<T>,
+    b: &ChannelRef<T>,
+    changes: &P,
+) -> Result<Vec<PathDiff>, FileError<P::Error, T>> {
+    let (files_a, _) = output_read_only(changes, txn, a)?;
+    let (mut files_b, _) = output_read_only(changes, txn, b)?;
+    let mut diffs = Vec::new();
+    for (path, contents) in files_a {
+        let kind = match files_b.remove(&path) {
+            None => PathDiffKind::Removed,
+            Some(contents_b) if contents_b != contents => PathDiffKind::Modified,
+            Some(_) => continue,
+        };
+        diffs.push(PathDiff { path, kind })
+    }
+    diffs.extend(files_b.into_keys().map(|path| PathDiff {
+        path,
+        kind: PathDiffKind::Added,
+    }));
+    diffs.sort_by(|x, y| x.path.cmp(&y.path));
+    Ok(diffs)
+}
+
+/// The three versions of a conflicting file, in the form expected by
+/// external three-way merge tools.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeInputs {
+    /// The lines outside all conflicts.
+    pub base: Vec<u8>,
+    /// The base, plus the first side of each conflict.
+    pub ours: Vec<u8>,
+    /// The base, plus all the other sides of each conflict.
+    pub theirs: Vec<u8>,
+}
+
+#[derive(Error)]
+pub enum MergeInputsError<C: std::error::Error + 'static, T: GraphTxnT> {
+    #[error(transparent)]
+    File(#[from] FileError<C, T>),
+    #[error(transparent)]
+    Fs(#[from] crate::fs::FsErrorC<C, T>),
+}
+
+impl<C: std::error::Error + 'static, T: GraphTxnT> std::fmt::Debug for MergeInputsError<C, T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MergeInputsError::File(e) => std::fmt::Debug::fmt(e, fmt),
+            MergeInputsError::Fs(e) => std::fmt::Debug::fmt(e, fmt),
+        }
+    }
+}
+
+/// Compute the inputs of a three-way merge tool for the file at
+/// `path` in `channel`, or `None` if that file has no conflicts.
+///
+/// Pijul's conflicts don't have a common ancestor, so this is only a
+/// best-effort approximation: the base is made of the lines that all
+/// sides agree on, "ours" takes the first side of each conflict, and
+/// "theirs" takes all the other sides. Nested conflicts follow the
+/// side of their outermost conflict.
+pub fn merge_inputs<T: ChannelTxnT, P: crate::changestore::ChangeStore>(
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    changes: &P,
+    path: &str,
+) -> Result<Option<MergeInputs>, MergeInputsError<P::Error, T>> {
+    let (pos, _) = {
+        let txn = txn.read();
+        let channel = channel.read();
+        crate::fs::follow_oldest_path(changes, &*txn, &*channel, path)?
+    };
+    let mut buf = Vec::new();
+    let mut conflicts = Vec::new();
+    output_file_streaming(changes, txn, channel, pos, path, &mut buf, &mut conflicts)?;
+    if conflicts.is_empty() {
+        return Ok(None);
+    }
+
+    let mut inputs = MergeInputs::default();
+    // The id and current side of the outermost open conflict, and the
+    // depth of nesting inside it.
+    let mut outer: Option<(&[u8], usize)> = None;
+    let mut depth = 0;
+    for line in buf.split_inclusive(|&c| c == b'\n') {
+        match (crate::vertex_buffer::parse_conflict_marker(line), outer) {
+            (Some((crate::vertex_buffer::START_MARKER, id)), None) => {
+                outer = Some((id, 0));
+                depth = 1;
+            }
+            (Some((crate::vertex_buffer::START_MARKER, _)), Some(_)) => depth += 1,
+            (Some((crate::vertex_buffer::SEPARATOR, id)), Some((outer_id, side)))
+                if id == outer_id =>
+            {
+                outer = Some((outer_id, side + 1))
+            }
+            (Some((crate::vertex_buffer::END_MARKER, _)), Some(_)) => {
+                depth -= 1;
+                if depth == 0 {
+                    outer = None
+                }
+            }
+            (Some(_), _) => {}
+            (None, None) => {
+                inputs.base.extend_from_slice(line);
+                inputs.ours.extend_from_slice(line);
+                inputs.theirs.extend_from_slice(line);
+            }
+            (None, Some((_, 0))) => inputs.ours.extend_from_slice(line),
+            (None, Some(_)) => inputs.theirs.extend_from_slice(line),
+        }
+    }
+    Ok(Some(inputs))
+}
@@ -11,7 +11,7 @@ use crate::working_copy::WorkingCopy;
 use crate::{alive, path, vertex_buffer};
 use crate::{HashMap, HashSet};
 
-use std::collections::{hash_map::Entry, BTreeSet};
+use std::collections::{hash_map::Entry, BTreeMap, BTreeSet};
 use std::sync::Arc;
 
 /// A structure representing a file with conflicts.
@@ -84,6 +84,97 @@ impl Conflict {
             } => inode_vertex,
         }
     }
+
+    /// The path of the file this conflict is in, as recorded at
+    /// output time.
+    pub fn path(&self) -> &str {
+        match self {
+            Conflict::Name { ref path, .. }
+            | Conflict::ZombieFile { ref path, .. }
+            | Conflict::MultipleNames { ref path, .. }
+            | Conflict::Zombie { ref path, .. }
+            | Conflict::Cyclic { ref path, .. }
+            | Conflict::Order { ref path, .. } => path,
+        }
+    }
+
+    /// The line on which this conflict starts, for conflicts inside
+    /// a file.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Conflict::Zombie { line, .. }
+            | Conflict::Cyclic { line, .. }
+            | Conflict::Order { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> ConflictKind {
+        match self {
+            Conflict::Name { .. } => ConflictKind::Name,
+            Conflict::ZombieFile { .. } => ConflictKind::ZombieFile,
+            Conflict::MultipleNames { .. } => ConflictKind::MultipleNames,
+            Conflict::Zombie { .. } => ConflictKind::Zombie,
+            Conflict::Cyclic { .. } => ConflictKind::Cyclic,
+            Conflict::Order { .. } => ConflictKind::Order,
+        }
+    }
+
+    /// Resolve this conflict into a [`ConflictReport`]. The path is
+    /// looked up in the tree tables of `txn` (falling back to the
+    /// path recorded at output time if the inode isn't in the tree),
+    /// and the changes are rendered in base32. No channel is needed,
+    /// since the tree tables are shared by all channels.
+    pub fn to_report<T: TreeTxnT>(&self, txn: &T) -> Result<ConflictReport, TreeErr<T::TreeError>> {
+        let path = if let Some(inode) = self.inodes().first() {
+            tree_path(txn, inode)?.unwrap_or_else(|| self.path().to_string())
+        } else {
+            self.path().to_string()
+        };
+        Ok(ConflictReport {
+            kind: self.kind(),
+            path,
+            line: self.line(),
+            changes: self.changes().iter().map(|h| h.to_base32()).collect(),
+        })
+    }
+}
+
+/// Group `conflicts` by the path of the file they are in (see
+/// [`Conflict::path`]). Within each file, conflicts are in the order
+/// of `conflicts`.
+pub fn group_conflicts_by_path(conflicts: &BTreeSet<Conflict>) -> BTreeMap<String, Vec<&Conflict>> {
+    let mut groups: BTreeMap<String, Vec<&Conflict>> = BTreeMap::new();
+    for c in conflicts.iter() {
+        if let Some(g) = groups.get_mut(c.path()) {
+            g.push(c)
+        } else {
+            groups.insert(c.path().to_string(), vec![c]);
+        }
+    }
+    groups
+}
+
+/// The kind of a [`Conflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    Name,
+    ZombieFile,
+    MultipleNames,
+    Zombie,
+    Cyclic,
+    Order,
+}
+
+/// A machine-readable version of a [`Conflict`], with the path and
+/// changes resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictReport {
+    pub kind: ConflictKind,
+    pub path: String,
+    pub line: Option<usize>,
+    pub changes: Vec<String>,
 }
 
 /// Output updates the working copy after applying changes, including
@@ -110,6 +201,7 @@ where
     T::Channel: Send + Sync + 'static,
 {
     debug!("output_repository_no_pending: {:?}", prefix);
+    check_available_space(repo, txn, channel, prefix)?;
     let (c, f) = output_repository(
         repo,
         changes,
@@ -127,6 +219,132 @@ where
     Ok(c)
 }
 
+/// The result of [`output_prefixes`].
+#[derive(Debug, Default)]
+pub struct PrefixesOutput {
+    pub conflicts: BTreeSet<Conflict>,
+    /// The prefixes that were fully output.
+    pub complete: Vec<String>,
+    /// The prefixes that were not output, because they don't exist
+    /// in the channel.
+    pub not_found: Vec<String>,
+}
+
+impl PrefixesOutput {
+    /// Whether all the prefixes were fully output.
+    pub fn is_complete(&self) -> bool {
+        self.not_found.is_empty()
+    }
+}
+
+/// Same as [`output_repository_no_pending`], but outputs several
+/// prefixes, one after the other, and reports which ones were fully
+/// output. This is meant for incremental checkouts of parts of a
+/// large repository.
+pub fn output_prefixes<
+    T: ChannelMutTxnT + TreeMutTxnT<TreeError = T::GraphError> + Send + Sync + 'static,
+    R: WorkingCopy + Send + Clone + Sync + 'static,
+    P: ChangeStore + Send + Clone + 'static,
+>(
+    repo: &R,
+    changes: &P,
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    prefixes: &[&str],
+    output_name_conflicts: bool,
+    if_modified_since: Option<std::time::SystemTime>,
+    n_workers: usize,
+    salt: u64,
+) -> Result<PrefixesOutput, OutputError<P::Error, T, R::Error>>
+where
+    T::Channel: Send + Sync + 'static,
+{
+    let mut result = PrefixesOutput::default();
+    for prefix in prefixes {
+        let conflicts = output_repository_no_pending(
+            repo,
+            changes,
+            txn,
+            channel,
+            prefix,
+            output_name_conflicts,
+            if_modified_since,
+            n_workers,
+            salt,
+        )?;
+        result.conflicts.extend(conflicts);
+        // Outputting a prefix adds it to the tree, unless it
+        // couldn't be found in the channel.
+        if crate::fs::is_tracked(&*txn.read(), prefix)? {
+            result.complete.push(prefix.to_string())
+        } else {
+            result.not_found.push(prefix.to_string())
+        }
+    }
+    Ok(result)
+}
+
+/// When outputting a whole channel to an empty working copy (for
+/// example after a clone), check that the working copy has enough
+/// space for all the files, so that we don't leave a half-written
+/// working copy behind.
+fn check_available_space<
+    T: ChannelTxnT + TreeTxnT,
+    R: WorkingCopy,
+    C: std::error::Error + 'static,
+>(
+    repo: &R,
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    prefix: &str,
+) -> Result<(), OutputError<C, T, R::Error>> {
+    if !prefix.is_empty() {
+        return Ok(());
+    }
+    let available =
+        if let Some(available) = repo.available_space().map_err(OutputError::WorkingCopy)? {
+            available
+        } else {
+            return Ok(());
+        };
+    let txn = txn.read();
+    if crate::fs::iter_working_copy(&*txn, Inode::ROOT)
+        .next()
+        .is_some()
+    {
+        return Ok(());
+    }
+    let channel = channel.read();
+    let needed = output_size(&*txn, txn.graph(&*channel))?;
+    debug!("check_available_space: {:?} {:?}", needed, available);
+    if needed > available {
+        return Err(OutputError::NotEnoughSpace { needed, available });
+    }
+    Ok(())
+}
+
+/// Estimate the number of bytes needed to output a graph, by adding
+/// the sizes of all alive vertices that are not file names.
+fn output_size<T: GraphTxnT>(txn: &T, graph: &T::Graph) -> Result<u64, BlockError<T::GraphError>> {
+    let mut size = 0;
+    let mut visited = HashSet::default();
+    let mut stack = vec![(Vertex::ROOT, false)];
+    while let Some((v, is_content)) = stack.pop() {
+        if !visited.insert(v) {
+            continue;
+        }
+        if is_content {
+            size += (v.end - v.start) as u64;
+        }
+        for e in iter_alive_children(txn, graph, v)? {
+            let e = e?;
+            let child = *txn.find_block(graph, e.dest())?;
+            stack.push((child, !e.flag().contains(EdgeFlags::FOLDER)));
+        }
+    }
+    Ok(size)
+}
+
 /// Output updates the working copy after applying changes, including
 /// the graph-file correspondence.
 ///
@@ -295,7 +513,7 @@ where
         next_files.clear();
         state.next_prefix_basename = prefix.next();
         for (a, mut b) in files.drain() {
-            sort_conflicting_names(&txn, &channel, &mut b);
+            sort_conflicting_names(&txn, &channel, &mut b)?;
             state.output_name(repo, changes, &txn, &channel, &mut next_files, a, b)?;
         }
         std::mem::swap(&mut files, &mut next_files);
@@ -347,18 +565,24 @@ fn sort_conflicting_names<T: ChannelTxnT + Send + Sync + 'static>(
     txn: &ArcTxn<T>,
     channel: &ChannelRef<T>,
     b: &mut [(Vertex<ChangeId>, OutputItem)],
-) {
+) -> Result<(), TxnErr<T::GraphError>> {
     debug!("files: {:?}", b);
     let txn = txn.read();
     let channel = channel.read();
-    b.sort_unstable_by(|u, v| {
-        txn.get_changeset(txn.changes(&channel), &u.0.change)
-            .unwrap()
-            .cmp(
-                &txn.get_changeset(txn.changes(&channel), &v.0.change)
-                    .unwrap(),
-            )
-    });
+    let mut err = None;
+    b.sort_unstable_by(
+        |u, v| match change_order(&*txn, &channel, &u.0.change, &v.0.change) {
+            Ok(o) => o,
+            Err(e) => {
+                err = Some(e);
+                std::cmp::Ordering::Equal
+            }
+        },
+    );
+    if let Some(e) = err {
+        return Err(e);
+    }
+    Ok(())
 }
 
 struct OutputState<'a> {
@@ -769,11 +993,22 @@ fn output_item<T: ChannelTxnT + TreeTxnT, P: ChangeStore, W: WorkingCopy>(
         let channel = channel.read();
         retrieve(&*txn, txn.graph(&*channel), output_item.pos, false)?
     };
+    if output_item.meta.is_symlink() {
+        let mut f =
+            vertex_buffer::ConflictsWriter::new(Vec::new(), &path, output_item.pos, conflicts);
+        alive::output_graph(changes, &txn, &channel, &mut f, &mut l, forward)
+            .map_err(PristineOutputError::from)?;
+        let target = String::from_utf8_lossy(&f.w);
+        return repo
+            .write_symlink(&path, &target)
+            .map_err(OutputError::WorkingCopy);
+    }
     let w = repo
         .write_file(&path, inode)
         .map_err(OutputError::WorkingCopy)?;
     debug!("vertex_buffer");
-    let mut f = vertex_buffer::ConflictsWriter::new(w, &path, output_item.pos, conflicts);
+    let mut f = vertex_buffer::ConflictsWriter::new(w, &path, output_item.pos, conflicts)
+        .with_style(repo.conflict_marker_style());
     debug!("outputting graph");
     alive::output_graph(changes, &txn, &channel, &mut f, &mut l, forward)
         .map_err(PristineOutputError::from)?;
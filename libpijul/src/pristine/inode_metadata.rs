@@ -1,9 +1,11 @@
-/// Metadata about an inode, including unix-style permissions and
-/// whether this inode is a directory.
+/// Metadata about an inode, including unix-style permissions,
+/// whether this inode is a directory, and whether it is a symbolic
+/// link, in which case its contents are the target of the link.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 #[doc(hidden)]
 pub struct InodeMetadata(pub u16);
 const DIR_BIT: u16 = 0x200;
+const SYMLINK_BIT: u16 = 0x400;
 
 impl InodeMetadata {
     pub const DIR: Self = InodeMetadata(DIR_BIT);
@@ -31,6 +33,18 @@ impl InodeMetadata {
         self.0 & 0x1ff
     }
 
+    /// Whether `self` and `other` are equal, comparing only the
+    /// executable bit if one of them doesn't carry the full
+    /// permission bits (i.e. doesn't have the owner read bit), as
+    /// when recorded without the full mode.
+    pub fn same_as(&self, other: &InodeMetadata) -> bool {
+        if self.0 & other.0 & 0o400 != 0 {
+            self == other
+        } else {
+            self.0 & !0o677 == other.0 & !0o677
+        }
+    }
+
     /// Set the permissions to the supplied parameters.
     pub fn set_permissions(&mut self, perm: u16) {
         self.0 |= perm & 0x1ff
@@ -55,4 +69,15 @@ impl InodeMetadata {
     pub fn unset_dir(&mut self) {
         self.0 &= 0o777
     }
+
+    /// Tell whether this `InodeMetadata` is a symbolic link. Symbolic
+    /// links are also files, whose contents are their target.
+    pub fn is_symlink(&self) -> bool {
+        self.0 & SYMLINK_BIT != 0
+    }
+
+    /// Set the metadata to be a symbolic link.
+    pub fn set_symlink(&mut self) {
+        self.0 = (self.0 & 0o777) | SYMLINK_BIT
+    }
 }
@@ -222,6 +222,20 @@ impl std::fmt::Debug for RemoteId {
     }
 }
 
+/// The checksum of a file's contents, as output from a channel,
+/// stored for the file's inode vertex.
+///
+/// The checksum is only valid as long as `change` is the latest
+/// change touching the file in `channel`, applied at `timestamp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(C)]
+pub struct FileChecksum {
+    pub change: ChangeId,
+    pub timestamp: L64,
+    pub channel: RemoteId,
+    pub blake3: [u8; 32],
+}
+
 #[derive(Debug, Error)]
 pub enum HashPrefixError<T: std::error::Error + 'static> {
     #[error("Failed to parse hash prefix: {0}")]
@@ -300,6 +314,11 @@ pub trait ChannelTxnT: GraphTxnT {
     fn graph<'a>(&self, channel: &'a Self::Channel) -> &'a Self::Graph;
     fn apply_counter(&self, channel: &Self::Channel) -> u64;
     fn last_modified(&self, channel: &Self::Channel) -> u64;
+    /// The URL or path this channel was cloned from, if known. The
+    /// default implementation doesn't know any.
+    fn source_url<'a>(&self, _channel: &'a Self::Channel) -> Option<&'a str> {
+        None
+    }
     fn changes<'a>(&self, channel: &'a Self::Channel) -> &'a Self::Changeset;
     fn rev_changes<'a>(&self, channel: &'a Self::Channel) -> &'a Self::RevChangeset;
     fn tags<'a>(&self, channel: &'a Self::Channel) -> &'a Self::Tags;
@@ -517,6 +536,14 @@ pub trait DepsTxnT: Sized {
     table_get!(rev_touched_files, ChangeId, Position<ChangeId>, DepsError);
     iter!(touched_files, Position<ChangeId>, ChangeId, DepsError);
     iter!(rev_touched_files, ChangeId, Position<ChangeId>, DepsError);
+    /// The checksum stored for the file at inode vertex `file`, if
+    /// any. The default implementation stores no checksums.
+    fn get_file_checksum(
+        &self,
+        _file: &Position<ChangeId>,
+    ) -> Result<Option<&FileChecksum>, TxnErr<Self::DepsError>> {
+        Ok(None)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -576,6 +603,28 @@ pub trait TxnT:
     table!(channels);
     cursor!(channels, SmallStr, SerializedChannel);
 
+    table!(external);
+    cursor!(external, ChangeId, SerializedHash);
+    /// The hashes of all the changes known to this pristine, in no
+    /// particular order. The default implementation only finds the
+    /// changes that are on at least one channel.
+    fn external_hashes(&self) -> Result<Vec<Hash>, TxnErr<Self::GraphError>>
+    where
+        Self: Sized,
+    {
+        let mut hashes: HashSet<Hash> = HashSet::default();
+        for channel in self.channels("")? {
+            let channel = channel.read();
+            for x in changeid_log(self, &*channel, 0u64.into())? {
+                let (_, p) = x?;
+                if let Some(h) = self.get_external(&p.a)? {
+                    hashes.insert(h.into());
+                }
+            }
+        }
+        Ok(hashes.into_iter().collect())
+    }
+
     fn hash_from_prefix(
         &self,
         prefix: &str,
@@ -610,6 +659,13 @@ pub trait TxnT:
         start: &str,
     ) -> Result<Vec<ChannelRef<Self>>, TxnErr<Self::GraphError>>;
 
+    /// Iterate over the names of the channels, in alphabetical order
+    /// starting from `start`, without loading the channels.
+    fn channel_names<'txn>(
+        &'txn self,
+        start: &str,
+    ) -> Result<ChannelNamesIterator<'txn, Self>, TxnErr<Self::GraphError>>;
+
     fn iter_remotes<'txn>(
         &'txn self,
         start: &RemoteId,
@@ -714,6 +770,23 @@ pub(crate) fn iter_adjacent<'txn, T: GraphTxnT>(
     })
 }
 
+/// All the edges out of `v` (including parent edges, which are
+/// stored as edges with the `PARENT` flag), as `(flag, dest,
+/// introduced_by)` tuples, in the order they are stored. This is
+/// meant for debugging.
+pub fn dump_adjacency<T: GraphTxnT>(
+    txn: &T,
+    graph: &T::Graph,
+    v: Vertex<ChangeId>,
+) -> Result<Vec<(EdgeFlags, Position<ChangeId>, ChangeId)>, TxnErr<T::GraphError>> {
+    let mut edges = Vec::new();
+    for e in iter_adjacent(txn, graph, v, EdgeFlags::empty(), EdgeFlags::all())? {
+        let e = e?;
+        edges.push((e.flag(), e.dest(), e.introduced_by()))
+    }
+    Ok(edges)
+}
+
 pub(crate) fn iter_alive_children<'txn, T: GraphTxnT>(
     txn: &'txn T,
     graph: &'txn T::Graph,
@@ -855,6 +928,30 @@ pub fn changeid_log<'db, 'txn: 'db, T: ChannelTxnT>(
     T::cursor_revchangeset_ref(txn, txn.rev_changes(&channel), Some(from))
 }
 
+/// A total order on changes, used to display them consistently
+/// across runs: changes are ordered by the time they were applied to
+/// `channel`, with their hashes as a tiebreaker (for instance for
+/// changes that aren't on `channel`).
+pub fn change_order<T: ChannelTxnT>(
+    txn: &T,
+    channel: &T::Channel,
+    a: &ChangeId,
+    b: &ChangeId,
+) -> Result<std::cmp::Ordering, TxnErr<T::GraphError>> {
+    let ta = txn
+        .get_changeset(txn.changes(channel), a)?
+        .map(|x| u64::from_le(x.0));
+    let tb = txn
+        .get_changeset(txn.changes(channel), b)?
+        .map(|x| u64::from_le(x.0));
+    if ta != tb {
+        return Ok(ta.cmp(&tb));
+    }
+    let ha: Option<Hash> = txn.get_external(a)?.map(|x| x.into());
+    let hb: Option<Hash> = txn.get_external(b)?.map(|x| x.into());
+    Ok(ha.cmp(&hb).then(a.cmp(b)))
+}
+
 pub fn current_state<'db, 'txn: 'db, T: ChannelTxnT>(
     txn: &'txn T,
     channel: &'db T::Channel,
@@ -869,6 +966,66 @@ pub fn current_state<'db, 'txn: 'db, T: ChannelTxnT>(
     }
 }
 
+/// The last change of `channel`, with the state after applying it
+/// and its position in the log, or `None` if `channel` is empty.
+pub fn channel_tip<T: ChannelTxnT>(
+    txn: &T,
+    channel: &T::Channel,
+) -> Result<Option<(Hash, Merkle, u64)>, TxnErr<T::GraphError>> {
+    if let Some(e) = txn
+        .rev_cursor_revchangeset(txn.rev_changes(&channel), None)?
+        .next()
+    {
+        let (n, p) = e?;
+        let h: Hash = txn.get_external(&p.a)?.unwrap().into();
+        Ok(Some((h, (&p.b).into(), (*n).into())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The changes applied to `channel` up to and including the one
+/// that brought it to `state`, in the order of the log. Returns
+/// `None` if `channel` never was in `state`.
+pub fn changes_at_state<T: ChannelTxnT>(
+    txn: &T,
+    channel: &T::Channel,
+    state: &Merkle,
+) -> Result<Option<Vec<Hash>>, TxnErr<T::GraphError>> {
+    if *state == Merkle::zero() {
+        return Ok(Some(Vec::new()));
+    }
+    let t = if let Some(t) = txn.channel_has_state(txn.states(channel), &state.into())? {
+        t
+    } else {
+        return Ok(None);
+    };
+    let mut changes = Vec::new();
+    for x in changeid_log(txn, channel, L64(0))? {
+        let (n, p) = x?;
+        if *n > t {
+            break;
+        }
+        changes.push(txn.get_external(&p.a)?.unwrap().into())
+    }
+    Ok(Some(changes))
+}
+
+/// Same as [`channel_tip`], for a remote: returns the last change
+/// known to be on `remote`, the remote state after it and its
+/// position in the remote log.
+pub fn remote_tip<T: TxnT>(
+    txn: &T,
+    remote: &RemoteRef<T>,
+) -> Result<Option<(Hash, Merkle, u64)>, TxnErr<T::GraphError>> {
+    let remote = remote.lock();
+    if let Some((n, p)) = txn.last_remote(&remote.remote)? {
+        Ok(Some(((&p.a).into(), (&p.b).into(), n)))
+    } else {
+        Ok(None)
+    }
+}
+
 pub(crate) fn changeid_rev_log<'db, 'txn: 'db, T: ChannelTxnT>(
     txn: &'txn T,
     channel: &'db T::Channel,
@@ -1448,6 +1605,7 @@ initialized_cursor!(
     DepsTxnT,
     DepsError
 );
+initialized_cursor!(external, ChangeId, SerializedHash);
 initialized_cursor!(remote, L64, Pair<SerializedHash, SerializedMerkle>);
 initialized_rev_cursor!(remote, L64, Pair<SerializedHash, SerializedMerkle>);
 initialized_cursor!(
@@ -1644,6 +1802,24 @@ impl<'txn, T: TxnT> Iterator for ChannelIterator<'txn, T> {
     }
 }
 
+/// An iterator over the names of the channels, see
+/// [`TxnT::channel_names`].
+pub struct ChannelNamesIterator<'txn, T: TxnT> {
+    txn: &'txn T,
+    cursor: T::ChannelsCursor,
+}
+
+impl<'txn, T: TxnT> Iterator for ChannelNamesIterator<'txn, T> {
+    type Item = Result<SmallString, TxnErr<T::GraphError>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.txn.cursor_channels_next(&mut self.cursor) {
+            Ok(Some((name, _))) => Some(Ok(name.to_owned())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
 pub struct RemotesIterator<'txn, T: TxnT> {
     txn: &'txn T,
     cursor: T::RemotesCursor,
@@ -1696,6 +1872,10 @@ pub trait GraphMutTxnT: GraphTxnT {
 pub trait ChannelMutTxnT: ChannelTxnT + GraphMutTxnT {
     fn graph_mut(channel: &mut Self::Channel) -> &mut Self::Graph;
     fn touch_channel(&mut self, channel: &mut Self::Channel, t: Option<u64>);
+    /// Record where `channel` was cloned from. This is copied to the
+    /// channels forked from it. The default implementation doesn't
+    /// store anything, see [`ChannelTxnT::source_url`].
+    fn set_source_url(&mut self, _channel: &mut Self::Channel, _url: Option<&str>) {}
 
     /// Add a change and a timestamp to a change table. Returns `None` if and only if `(p, t)` was already in the change table, in which case no insertion happened. Returns the new state else.
     fn put_changes(
@@ -1706,6 +1886,23 @@ pub trait ChannelMutTxnT: ChannelTxnT + GraphMutTxnT {
         h: &Hash,
     ) -> Result<Option<Merkle>, TxnErr<Self::GraphError>>;
 
+    /// Same as calling [`Self::put_changes`] on each element of
+    /// `changes` in order, but looks the current state up only once,
+    /// which is faster when replaying a log onto a channel. The
+    /// timestamps must be increasing. Returns the result of each
+    /// insertion, in the same order as `changes`.
+    fn put_changes_batch(
+        &mut self,
+        channel: &mut Self::Channel,
+        changes: &[(ChangeId, ApplyTimestamp, Hash)],
+    ) -> Result<Vec<Option<Merkle>>, TxnErr<Self::GraphError>> {
+        let mut result = Vec::with_capacity(changes.len());
+        for (p, t, h) in changes {
+            result.push(self.put_changes(channel, *p, *t, h)?)
+        }
+        Ok(result)
+    }
+
     /// Delete a change from a change table. Returns `true` if and only if `(p, t)` was in the change table.
     fn del_changes(
         &mut self,
@@ -1735,6 +1932,16 @@ pub trait DepsMutTxnT: DepsTxnT {
     put_del!(revdep, ChangeId, ChangeId, DepsError);
     put_del!(touched_files, Position<ChangeId>, ChangeId, DepsError);
     put_del!(rev_touched_files, ChangeId, Position<ChangeId>, DepsError);
+    /// Replace the checksum stored for the file at inode vertex
+    /// `file`, or delete it if `checksum` is `None`. Checksums are
+    /// only a cache, and the default implementation ignores them.
+    fn put_file_checksum(
+        &mut self,
+        _file: &Position<ChangeId>,
+        _checksum: Option<&FileChecksum>,
+    ) -> Result<(), TxnErr<Self::DepsError>> {
+        Ok(())
+    }
 }
 
 pub trait TreeMutTxnT: TreeTxnT {
@@ -1787,8 +1994,23 @@ pub trait MutTxnT:
         name: &str,
     ) -> Result<(), ForkError<Self::GraphError>>;
 
+    /// Delete channel `name`, returning `false` if it doesn't
+    /// exist. Fails if `name` is the current channel, see
+    /// [`MutTxnT::force_drop_channel`].
     fn drop_channel(&mut self, name: &str) -> Result<bool, Self::GraphError>;
 
+    /// Same as [`MutTxnT::drop_channel`], but if `name` is the
+    /// current channel, reset the current channel to
+    /// [`DEFAULT_CHANNEL`](crate::DEFAULT_CHANNEL) first. This still
+    /// fails when dropping the default channel while it is current.
+    fn force_drop_channel(&mut self, name: &str) -> Result<bool, Self::GraphError> {
+        let is_current = self.current_channel()? == name;
+        if is_current {
+            self.set_current_channel(crate::DEFAULT_CHANNEL)?
+        }
+        self.drop_channel(name)
+    }
+
     /// Commit this transaction.
     fn commit(self) -> Result<(), Self::GraphError>;
 
@@ -2050,6 +2272,130 @@ pub fn last_common_state<T: ChannelTxnT>(
     }
 }
 
+/// Find the channel identifiers shared by more than one channel,
+/// along with the names of the channels sharing them. Channel
+/// identifiers are random, so this should never happen, except after
+/// manual surgery on the database.
+pub fn find_duplicate_channel_ids<T: TxnT>(
+    txn: &T,
+) -> Result<Vec<(RemoteId, Vec<SmallString>)>, TxnErr<T::GraphError>> {
+    let mut ids = std::collections::BTreeMap::new();
+    for channel in txn.channels("")? {
+        let channel = channel.read();
+        if let Some(id) = txn.id(&channel) {
+            ids.entry(*id)
+                .or_insert_with(Vec::new)
+                .push(SmallString::from_str(txn.name(&channel)))
+        }
+    }
+    Ok(ids
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(id, mut names)| {
+            names.sort();
+            (id, names)
+        })
+        .collect())
+}
+
+/// Find the dependencies `(a, b)` (meaning that `a` depends on `b`)
+/// recorded in only one of the `dep` and `revdep` tables, which should
+/// mirror each other. This can only happen after a crash or manual
+/// surgery on the database, see [`repair_dep_symmetry`].
+pub fn check_dep_symmetry<T: DepsTxnT>(
+    txn: &T,
+) -> Result<Vec<(ChangeId, ChangeId)>, TxnErr<T::DepsError>> {
+    let mut result = Vec::new();
+    for x in txn.iter_dep(&ChangeId::ROOT)? {
+        let (a, b) = x?;
+        if txn.get_revdep(b, Some(a))? != Some(a) {
+            result.push((*a, *b))
+        }
+    }
+    for x in txn.iter_revdep(&ChangeId::ROOT)? {
+        let (b, a) = x?;
+        if txn.get_dep(a, Some(b))? != Some(b) {
+            result.push((*a, *b))
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// Reinsert the missing halves of the dependencies reported by
+/// [`check_dep_symmetry`], returning how many were repaired.
+pub fn repair_dep_symmetry<T: DepsMutTxnT>(txn: &mut T) -> Result<usize, TxnErr<T::DepsError>> {
+    let broken = check_dep_symmetry(txn)?;
+    for (a, b) in broken.iter() {
+        txn.put_dep(a, b)?;
+        txn.put_revdep(b, a)?;
+    }
+    Ok(broken.len())
+}
+
+/// Find the channels whose current state starts with `state_prefix`
+/// (in base32), along with their full current state. The result is
+/// sorted by channel name.
+pub fn channel_at_state<T: TxnT>(
+    txn: &T,
+    state_prefix: &str,
+) -> Result<Vec<(SmallString, Merkle)>, TxnErr<T::GraphError>> {
+    let mut result = Vec::new();
+    for channel in txn.channels("")? {
+        let channel = channel.read();
+        let state = current_state(txn, &*channel)?;
+        if state.to_base32().starts_with(state_prefix) {
+            result.push((SmallString::from_str(txn.name(&channel)), state))
+        }
+    }
+    result.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(result)
+}
+
+/// The length of the shortest base32 prefix that identifies every
+/// change known to this pristine unambiguously, for use when
+/// abbreviating hashes. This is one more than the longest prefix
+/// shared by two hashes, capped at the length of a full hash, and
+/// grows as the repository does.
+pub fn min_unambiguous_prefix_len<T: TxnT>(txn: &T) -> Result<usize, TxnErr<T::GraphError>> {
+    let mut hashes: Vec<_> = txn
+        .external_hashes()?
+        .into_iter()
+        .map(|h| h.to_base32())
+        .collect();
+    hashes.sort_unstable();
+    let mut len = 1;
+    for w in hashes.windows(2) {
+        let common = w[0]
+            .bytes()
+            .zip(w[1].bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        len = len.max((common + 1).min(w[0].len().min(w[1].len())));
+    }
+    Ok(len)
+}
+
+/// List the tags of all channels, as `(channel name, position in
+/// the channel's log, state)`, sorted by position in the log.
+pub fn all_tags<T: TxnT>(
+    txn: &T,
+) -> Result<Vec<(SmallString, u64, Merkle)>, TxnErr<T::GraphError>> {
+    let mut result = Vec::new();
+    for channel in txn.channels("")? {
+        let channel = channel.read();
+        let name = SmallString::from_str(txn.name(&channel));
+        for t in txn.iter_tags(txn.tags(&channel), 0)? {
+            let (t, _) = t?;
+            if let Some(p) = txn.get_revchangeset(txn.rev_changes(&channel), t)? {
+                result.push((name.clone(), (*t).into(), (&p.b).into()))
+            }
+        }
+    }
+    result.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+    Ok(result)
+}
+
 /// Check that each inode in the inodes table maps to an alive vertex,
 /// and that each inode in the tree table is reachable by only one
 /// path.
@@ -29,8 +29,10 @@ pub enum SanakirjaError {
     Borrow(#[from] std::cell::BorrowError),
     #[error("Cannot dropped a borrowed channel: {:?}", c)]
     ChannelRc { c: String },
-    #[error("Pristine version mismatch. Cloning over the network can fix this.")]
-    Version,
+    #[error("Pristine version mismatch: found version {found}, expected {expected}. Cloning over the network can fix this.")]
+    Version { found: u64, expected: u64 },
+    #[error("Cannot drop the current channel: {:?}", name)]
+    CannotDropCurrentChannel { name: String },
 }
 
 impl std::convert::From<::sanakirja::CRCError> for SanakirjaError {
@@ -130,15 +132,121 @@ pub enum Root {
     RevTouchedFiles,
     Partials,
     Remotes,
+    // The following tables are optional: pristines written before
+    // they were introduced don't have them, and they are created on
+    // the first write, so adding them didn't change `VERSION`.
+    ChannelSources,
+    FileChecksums,
 }
 
 const VERSION: u64 = 1u64;
 
+/// Errors that can happen while upgrading a pristine, see
+/// [`Pristine::upgrade`].
+#[derive(Debug, Error)]
+pub enum UpgradeError {
+    #[error(transparent)]
+    Sanakirja(#[from] SanakirjaError),
+    #[error(
+        "No migration from pristine version {version}. Cloning over the network can fix this."
+    )]
+    NoMigration { version: u64 },
+    #[error("Pristine version {version} is newer than this version of Pijul")]
+    Newer { version: u64 },
+    #[error("Cannot downgrade the pristine from version {from} to version {to}")]
+    Downgrade { from: u64, to: u64 },
+    #[error("Expected pristine version {expected}, found version {found}")]
+    VersionMismatch { expected: u64, found: u64 },
+}
+
+impl std::convert::From<::sanakirja::Error> for UpgradeError {
+    fn from(e: ::sanakirja::Error) -> Self {
+        UpgradeError::Sanakirja(e.into())
+    }
+}
+
+/// Migrate the pristine from version `from` of the format to the
+/// next one, returning the new version, or fail if there is no
+/// migration from `from`.
+///
+/// New versions of the format add a case here, converting the tables
+/// of the previous version in `txn`.
+fn migrate_step(
+    _txn: &mut ::sanakirja::MutTxn<Arc<::sanakirja::Env>, ()>,
+    from: u64,
+) -> Result<u64, UpgradeError> {
+    Err(UpgradeError::NoMigration { version: from })
+}
+
 impl Pristine {
+    /// The version of the format stored in this pristine, without
+    /// checking that it matches the version of this library, and
+    /// without opening any table. This is 0 if the pristine was never
+    /// initialised.
+    pub fn on_disk_version(&self) -> Result<u64, SanakirjaError> {
+        let txn = ::sanakirja::Env::txn_begin(self.env.clone())?;
+        Ok(txn.root(Root::Version as usize))
+    }
+
+    /// Same as [`Pristine::on_disk_version`].
+    pub fn version(&self) -> Result<u64, SanakirjaError> {
+        self.on_disk_version()
+    }
+
+    /// Upgrade this pristine in place from version `from` (usually
+    /// obtained from [`Pristine::on_disk_version`]) to the current version,
+    /// running the migrations one version at a time. This is a no-op
+    /// if the pristine is already up to date.
+    pub fn upgrade(&self, from: u64) -> Result<(), UpgradeError> {
+        self.migrate(from, VERSION)
+    }
+
+    /// Migrate this pristine in place from version `from` to version
+    /// `to` of the format, one version at a time. Only upgrades are
+    /// supported, and `to` can't be newer than the current version.
+    /// `from` must be the version stored in the pristine.
+    pub fn migrate(&self, from: u64, to: u64) -> Result<(), UpgradeError> {
+        if from > VERSION {
+            return Err(UpgradeError::Newer { version: from });
+        }
+        if to > VERSION {
+            return Err(UpgradeError::Newer { version: to });
+        }
+        if to < from {
+            return Err(UpgradeError::Downgrade { from, to });
+        }
+        let mut txn = ::sanakirja::Env::mut_txn_begin(self.env.clone())?;
+        let found = txn.root(Root::Version as usize).unwrap_or(0);
+        if found != from {
+            return Err(UpgradeError::VersionMismatch {
+                expected: from,
+                found,
+            });
+        }
+        let mut version = from;
+        while version < to {
+            let next = migrate_step(&mut txn, version)?;
+            if next <= version {
+                return Err(UpgradeError::NoMigration { version });
+            }
+            debug!("upgraded pristine from {:?} to {:?}", version, next);
+            txn.set_root(Root::Version as usize, next);
+            version = next
+        }
+        if version != from {
+            txn.commit()?;
+        }
+        Ok(())
+    }
+
     pub fn txn_begin(&self) -> Result<Txn, SanakirjaError> {
         let txn = ::sanakirja::Env::txn_begin(self.env.clone())?;
-        if txn.root(Root::Version as usize) != VERSION {
-            return Err(SanakirjaError::Version);
+        let found = txn.root(Root::Version as usize);
+        if found != VERSION {
+            return Err(SanakirjaError::Version {
+                found,
+                expected: VERSION,
+            });
         }
         debug!("txn_begin");
         fn begin(txn: ::sanakirja::Txn<Arc<::sanakirja::Env>>) -> Option<Txn> {
@@ -156,6 +264,8 @@ impl Pristine {
                 partials: txn.root_db(Root::Partials as usize)?,
                 dep: txn.root_db(Root::Dep as usize)?,
                 remotes: txn.root_db(Root::Remotes as usize)?,
+                channel_sources: txn.root_db(Root::ChannelSources as usize),
+                file_checksums: txn.root_db(Root::FileChecksums as usize),
                 open_channels: Mutex::new(HashMap::default()),
                 open_remotes: Mutex::new(HashMap::default()),
                 txn,
@@ -180,7 +290,11 @@ impl Pristine {
             let mut txn = ::sanakirja::Env::mut_txn_begin(self.env.clone()).unwrap();
             if let Some(version) = txn.root(Root::Version as usize) {
                 if version != VERSION {
-                    return Err(SanakirjaError::Version.into());
+                    return Err(SanakirjaError::Version {
+                        found: version,
+                        expected: VERSION,
+                    }
+                    .into());
                 }
             } else {
                 txn.set_root(Root::Version as usize, VERSION);
@@ -251,6 +365,9 @@ impl Pristine {
                 } else {
                     btree::create_db_(&mut txn)?
                 },
+                // Created on the first write, see `Root`.
+                channel_sources: txn.root_db(Root::ChannelSources as usize),
+                file_checksums: txn.root_db(Root::FileChecksums as usize),
                 open_channels: Mutex::new(HashMap::default()),
                 open_remotes: Mutex::new(HashMap::default()),
                 txn,
@@ -295,6 +412,12 @@ pub struct GenericTxn<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::s
     partials: UDb<SmallStr, Position<ChangeId>>,
     channels: UDb<SmallStr, SerializedChannel>,
     remotes: UDb<RemoteId, SerializedRemote>,
+    /// Where channels were cloned from. This table is only created
+    /// when the first source is set.
+    channel_sources: Option<UDb<SmallStr, SmallStr>>,
+    /// Checksums of the files, computed when recording. Like
+    /// `channel_sources`, only created on the first write.
+    file_checksums: Option<Db<Position<ChangeId>, FileChecksum>>,
 
     pub(crate) open_channels: Mutex<HashMap<SmallString, ChannelRef<Self>>>,
     open_remotes: Mutex<HashMap<RemoteId, RemoteRef<Self>>>,
@@ -747,6 +870,7 @@ pub struct Channel {
     pub name: SmallString,
     pub last_modified: u64,
     pub id: RemoteId,
+    pub source_url: Option<SmallString>,
 }
 
 impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPage> ChannelTxnT
@@ -769,6 +893,9 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
     fn last_modified(&self, channel: &Self::Channel) -> u64 {
         channel.last_modified.into()
     }
+    fn source_url<'a>(&self, channel: &'a Self::Channel) -> Option<&'a str> {
+        channel.source_url.as_ref().map(|s| s.as_str())
+    }
     fn changes<'a>(&self, channel: &'a Self::Channel) -> &'a Self::Changeset {
         &channel.changes
     }
@@ -1126,6 +1253,21 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
     > {
         self.cursor_rev_touched_files(&self.rev_touched_files, Some((k, None)))
     }
+
+    fn get_file_checksum(
+        &self,
+        k: &Position<ChangeId>,
+    ) -> Result<Option<&FileChecksum>, TxnErr<Self::DepsError>> {
+        let db = if let Some(ref db) = self.file_checksums {
+            db
+        } else {
+            return Ok(None);
+        };
+        match btree::get(&self.txn, db, k, None)? {
+            Some((k_, v)) if k_ == k => Ok(Some(v)),
+            _ => Ok(None),
+        }
+    }
 }
 
 impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPage> TreeTxnT
@@ -1194,6 +1336,24 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
 }
 
 impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPage> GenericTxn<T> {
+    /// The current channel, if one was set, unlike
+    /// [`TxnT::current_channel`] which defaults to "main".
+    fn stored_current_channel(&self) -> Option<&str> {
+        if let Some(ref c) = self.cur_channel {
+            return Some(c);
+        }
+        unsafe {
+            let b = self.txn.root_page();
+            let len = b[4096 - 256] as usize;
+            if len == 0 {
+                None
+            } else {
+                let s = std::slice::from_raw_parts(b.as_ptr().add(4096 - 255), len);
+                std::str::from_utf8(s).ok()
+            }
+        }
+    }
+
     #[doc(hidden)]
     pub unsafe fn unsafe_load_channel(
         &self,
@@ -1212,6 +1372,7 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
                     apply_counter: tup.apply_counter.into(),
                     last_modified: tup.last_modified.into(),
                     id: tup.id,
+                    source_url: self.channel_source(&name)?,
                     name,
                 }))
             }
@@ -1221,6 +1382,35 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
             }
         }
     }
+
+    fn channel_source(
+        &self,
+        name: &SmallString,
+    ) -> Result<Option<SmallString>, TxnErr<SanakirjaError>> {
+        let db = if let Some(ref db) = self.channel_sources {
+            db
+        } else {
+            return Ok(None);
+        };
+        match btree::get(&self.txn, db, name, None)? {
+            Some((name_, url)) if name_ == name.as_ref() => Ok(Some(url.to_owned())),
+            _ => Ok(None),
+        }
+    }
+
+    /// The names of the channels and the ids of the remotes currently
+    /// held open by this transaction, sorted. Dropping one of these
+    /// fails with [`SanakirjaError::ChannelRc`] as long as another
+    /// reference to it is alive, so this is meant for finding such
+    /// leaks.
+    #[cfg(debug_assertions)]
+    pub fn open_handles(&self) -> (Vec<SmallString>, Vec<RemoteId>) {
+        let mut channels: Vec<_> = self.open_channels.lock().keys().cloned().collect();
+        channels.sort();
+        let mut remotes: Vec<_> = self.open_remotes.lock().keys().cloned().collect();
+        remotes.sort();
+        (channels, remotes)
+    }
 }
 
 impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPage> TxnT
@@ -1421,6 +1611,19 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
         UP<SmallStr, SerializedChannel>,
     >;
     sanakirja_cursor!(channels, SmallStr, SerializedChannel);
+
+    type External = UDb<ChangeId, SerializedHash>;
+    type ExternalCursor =
+        ::sanakirja::btree::cursor::Cursor<ChangeId, SerializedHash, UP<ChangeId, SerializedHash>>;
+    sanakirja_cursor!(external, ChangeId, SerializedHash);
+    fn external_hashes(&self) -> Result<Vec<Hash>, TxnErr<Self::GraphError>> {
+        let mut hashes = Vec::new();
+        for x in self.cursor_external(&self.external, None)? {
+            let (_, h) = x?;
+            hashes.push(h.into())
+        }
+        Ok(hashes)
+    }
     fn channels<'txn>(
         &'txn self,
         start: &str,
@@ -1439,6 +1642,16 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
             .collect())
     }
 
+    fn channel_names<'txn>(
+        &'txn self,
+        start: &str,
+    ) -> Result<ChannelNamesIterator<'txn, Self>, TxnErr<Self::GraphError>> {
+        let name = SmallString::from_str(start);
+        let mut cursor = btree::cursor::Cursor::new(&self.txn, &self.channels)?;
+        cursor.set(&self.txn, &name, None)?;
+        Ok(ChannelNamesIterator { cursor, txn: self })
+    }
+
     type Remotes = UDb<RemoteId, SerializedRemote>;
     type RemotesCursor = ::sanakirja::btree::cursor::Cursor<
         RemoteId,
@@ -1609,20 +1822,7 @@ impl<T: ::sanakirja::LoadPage<Error = ::sanakirja::Error> + ::sanakirja::RootPag
         }
     }
     fn current_channel(&self) -> Result<&str, Self::GraphError> {
-        if let Some(ref c) = self.cur_channel {
-            Ok(c)
-        } else {
-            unsafe {
-                let b = self.txn.root_page();
-                let len = b[4096 - 256] as usize;
-                if len == 0 {
-                    Ok("main")
-                } else {
-                    let s = std::slice::from_raw_parts(b.as_ptr().add(4096 - 255), len);
-                    Ok(std::str::from_utf8(s).unwrap_or("main"))
-                }
-            }
-        }
+        Ok(self.stored_current_channel().unwrap_or("main"))
     }
 }
 
@@ -1731,6 +1931,59 @@ impl GraphMutTxnT for MutTxn<()> {
     }
 }
 
+impl MutTxn<()> {
+    /// The timestamp and state of the last change applied to
+    /// `channel`, if any.
+    fn last_change(
+        &self,
+        channel: &Channel,
+    ) -> Result<(Option<u64>, Merkle), TxnErr<SanakirjaError>> {
+        if let Some(x) = btree::rev_iter(&self.txn, &channel.revchanges, None)?.next() {
+            let (a, b) = x?;
+            Ok((Some((*a).into()), (&b.b).into()))
+        } else {
+            Ok((None, Merkle::zero()))
+        }
+    }
+
+    /// Add change `p`, which isn't on `channel` yet, at time `t`
+    /// after the change applied at time `last`, with the resulting
+    /// state `m`.
+    fn insert_change(
+        &mut self,
+        channel: &mut Channel,
+        last: Option<u64>,
+        p: ChangeId,
+        t: ApplyTimestamp,
+        m: Merkle,
+    ) -> Result<(), TxnErr<SanakirjaError>> {
+        assert!(last.map(|last| last < t).unwrap_or(true));
+        channel.apply_counter += 1;
+        assert!(self
+            .get_revchangeset(&channel.revchanges, &t.into())?
+            .is_none());
+        assert!(btree::put(
+            &mut self.txn,
+            &mut channel.changes,
+            &p,
+            &t.into()
+        )?);
+        assert!(btree::put(
+            &mut self.txn,
+            &mut channel.revchanges,
+            &t.into(),
+            &Pair { a: p, b: m.into() }
+        )?);
+        assert!(btree::put(
+            &mut self.txn,
+            &mut channel.states,
+            &m.into(),
+            &t.into(),
+        )?);
+        Ok(())
+    }
+}
+
 impl ChannelMutTxnT for MutTxn<()> {
     fn graph_mut(c: &mut Self::Channel) -> &mut Self::Graph {
         c
@@ -1745,6 +1998,10 @@ impl ChannelMutTxnT for MutTxn<()> {
         }
     }
 
+    fn set_source_url(&mut self, channel: &mut Self::Channel, url: Option<&str>) {
+        channel.source_url = url.map(SmallString::from_str)
+    }
+
     fn put_changes(
         &mut self,
         channel: &mut Self::Channel,
@@ -1757,40 +2014,34 @@ impl ChannelMutTxnT for MutTxn<()> {
             debug!("found m = {:?}, p = {:?}", m, p);
             Ok(None)
         } else {
-            channel.apply_counter += 1;
             debug!("put_changes {:?} {:?}", t, p);
-            let m = if let Some(x) = btree::rev_iter(&self.txn, &channel.revchanges, None)?.next() {
-                let (a, b) = x?;
-                let a: u64 = (*a).into();
-                assert!(a < t);
-                (&b.b).into()
-            } else {
-                Merkle::zero()
-            };
+            let (last, m) = self.last_change(channel)?;
             let m = m.next(h);
-            assert!(self
-                .get_revchangeset(&channel.revchanges, &t.into())?
-                .is_none());
-            assert!(btree::put(
-                &mut self.txn,
-                &mut channel.changes,
-                &p,
-                &t.into()
-            )?);
-            assert!(btree::put(
-                &mut self.txn,
-                &mut channel.revchanges,
-                &t.into(),
-                &Pair { a: p, b: m.into() }
-            )?);
-            assert!(btree::put(
-                &mut self.txn,
-                &mut channel.states,
-                &m.into(),
-                &t.into(),
-            )?);
-            Ok(Some(m.into()))
+            self.insert_change(channel, last, p, t, m)?;
+            Ok(Some(m))
+        }
+    }
+
+    fn put_changes_batch(
+        &mut self,
+        channel: &mut Self::Channel,
+        changes: &[(ChangeId, ApplyTimestamp, Hash)],
+    ) -> Result<Vec<Option<Merkle>>, TxnErr<Self::GraphError>> {
+        debug!("put_changes_batch {:?}", changes.len());
+        let (mut last, mut m) = self.last_change(channel)?;
+        let mut result = Vec::with_capacity(changes.len());
+        for &(p, t, ref h) in changes {
+            if self.get_changeset(&channel.changes, &p)?.is_some() {
+                debug!("found p = {:?}", p);
+                result.push(None);
+                continue;
+            }
+            m = m.next(h);
+            self.insert_change(channel, last, p, t, m)?;
+            last = Some(t);
+            result.push(Some(m));
         }
+        Ok(result)
     }
 
     fn del_changes(
@@ -1909,6 +2160,25 @@ impl DepsMutTxnT for MutTxn<()> {
     sanakirja_put_del!(revdep, ChangeId, ChangeId, DepsError);
     sanakirja_put_del!(touched_files, Position<ChangeId>, ChangeId, DepsError);
     sanakirja_put_del!(rev_touched_files, ChangeId, Position<ChangeId>, DepsError);
+
+    fn put_file_checksum(
+        &mut self,
+        k: &Position<ChangeId>,
+        checksum: Option<&FileChecksum>,
+    ) -> Result<(), TxnErr<Self::DepsError>> {
+        if let Some(ref mut db) = self.file_checksums {
+            btree::del(&mut self.txn, db, k, None)?;
+        }
+        if let Some(checksum) = checksum {
+            if self.file_checksums.is_none() {
+                self.file_checksums = Some(btree::create_db_(&mut self.txn)?);
+            }
+            if let Some(ref mut db) = self.file_checksums {
+                btree::put(&mut self.txn, db, k, checksum)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl TreeMutTxnT for MutTxn<()> {
@@ -2005,6 +2275,7 @@ impl MutTxnT for MutTxn<()> {
                                 apply_counter: b.apply_counter.into(),
                                 last_modified: b.last_modified.into(),
                                 id: b.id,
+                                source_url: self.channel_source(&name).map_err(|e| e.0)?,
                                 name: name.clone(),
                             })),
                         },
@@ -2027,6 +2298,7 @@ impl MutTxnT for MutTxn<()> {
                                     },
                                     apply_counter: 0,
                                     last_modified: 0,
+                                    source_url: None,
                                     name: name.clone(),
                                 })),
                             };
@@ -2074,6 +2346,7 @@ impl MutTxnT for MutTxn<()> {
                         name: name.clone(),
                         apply_counter: channel.apply_counter,
                         last_modified: channel.last_modified,
+                        source_url: channel.source_url.clone(),
                         id: {
                             let mut rng = rand::thread_rng();
                             use rand::Rng;
@@ -2111,12 +2384,24 @@ impl MutTxnT for MutTxn<()> {
                     None,
                 )
                 .map_err(|e| ForkError::Txn(e.into()))?;
+                if let Some(ref mut db) = self.channel_sources {
+                    btree::del(&mut self.txn, db, &channel.r.read().name, None)
+                        .map_err(|e| ForkError::Txn(e.into()))?;
+                }
                 std::mem::drop(
                     self.open_channels
                         .lock()
                         .remove(&channel.r.read().name)
                         .unwrap(),
                 );
+                let is_current = self
+                    .current_channel()
+                    .map(|c| c == channel.r.read().name.as_str())
+                    .unwrap_or(false);
+                if is_current {
+                    // Keep the current channel pointer valid.
+                    self.cur_channel = Some(new_name.to_string());
+                }
                 channel.r.write().name = name.clone();
                 self.open_channels.lock().insert(name, channel.clone());
                 Ok(())
@@ -2127,6 +2412,11 @@ impl MutTxnT for MutTxn<()> {
     fn drop_channel(&mut self, name0: &str) -> Result<bool, Self::GraphError> {
         unsafe {
             debug!(target: "drop_channel", "drop channel {:?}", name0);
+            if self.stored_current_channel() == Some(name0) {
+                return Err(SanakirjaError::CannotDropCurrentChannel {
+                    name: name0.to_string(),
+                });
+            }
             let name = SmallString::from_str(name0);
             let channel = if let Some(channel) = self.open_channels.lock().remove(&name) {
                 let channel = Arc::try_unwrap(channel.r)
@@ -2158,6 +2448,9 @@ impl MutTxnT for MutTxn<()> {
                 None
             };
             btree::del(&mut self.txn, &mut self.channels, &name, None)?;
+            if let Some(ref mut db) = self.channel_sources {
+                btree::del(&mut self.txn, db, &name, None)?;
+            }
             if let Some((a, b, c, d, e)) = channel {
                 let mut unused_changes = Vec::new();
                 'outer: for x in btree::rev_iter(&self.txn, &c, None)? {
@@ -2349,6 +2642,14 @@ impl MutTxnT for MutTxn<()> {
         );
         self.txn
             .set_root(Root::Partials as usize, self.partials.db.into());
+        if let Some(ref db) = self.channel_sources {
+            self.txn
+                .set_root(Root::ChannelSources as usize, db.db.into());
+        }
+        if let Some(ref db) = self.file_checksums {
+            self.txn
+                .set_root(Root::FileChecksums as usize, db.db.into());
+        }
         self.txn.commit()?;
         Ok(())
     }
@@ -2375,6 +2676,7 @@ impl Txn {
                         apply_counter: c.apply_counter.into(),
                         last_modified: c.last_modified.into(),
                         id: c.id,
+                        source_url: self.channel_source(&name).map_err(|e| e.0)?,
                         name,
                     }))
                 }
@@ -2384,6 +2686,24 @@ impl Txn {
     }
 }
 
+impl MutTxn<()> {
+    /// Cancel this transaction. Nothing it did is written to the
+    /// pristine, and the channels and remotes it opened are
+    /// forgotten. Dropping a transaction without committing it has the
+    /// same effect, but this makes the intent explicit.
+    ///
+    /// The [`ChannelRef`]s and [`RemoteRef`]s obtained from this
+    /// transaction must not be used afterwards.
+    pub fn abort(self) {
+        debug!("abort");
+        self.open_channels.lock().clear();
+        self.open_remotes.lock().clear();
+        // Sanakirja discards uncommitted transactions when they are
+        // dropped, and the pages they allocated are freed.
+        std::mem::drop(self)
+    }
+}
+
 impl<T> MutTxn<T> {
     fn put_channel(&mut self, channel: ChannelRef<Self>) -> Result<(), SanakirjaError> {
         debug!("Commit_channel.");
@@ -2409,6 +2729,17 @@ impl<T> MutTxn<T> {
             id: channel.id,
         };
         btree::put(&mut self.txn, &mut self.channels, &channel.name, &sc)?;
+        if let Some(ref mut db) = self.channel_sources {
+            btree::del(&mut self.txn, db, &channel.name, None)?;
+        }
+        if let Some(ref url) = channel.source_url {
+            if self.channel_sources.is_none() {
+                self.channel_sources = Some(btree::create_db_(&mut self.txn)?);
+            }
+            if let Some(ref mut db) = self.channel_sources {
+                btree::put(&mut self.txn, db, &channel.name, url)?;
+            }
+        }
         debug!("Commit_channel, self.channels = {:?}", self.channels);
         Ok(())
     }
@@ -2510,6 +2841,8 @@ direct_repr!(SerializedMerkle);
 impl ::sanakirja::debug::Check for SerializedMerkle {}
 direct_repr!(SerializedHash);
 impl ::sanakirja::debug::Check for SerializedHash {}
+direct_repr!(FileChecksum);
+impl ::sanakirja::debug::Check for FileChecksum {}
 
 impl<A: ::sanakirja::debug::Check, B: ::sanakirja::debug::Check> ::sanakirja::debug::Check
     for Pair<A, B>
@@ -32,6 +32,10 @@ pub enum RecordError<C: std::error::Error + 'static, W: std::error::Error, T: Gr
     PathNotInRepo(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error(transparent)]
+    MakeChange(#[from] MakeChangeError<T>),
+    #[error(transparent)]
+    LocalApply(#[from] crate::apply::LocalApplyError<T>),
 }
 
 impl<C: std::error::Error, W: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Debug
@@ -47,6 +51,8 @@ impl<C: std::error::Error, W: std::error::Error, T: GraphTxnT + TreeTxnT> std::f
             RecordError::Diff(e) => std::fmt::Debug::fmt(e, fmt),
             RecordError::PathNotInRepo(p) => write!(fmt, "Path not in repository: {}", p),
             RecordError::Io(e) => std::fmt::Debug::fmt(e, fmt),
+            RecordError::MakeChange(e) => std::fmt::Debug::fmt(e, fmt),
+            RecordError::LocalApply(e) => std::fmt::Debug::fmt(e, fmt),
         }
     }
 }
@@ -1194,7 +1200,7 @@ impl Recorded {
             )?
         } else if former_parents.len() > 1
             || former_parents[0].basename != item.basename
-            || former_parents[0].metadata != item.metadata
+            || !former_parents[0].metadata.same_as(&item.metadata)
             || former_parents[0].parent != item.v_papa
             || is_deleted
         {
@@ -1244,6 +1250,7 @@ impl Recorded {
                 &b,
                 &encoding,
                 diff_sep,
+                working_copy.conflict_marker_style(),
             )?;
             if self.actions.len() > len {
                 if let Ok(last_modified) = working_copy.modified_time(&item.full_path) {
@@ -1405,6 +1412,95 @@ impl Recorded {
     }
 }
 
+/// Record exactly the paths in `paths` (and their subtrees, for
+/// directories), and make a change out of the result. Paths nested
+/// inside another path of the list are only recorded once.
+pub fn record_paths<T, C, W>(
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    working_copy: &W,
+    changes: &C,
+    paths: &[&str],
+    header: ChangeHeader,
+) -> Result<Change, RecordError<C::Error, W::Error, T>>
+where
+    T: ChannelMutTxnT + TreeTxnT + DepsTxnT<DepsError = <T as GraphTxnT>::GraphError>,
+    C: ChangeStore + Clone,
+    W: WorkingCopyRead + Clone,
+    W::Error: 'static,
+{
+    // Sorting by components puts each directory right before its
+    // contents, but the prefix check below doesn't depend on it.
+    let mut paths = paths.to_vec();
+    paths.sort_unstable_by(|a, b| crate::path::components(a).cmp(crate::path::components(b)));
+    paths.dedup();
+    let mut state = Builder::new();
+    let mut kept: Vec<&str> = Vec::new();
+    for p in paths {
+        let is_covered = kept.iter().any(|k| {
+            let mut p = crate::path::components(p);
+            crate::path::components(k).all(|c| p.next() == Some(c))
+        });
+        if is_covered {
+            continue;
+        }
+        state.record_single_thread(
+            txn.clone(),
+            Algorithm::default(),
+            false,
+            &crate::DEFAULT_SEPARATOR,
+            channel.clone(),
+            working_copy,
+            changes,
+            p,
+        )?;
+        kept.push(p);
+    }
+    let rec = state.finish();
+    Ok(rec.into_change(&*txn.read(), channel, header)?)
+}
+
+/// Record all the tracked paths of `working_copy` as a single change,
+/// save it to `changes` and apply it to `channel`, like `pijul record`
+/// without paths. Returns `None` if the working copy doesn't differ
+/// from `channel`.
+pub fn record_working_copy<T, C, W>(
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    working_copy: &W,
+    changes: &C,
+    header: ChangeHeader,
+) -> Result<Option<(Change, Hash)>, RecordError<C::Error, W::Error, T>>
+where
+    T: ChannelMutTxnT + TreeMutTxnT + DepsMutTxnT<DepsError = <T as GraphTxnT>::GraphError>,
+    C: ChangeStore + Clone,
+    W: WorkingCopyRead + Clone,
+    W::Error: 'static,
+{
+    let mut state = Builder::new();
+    state.record_single_thread(
+        txn.clone(),
+        Algorithm::default(),
+        false,
+        &crate::DEFAULT_SEPARATOR,
+        channel.clone(),
+        working_copy,
+        changes,
+        "",
+    )?;
+    let mut rec = state.finish();
+    if rec.actions.is_empty() {
+        return Ok(None);
+    }
+    let updatables = rec.take_updatables();
+    let mut change = rec.into_change(&*txn.read(), channel, header)?;
+    let hash = changes
+        .save_change(&mut change, |_, _| Ok::<_, C::Error>(()))
+        .map_err(RecordError::Changestore)?;
+    crate::apply::apply_local_change(&mut *txn.write(), channel, &change, &hash, &updatables)?;
+    Ok(Some((change, hash)))
+}
+
 fn collect_former_parents<C: ChangeStore, W: WorkingCopyRead, T: ChannelTxnT + TreeTxnT>(
     changes: &C,
     txn: &T,
@@ -1564,10 +1660,10 @@ where
             parent_dest, parent_meta, parent_name, name
         );
         let name_changed = parent_name != name;
-        let mut meta_changed = new_meta != parent_meta;
+        let mut meta_changed = !new_meta.same_as(&parent_meta);
         if cfg!(windows) && !meta_changed {
             if let Some(m) = last_alive_meta {
-                meta_changed = new_meta != m
+                meta_changed = !new_meta.same_as(&m)
             }
         }
         let mut name_is_alive = false;
@@ -676,6 +676,9 @@ impl<T> ChannelTxnT for WithTag<T> {
     fn last_modified(&self, c: &Self::Channel) -> u64 {
         self.tag.last_modified(c)
     }
+    fn source_url<'a>(&self, c: &'a Self::Channel) -> Option<&'a str> {
+        self.tag.source_url(c)
+    }
     fn changes<'a>(&self, channel: &'a Self::Channel) -> &'a Self::Changeset {
         self.tag.changes(channel)
     }
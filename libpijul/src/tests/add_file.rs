@@ -872,3 +872,381 @@ fn add_file2_test() -> Result<(), anyhow::Error> {
     assert_eq!(repo.list_files().len(), 8);
     Ok(())
 }
+
+/// Record only some of the files present in the working copy.
+#[test]
+fn record_paths_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("x", b"x\n".to_vec());
+    repo.add_file("y", b"y\n".to_vec());
+    repo.add_file("z", b"z\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("x", 0)?;
+    txn.write().add_file("y", 0)?;
+    txn.write().add_file("z", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    let change = crate::record::record_paths(
+        &txn,
+        &channel,
+        &repo,
+        &changes,
+        &["z", "x", "x"],
+        crate::change::ChangeHeader::default(),
+    )?;
+    let paths: std::collections::BTreeSet<_> = change
+        .changes
+        .iter()
+        .map(|h| h.path().to_string())
+        .collect();
+    assert_eq!(
+        paths.into_iter().collect::<Vec<_>>(),
+        vec!["x".to_string(), "z".to_string()]
+    );
+    Ok(())
+}
+
+/// Paths nested in another path of the list are recorded once, even
+/// when a sibling sorts between them (`.` sorts before `/`).
+#[test]
+fn record_paths_nested() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("a/b", b"b\n".to_vec());
+    repo.add_file("a.c", b"c\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("a/b", 0)?;
+    txn.write().add_file("a.c", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    let change = crate::record::record_paths(
+        &txn,
+        &channel,
+        &repo,
+        &changes,
+        &["a", "a.c", "a/b"],
+        crate::change::ChangeHeader::default(),
+    )?;
+    let mut paths: Vec<_> = change
+        .changes
+        .iter()
+        .map(|h| h.path().to_string())
+        .collect();
+    paths.sort();
+    let len = paths.len();
+    paths.dedup();
+    assert_eq!(paths.len(), len);
+    assert!(paths.iter().any(|p| p == "a/b"));
+    assert!(paths.iter().any(|p| p == "a.c"));
+    Ok(())
+}
+
+/// Record several edits and a move as a single change.
+#[test]
+fn record_working_copy_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("x", b"a\nb\n".to_vec());
+    repo.add_file("y", b"c\nd\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("x", 0)?;
+    txn.write().add_file("y", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    record_all(&repo, &changes, &txn, &channel, "")?;
+
+    repo.write_file("x", Inode::ROOT)?.write_all(b"a\nx\nb\n")?;
+    repo.write_file("y", Inode::ROOT)?.write_all(b"c\n")?;
+    repo.rename("y", "z")?;
+    txn.write().move_file("y", "z", 0)?;
+
+    let (change, hash) = crate::record::record_working_copy(
+        &txn,
+        &channel,
+        &repo,
+        &changes,
+        crate::change::ChangeHeader::default(),
+    )?
+    .unwrap();
+    let paths: std::collections::BTreeSet<_> = change
+        .changes
+        .iter()
+        .map(|h| h.path().to_string())
+        .collect();
+    // The edits of `x` and `y`, and the move of `y`, are all there.
+    assert_eq!(paths.len(), 2);
+    assert!(paths.contains("x"));
+    assert!(change.changes.len() >= 3);
+    assert!(txn.read().has_change(&channel, &hash)?.is_some());
+
+    // Nothing left to record.
+    assert!(crate::record::record_working_copy(
+        &txn,
+        &channel,
+        &repo,
+        &changes,
+        crate::change::ChangeHeader::default(),
+    )?
+    .is_none());
+    Ok(())
+}
+
+/// Output a channel without modifying it.
+#[test]
+fn output_read_only_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("dir/file", b"a\nb\nc\n".to_vec());
+    repo.add_file("other", b"d\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("dir/file", 0)?;
+    txn.write().add_file("other", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    record_all(&repo, &changes, &txn, &channel, "")?;
+    repo.write_file("dir/file", Inode::ROOT)
+        .unwrap()
+        .write_all(b"a\nx\nc\n")?;
+    record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let count_edges = || {
+        use crate::pristine::GraphIter;
+        let txn = txn.read();
+        let channel = channel.read();
+        txn.iter_graph(txn.graph(&*channel), None).unwrap().count()
+    };
+    let before = count_edges();
+    let (files, conflicts) = output::output_read_only(&changes, &txn, &channel)?;
+    assert_eq!(count_edges(), before);
+    assert!(conflicts.is_empty());
+    assert_eq!(files.keys().collect::<Vec<_>>(), vec!["dir/file", "other"]);
+    assert_eq!(files["dir/file"], b"a\nx\nc\n");
+    assert_eq!(files["other"], b"d\n");
+    Ok(())
+}
+
+/// Output a large file directly to a writer, and compare with the
+/// working copy output.
+#[test]
+fn output_file_streaming() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    let mut contents = Vec::new();
+    for i in 0..20_000 {
+        writeln!(contents, "line {}", i)?;
+    }
+    repo.add_file("file", contents.clone());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    record_all(&repo, &changes, &txn, &channel, "")?;
+    contents.clear();
+    for i in 0..20_000 {
+        if i % 7 == 0 {
+            writeln!(contents, "edited {}", i)?;
+        } else {
+            writeln!(contents, "line {}", i)?;
+        }
+    }
+    repo.write_file("file", Inode::ROOT)?.write_all(&contents)?;
+    record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let repo2 = working_copy::memory::Memory::new();
+    output::output_repository_no_pending(&repo2, &changes, &txn, &channel, "", true, None, 1, 0)?;
+    let mut expected = Vec::new();
+    repo2.read_file("file", &mut expected)?;
+    assert_eq!(expected, contents);
+
+    let key = {
+        let txn = txn.read();
+        let inode = crate::fs::find_inode(&*txn, "file")?;
+        *txn.get_inodes(&inode, None)?.unwrap()
+    };
+    let mut streamed = Vec::new();
+    let mut conflicts = Vec::new();
+    output::output_file_streaming(
+        &changes,
+        &txn,
+        &channel,
+        key,
+        "file",
+        &mut streamed,
+        &mut conflicts,
+    )?;
+    assert!(conflicts.is_empty());
+    assert_eq!(streamed, expected);
+    Ok(())
+}
+
+/// Predict the inodes touched by a change adding a file.
+#[test]
+fn touched_inodes_add_file() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    let h = record_all(&repo, &changes, &txn, &channel, "")?;
+    let other = txn.write().open_or_create_channel("other").unwrap();
+
+    let touched = {
+        let txn = txn.read();
+        assert!(apply::touched_inodes_for(&*txn, &*channel.read(), &changes, &h)?.is_empty());
+        apply::touched_inodes_for(&*txn, &*other.read(), &changes, &h)?
+    };
+    let file = {
+        let txn = txn.read();
+        let inode = crate::fs::find_inode(&*txn, "file")?;
+        *txn.get_inodes(&inode, None)?.unwrap()
+    };
+    assert!(touched.contains(&file));
+
+    apply::apply_change_arc(&changes, &txn, &other, &h)?;
+    let (files, _) = output::output_read_only(&changes, &txn, &other)?;
+    assert_eq!(files["file"], b"a\nb\n");
+    Ok(())
+}
+
+/// List the edges of the root vertex after adding a file.
+#[test]
+fn dump_root_adjacency() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    let h = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let txn = txn.read();
+    let id = *txn.get_internal(&(&h).into())?.unwrap();
+    let edges = pristine::dump_adjacency(&*txn, txn.graph(&*channel.read()), Vertex::ROOT)?;
+    assert_eq!(edges.len(), 1);
+    let (flag, dest, introduced_by) = edges[0];
+    assert_eq!(flag, EdgeFlags::FOLDER | EdgeFlags::BLOCK);
+    assert_eq!(dest.change, id);
+    assert_eq!(introduced_by, id);
+    Ok(())
+}
+
+/// Record a file with checksums, and compare the checksum with the
+/// working copy.
+#[test]
+fn file_checksum() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("dir/file", b"a\nb\nc\n".to_vec());
+    repo.add_file("dir/other", b"d\ne\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("dir/file", 0)?;
+    txn.write().add_file("dir/other", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    assert_eq!(
+        crate::fs::file_checksum(&*txn.read(), &*channel.read(), "dir/file")?,
+        None
+    );
+    // Only the two files get a checksum, not "dir".
+    assert_eq!(
+        crate::output::record_file_checksums(&changes, &txn, &channel, &h0)?,
+        2
+    );
+
+    let mut buf = Vec::new();
+    repo.read_file("dir/file", &mut buf)?;
+    assert_eq!(
+        crate::fs::file_checksum(&*txn.read(), &*channel.read(), "dir/file")?,
+        Some(*blake3::hash(&buf).as_bytes())
+    );
+
+    // Recording a change to the file without checksums invalidates
+    // its checksum, but not the other file's.
+    repo.write_file("dir/file", Inode::ROOT)?
+        .write_all(b"a\nx\nc\n")?;
+    record_all(&repo, &changes, &txn, &channel, "")?;
+    assert_eq!(
+        crate::fs::file_checksum(&*txn.read(), &*channel.read(), "dir/file")?,
+        None
+    );
+    let mut buf = Vec::new();
+    repo.read_file("dir/other", &mut buf)?;
+    assert_eq!(
+        crate::fs::file_checksum(&*txn.read(), &*channel.read(), "dir/other")?,
+        Some(*blake3::hash(&buf).as_bytes())
+    );
+
+    // A fork has its own checksums.
+    let fork = txn.write().fork(&channel, "fork")?;
+    assert_eq!(
+        crate::fs::file_checksum(&*txn.read(), &*fork.read(), "dir/other")?,
+        None
+    );
+    Ok(())
+}
+
+/// Compare the outputs of two channels without writing them.
+#[test]
+fn diff_channels_test() -> Result<(), anyhow::Error> {
+    use output::{PathDiff, PathDiffKind};
+    env_logger::try_init().unwrap_or(());
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("dir/file", b"a\nb\nc\n".to_vec());
+    repo.add_file("other", b"d\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("dir/file", 0)?;
+    txn.write().add_file("other", 0)?;
+    let main = txn.write().open_or_create_channel("main").unwrap();
+    record_all(&repo, &changes, &txn, &main, "")?;
+    let fork = txn.write().fork(&main, "fork")?;
+    assert!(output::diff_channels(&txn, &main, &fork, &changes)?.is_empty());
+
+    let repo_fork = working_copy::memory::Memory::new();
+    output::output_repository_no_pending(&repo_fork, &changes, &txn, &fork, "", true, None, 1, 0)?;
+    repo_fork
+        .write_file("dir/file", Inode::ROOT)?
+        .write_all(b"a\nx\nc\n")?;
+    repo_fork.remove_path("other", false)?;
+    repo_fork.add_file("new", b"e\n".to_vec());
+    txn.write().add_file("new", 0)?;
+    record_all(&repo_fork, &changes, &txn, &fork, "")?;
+
+    let diff = |path: &str, kind| PathDiff {
+        path: path.to_string(),
+        kind,
+    };
+    assert_eq!(
+        output::diff_channels(&txn, &main, &fork, &changes)?,
+        vec![
+            diff("dir/file", PathDiffKind::Modified),
+            diff("new", PathDiffKind::Added),
+            diff("other", PathDiffKind::Removed),
+        ]
+    );
+    assert_eq!(
+        output::diff_channels(&txn, &fork, &main, &changes)?,
+        vec![
+            diff("dir/file", PathDiffKind::Modified),
+            diff("new", PathDiffKind::Removed),
+            diff("other", PathDiffKind::Added),
+        ]
+    );
+    Ok(())
+}
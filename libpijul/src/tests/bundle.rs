@@ -0,0 +1,143 @@
+use crate::bundle::*;
+use crate::changestore::*;
+use crate::pristine::*;
+use crate::working_copy::*;
+use crate::*;
+use std::io::Write;
+
+use super::*;
+
+#[test]
+fn apply_bundle_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &store, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)
+        .unwrap()
+        .write_all(b"a\nx\nb\n")?;
+    let h1 = record_all(&repo, &store, &txn, &channel, "")?;
+
+    // Put the dependent change first, to check that the bundle is
+    // applied in dependency order.
+    let mut bundle = Vec::new();
+    for h in [h1, h0] {
+        let bytes = store.read_change_bytes(&h)?;
+        bundle.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+        bundle.extend_from_slice(&bytes);
+    }
+
+    let store2 = changestore::memory::Memory::new();
+    let env2 = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn2 = env2.mut_txn_begin()?;
+    let channel2 = txn2.open_or_create_channel("main")?;
+    let mut channel2 = channel2.write();
+    let applied = apply_bundle(&bundle[..], &store2, &mut txn2, &mut *channel2)?;
+    assert_eq!(applied, vec![h0, h1]);
+    for h in [h0, h1] {
+        let int = txn2.get_internal(&h.into())?.unwrap();
+        assert!(txn2.get_changeset(txn2.changes(&channel2), int)?.is_some());
+    }
+    assert_eq!(
+        current_state(&txn2, &*channel2)?,
+        current_state(&*txn.read(), &*channel.read())?
+    );
+
+    // Applying the same bundle again does nothing.
+    let applied = apply_bundle(&bundle[..], &store2, &mut txn2, &mut *channel2)?;
+    assert!(applied.is_empty());
+
+    // A truncated bundle is an error.
+    assert!(matches!(
+        apply_bundle(
+            &bundle[..bundle.len() - 1],
+            &store2,
+            &mut txn2,
+            &mut *channel2
+        ),
+        Err(BundleError::Io(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn export_bundle_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("a", b"a\n".to_vec());
+    repo.add_file("b", b"b\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("a", 0)?;
+    record_all(&repo, &store, &txn, &channel, "")?;
+    repo.write_file("a", Inode::ROOT)
+        .unwrap()
+        .write_all(b"a\nx\n")?;
+    let h1 = record_all(&repo, &store, &txn, &channel, "")?;
+    txn.write().add_file("b", 0)?;
+    let h2 = record_all(&repo, &store, &txn, &channel, "")?;
+
+    // Exporting `h1` brings the change adding "a" along.
+    let mut bundle = Vec::new();
+    assert_eq!(export_bundle(&mut bundle, &store, &*txn.read(), &[h1])?, 2);
+
+    let repo2 = working_copy::memory::Memory::new();
+    let store2 = changestore::memory::Memory::new();
+    let env2 = pristine::sanakirja::Pristine::new_anon()?;
+    let txn2 = env2.arc_txn_begin().unwrap();
+    let channel2 = txn2.write().open_or_create_channel("main")?;
+    apply_bundle(
+        &bundle[..],
+        &store2,
+        &mut *txn2.write(),
+        &mut *channel2.write(),
+    )?;
+    output::output_repository_no_pending(&repo2, &store2, &txn2, &channel2, "", true, None, 1, 0)
+        .unwrap();
+    assert_eq!(repo2.list_files(), vec!["a"]);
+    let mut a = Vec::new();
+    repo2.read_file("a", &mut a).unwrap();
+    assert_eq!(a, b"a\nx\n");
+
+    // Then the rest of the channel.
+    let mut bundle = Vec::new();
+    assert_eq!(
+        export_bundle(&mut bundle, &store, &*txn.read(), &[h1, h2])?,
+        3
+    );
+    let applied = apply_bundle(
+        &bundle[..],
+        &store2,
+        &mut *txn2.write(),
+        &mut *channel2.write(),
+    )?;
+    assert_eq!(applied, vec![h2]);
+    assert_eq!(
+        current_state(&*txn2.read(), &*channel2.read())?,
+        current_state(&*txn.read(), &*channel.read())?
+    );
+
+    // Changes unknown to the pristine can't be exported.
+    let mut bundle = Vec::new();
+    assert!(matches!(
+        export_bundle(
+            &mut bundle,
+            &store2,
+            &*txn2.read(),
+            &[Hash::Blake3([1; 32])]
+        ),
+        Err(BundleError::ChangeNotFound { .. })
+    ));
+    Ok(())
+}
@@ -201,3 +201,578 @@ fn text_test<C: ChangeStore>(c: &C, change0: &Change, h: Hash) {
     }
     assert_eq!(change0, &change1);
 }
+
+#[test]
+fn sign_verify() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\nc\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("file", 0)?;
+    let (_, mut change) = record_all_change(&repo, &store, &txn, &channel, "")?;
+
+    let key = crate::key::SKey::generate(None);
+    let other = crate::key::SKey::generate(None);
+    assert!(!verify_change(&change, &key.public_key())?);
+
+    sign_change(&mut change, &key)?;
+    assert!(verify_change(&change, &key.public_key())?);
+    assert!(!verify_change(&change, &other.public_key())?);
+    Ok(())
+}
+
+#[test]
+fn dependency_closure_chain() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\nc\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &store, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)
+        .unwrap()
+        .write_all(b"a\nb\nx\nc\n")?;
+    let h1 = record_all(&repo, &store, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)
+        .unwrap()
+        .write_all(b"a\nb\nx\ny\nc\n")?;
+    let h2 = record_all(&repo, &store, &txn, &channel, "")?;
+    assert!(store.get_dependencies(&h2)?.contains(&h1));
+
+    assert_eq!(dependency_closure(&store, &[h2])?, vec![h0, h1, h2]);
+    assert_eq!(dependency_closure(&store, &[h1, h2])?, vec![h0, h1, h2]);
+    assert_eq!(dependency_closure(&store, &[h0])?, vec![h0]);
+    Ok(())
+}
+
+#[test]
+fn record_extra_dependencies() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("a", b"a\n".to_vec());
+    repo.add_file("b", b"b\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("a", 0)?;
+    let ha = record_all(&repo, &store, &txn, &channel, "")?;
+    txn.write().add_file("b", 0)?;
+    let hb = record_all(&repo, &store, &txn, &channel, "")?;
+    assert!(!store.get_dependencies(&hb)?.contains(&ha));
+
+    repo.write_file("b", Inode::ROOT)
+        .unwrap()
+        .write_all(b"b\nx\n")?;
+    // Changes that aren't on the channel are rejected.
+    assert!(record_all_change_with_dependencies(
+        &repo,
+        &store,
+        &txn,
+        &channel,
+        "",
+        &[Hash::Blake3([1; 32])]
+    )
+    .is_err());
+    let (h, change) =
+        record_all_change_with_dependencies(&repo, &store, &txn, &channel, "", &[ha])?;
+    assert!(change.dependencies.contains(&hb));
+    assert!(change.dependencies.contains(&ha));
+    assert!(store.get_dependencies(&h)?.contains(&ha));
+    Ok(())
+}
+
+#[test]
+fn changes_by_author_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("a", b"a\n".to_vec());
+    repo.add_file("b", b"b\n".to_vec());
+    repo.add_file("c", b"c\n".to_vec());
+    repo.add_file("d", b"d\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    let record = |path: &str, author: &[(&str, &str)]| -> Result<Hash, anyhow::Error> {
+        txn.write().add_file(path, 0)?;
+        let author = author
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut change = crate::record::record_paths(
+            &txn,
+            &channel,
+            &repo,
+            &store,
+            &[path],
+            ChangeHeader {
+                authors: vec![Author(author)],
+                ..ChangeHeader::default()
+            },
+        )?;
+        let h = store.save_change(&mut change, |_, _| Ok::<_, anyhow::Error>(()))?;
+        apply::apply_change_arc(&store, &txn, &channel, &h)?;
+        Ok(h)
+    };
+    let alice = [("name", "alice"), ("email", "alice@example.org")];
+    let ha = record("a", &alice)?;
+    record("b", &[("name", "bob"), ("email", "bob@example.org")])?;
+    let hc = record("c", &alice)?;
+    let hd = record("d", &[("key", "7xMbt6Q5ZbK9qMbNnRkV")])?;
+
+    let txn = txn.read();
+    let channel = channel.read();
+    assert_eq!(
+        changes_by_author(&*txn, &channel, &store, "alice", None)?,
+        vec![hc, ha]
+    );
+    assert_eq!(
+        changes_by_author(&*txn, &channel, &store, "alice@", Some(1))?,
+        vec![hc]
+    );
+    assert_eq!(
+        changes_by_author(&*txn, &channel, &store, "7xMbt6Q5", None)?,
+        vec![hd]
+    );
+    assert!(changes_by_author(&*txn, &channel, &store, "carol", None)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn amend_change_message() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\nc\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("file", 0)?;
+    let (h, change) = record_all_change(&repo, &store, &txn, &channel, "")?;
+
+    let mut header = change.hashed.header.clone();
+    header.message = "amended".to_string();
+    let (amended, h_amended) = amend_change(&store, &h, header)?;
+    assert_ne!(h, h_amended);
+    assert_eq!(amended.hashed.header.message, "amended");
+    assert_eq!(amended.hashed.changes, change.hashed.changes);
+    assert_eq!(
+        store.get_change(&h_amended)?.hashed.header.message,
+        "amended"
+    );
+    Ok(())
+}
+
+#[test]
+fn unapplied_changes_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\nc\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    let other = txn.write().open_or_create_channel("other")?;
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &store, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)
+        .unwrap()
+        .write_all(b"a\nb\nx\nc\n")?;
+    let h1 = record_all(&repo, &store, &txn, &channel, "")?;
+
+    let txn = txn.read();
+    assert!(unapplied_changes(&*txn, &channel.read(), &store)?.is_empty());
+    let mut expected = vec![h0, h1];
+    expected.sort();
+    assert_eq!(unapplied_changes(&*txn, &other.read(), &store)?, expected);
+    Ok(())
+}
+
+#[test]
+fn change_activity_test() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    let dates = [(2022, 12, 31), (2023, 1, 1), (2023, 1, 15), (2023, 3, 2)];
+    for (i, (y, m, d)) in dates.iter().enumerate() {
+        let path = format!("file{}", i);
+        repo.add_file(&path, b"a\n".to_vec());
+        txn.write().add_file(&path, 0)?;
+        let mut change = crate::record::record_paths(
+            &txn,
+            &channel,
+            &repo,
+            &store,
+            &[path.as_str()],
+            ChangeHeader {
+                timestamp: chrono::Utc.with_ymd_and_hms(*y, *m, *d, 12, 0, 0).unwrap(),
+                ..ChangeHeader::default()
+            },
+        )?;
+        let h = store.save_change(&mut change, |_, _| Ok::<_, anyhow::Error>(()))?;
+        apply::apply_change_arc(&store, &txn, &channel, &h)?;
+    }
+
+    let activity = change_activity(&*txn.read(), &channel.read(), &store)?;
+    assert_eq!(
+        activity.into_iter().collect::<Vec<_>>(),
+        vec![((2022, 12), 1), ((2023, 1), 2), ((2023, 3), 1)]
+    );
+    Ok(())
+}
+
+/// A change store counting the headers it loads.
+struct CountingStore {
+    store: changestore::memory::Memory,
+    headers: std::sync::atomic::AtomicUsize,
+}
+
+impl ChangeStore for CountingStore {
+    type Error = changestore::memory::Error;
+    fn has_contents(&self, hash: Hash, change_id: Option<ChangeId>) -> bool {
+        self.store.has_contents(hash, change_id)
+    }
+    fn get_contents<F: Fn(ChangeId) -> Option<Hash>>(
+        &self,
+        hash: F,
+        key: Vertex<ChangeId>,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.store.get_contents(hash, key, buf)
+    }
+    fn get_header(&self, h: &Hash) -> Result<ChangeHeader, Self::Error> {
+        self.headers
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.store.get_header(h)
+    }
+    fn get_tag_header(&self, h: &crate::Merkle) -> Result<ChangeHeader, Self::Error> {
+        self.store.get_tag_header(h)
+    }
+    fn get_contents_ext(
+        &self,
+        key: Vertex<Option<Hash>>,
+        buf: &mut [u8],
+    ) -> Result<usize, Self::Error> {
+        self.store.get_contents_ext(key, buf)
+    }
+    fn change_deletes_position<F: Fn(ChangeId) -> Option<Hash>>(
+        &self,
+        hash: F,
+        change: ChangeId,
+        pos: Position<Option<Hash>>,
+    ) -> Result<Vec<Hash>, Self::Error> {
+        self.store.change_deletes_position(hash, change, pos)
+    }
+    fn save_change<
+        E: From<Self::Error> + From<ChangeError>,
+        F: FnOnce(&mut Change, &Hash) -> Result<(), E>,
+    >(
+        &self,
+        p: &mut Change,
+        f: F,
+    ) -> Result<Hash, E> {
+        self.store.save_change(p, f)
+    }
+    fn del_change(&self, h: &Hash) -> Result<bool, Self::Error> {
+        self.store.del_change(h)
+    }
+    fn get_change(&self, h: &Hash) -> Result<Change, Self::Error> {
+        self.store.get_change(h)
+    }
+    fn iter_changes(&self) -> Result<Vec<Hash>, Self::Error> {
+        self.store.iter_changes()
+    }
+}
+
+#[test]
+fn log_with_headers_lazy() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = CountingStore {
+        store: changestore::memory::Memory::new(),
+        headers: std::sync::atomic::AtomicUsize::new(0),
+    };
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    let mut hashes = Vec::new();
+    for i in 0..4 {
+        let path = format!("file{}", i);
+        repo.add_file(&path, b"a\n".to_vec());
+        txn.write().add_file(&path, 0)?;
+        hashes.push(record_all(&repo, &store.store, &txn, &channel, "")?);
+    }
+    let state = txn.read().current_state(&*channel.read())?;
+
+    let txn_ = txn.read();
+    let channel_ = channel.read();
+    let log: Vec<_> = log_with_headers(&*txn_, &channel_, &store, None)
+        .take(2)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        log.iter().map(|(h, _, _)| *h).collect::<Vec<_>>(),
+        vec![hashes[3], hashes[2]]
+    );
+    assert_eq!(log[0].1, state);
+    assert_eq!(store.headers.load(std::sync::atomic::Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[test]
+fn block_error_context() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    repo.add_file("file", b"a\n".to_vec());
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &store, &txn, &channel, "")?;
+
+    // Point the up context of the new name to a position that
+    // doesn't exist in any block.
+    let mut change = store.get_change(&h0)?;
+    match change.hashed.changes[0] {
+        Hunk::FileAdd {
+            add_name: Atom::NewVertex(ref mut n),
+            ..
+        } => {
+            n.up_context = vec![Position {
+                change: None,
+                pos: ChangePosition(10_000u64.into()),
+            }]
+        }
+        _ => unreachable!(),
+    }
+    let h1 = store.save_change(&mut change, |_, _| Ok::<_, anyhow::Error>(()))?;
+
+    let channel2 = txn.write().open_or_create_channel("other")?;
+    match apply::apply_change_arc(&store, &txn, &channel2, &h1) {
+        Err(ApplyError::LocalChange(
+            ref e @ LocalApplyError::BlockInChange {
+                ref hash, ref path, ..
+            },
+        )) => {
+            assert_eq!(*hash, h1);
+            assert_eq!(path, "file");
+            assert!(e.to_string().contains(&h1.to_base32()));
+        }
+        e => panic!("unexpected result: {:?}", e),
+    }
+    Ok(())
+}
+
+#[test]
+fn block_cache() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    repo.add_file("file", b"a\nb\nc\nd\ne\nf\ng\nh\n".to_vec());
+    txn.write().add_file("file", 0)?;
+    let mut hashes = vec![record_all(&repo, &store, &txn, &channel, "")?];
+    for contents in [
+        &b"a\nb\nx\nc\nd\ne\nf\ng\nh\n"[..],
+        b"a\nx\nc\ne\ny\nf\nh\n",
+        b"a\nc\ne\ny\nz\nh\n",
+    ] {
+        repo.write_file("file", Inode::ROOT)?.write_all(contents)?;
+        hashes.push(record_all(&repo, &store, &txn, &channel, "")?);
+    }
+
+    // Apply the same changes with and without a (very small) cache.
+    let uncached = txn.write().open_or_create_channel("uncached")?;
+    let cached = txn.write().open_or_create_channel("cached")?;
+    let mut ws = apply::Workspace::new();
+    let mut cws = apply::Workspace::with_block_cache(2);
+    for h in hashes.iter() {
+        let mut txn = txn.write();
+        apply::apply_change_ws(&store, &mut *txn, &mut *uncached.write(), h, &mut ws)?;
+        apply::apply_change_ws(&store, &mut *txn, &mut *cached.write(), h, &mut cws)?;
+    }
+
+    let txn = txn.read();
+    let graph = |c: &ChannelRef<_>| -> Vec<_> {
+        let c = c.read();
+        txn.iter_graph(txn.graph(&*c), None)
+            .unwrap()
+            .map(|x| {
+                let (v, e) = x.unwrap();
+                (*v, *e)
+            })
+            .collect()
+    };
+    let g = graph(&cached);
+    assert_eq!(g, graph(&uncached));
+
+    let cached = cached.read();
+    let mut cws = apply::Workspace::with_block_cache(16);
+    for _ in 0..2 {
+        for (v, _) in g.iter() {
+            let start = v.start_pos();
+            assert_eq!(
+                cws.find_block(&*txn, txn.graph(&*cached), start)?,
+                *txn.find_block(txn.graph(&*cached), start)?
+            );
+            let end = v.end_pos();
+            assert_eq!(
+                cws.find_block_end(&*txn, txn.graph(&*cached), end)?,
+                *txn.find_block_end(txn.graph(&*cached), end)?
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn prune_empty_atoms() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("file", 0)?;
+    let h = record_all(&repo, &store, &txn, &channel, "")?;
+    let recorded = store.get_change(&h)?;
+
+    // The file addition contains an empty inode vertex, which must
+    // survive, next to an empty edge map, which must not.
+    let mut change = recorded.clone();
+    let inode = change
+        .changes
+        .iter()
+        .find_map(|h| match h {
+            Hunk::FileAdd { add_inode, .. } => Some(add_inode.inode()),
+            _ => None,
+        })
+        .unwrap();
+    change.changes.push(Hunk::Edit {
+        change: Atom::EdgeMap(EdgeMap {
+            edges: Vec::new(),
+            inode,
+        }),
+        local: Local {
+            path: "file".to_string(),
+            line: 1,
+        },
+        encoding: None,
+    });
+    assert_eq!(crate::change::prune_empty_atoms(&mut change), 1);
+    assert_eq!(change.changes, recorded.changes);
+    Ok(())
+}
+
+#[test]
+fn min_unambiguous_prefix_len() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    let mut hashes = Vec::new();
+    for i in 0..3 {
+        let name = format!("file{}", i);
+        repo.add_file(&name, b"a\n".to_vec());
+        txn.write().add_file(&name, 0)?;
+        hashes.push(record_all(&repo, &store, &txn, &channel, "")?);
+    }
+    let len = pristine::min_unambiguous_prefix_len(&*txn.read())?;
+    assert!(len < 8);
+    for h in hashes.iter() {
+        let b32 = h.to_base32();
+        let (prefix, _) = b32.split_at(len);
+        assert_eq!(txn.read().hash_from_prefix(prefix)?.0, *h);
+    }
+
+    // A hash that differs from an existing one in its very last bit.
+    let mut near = match hashes[0] {
+        Hash::Blake3(h) => h,
+        Hash::None => unreachable!(),
+    };
+    near[31] ^= 1;
+    txn.write()
+        .put_external(&ChangeId(L64(1 << 40)), &Hash::Blake3(near).into())?;
+    assert_eq!(pristine::min_unambiguous_prefix_len(&*txn.read())?, 52);
+    Ok(())
+}
+
+#[test]
+fn dep_symmetry() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let store = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &store, &txn, &channel, "")?;
+    write!(repo.write_file("file", Inode::ROOT)?, "a\nx\nb\n")?;
+    let h1 = record_all(&repo, &store, &txn, &channel, "")?;
+    assert!(pristine::check_dep_symmetry(&*txn.read())?.is_empty());
+
+    let mut txn = txn.write();
+    let p0 = *txn.get_internal(&h0.into())?.unwrap();
+    let p1 = *txn.get_internal(&h1.into())?.unwrap();
+    assert!(txn.del_revdep(&p0, Some(&p1))?);
+    assert_eq!(pristine::check_dep_symmetry(&*txn)?, vec![(p1, p0)]);
+
+    assert_eq!(pristine::repair_dep_symmetry(&mut *txn)?, 1);
+    assert!(pristine::check_dep_symmetry(&*txn)?.is_empty());
+    assert_eq!(txn.get_revdep(&p0, Some(&p1))?, Some(&p1));
+    Ok(())
+}
+
+#[test]
+fn append_description() {
+    let mut header = ChangeHeader {
+        message: "Fix the parser".to_string(),
+        ..ChangeHeader::default()
+    };
+    // Output of a hook, with its trailing newline.
+    header.append_description("Ticket: PIJ-12\n");
+    assert_eq!(header.description.as_deref(), Some("Ticket: PIJ-12"));
+    header.append_description("  ");
+    header.append_description("Reviewed-by: pmeunier");
+    assert_eq!(
+        header.description.as_deref(),
+        Some("Ticket: PIJ-12\n\nReviewed-by: pmeunier")
+    );
+}
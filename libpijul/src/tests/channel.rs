@@ -0,0 +1,509 @@
+use super::*;
+
+#[test]
+fn duplicate_channel_ids() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let id = {
+        let txn = env.arc_txn_begin().unwrap();
+        let a = txn.write().open_or_create_channel("a")?;
+        let b = txn.write().open_or_create_channel("b")?;
+        txn.write().open_or_create_channel("c")?;
+        assert!(pristine::find_duplicate_channel_ids(&*txn.read())?.is_empty());
+
+        // Pin the id of `b` to the id of `a`.
+        let id = a.read().id;
+        b.write().id = id;
+        txn.commit()?;
+        id
+    };
+    let txn = env.txn_begin()?;
+    let dup = pristine::find_duplicate_channel_ids(&txn)?;
+    assert_eq!(dup.len(), 1);
+    let (dup_id, names) = &dup[0];
+    assert_eq!(*dup_id, id);
+    let names: Vec<_> = names.iter().map(|x| x.as_str()).collect();
+    assert_eq!(names, vec!["a", "b"]);
+    Ok(())
+}
+
+#[test]
+fn change_order_application_then_hash() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("x", b"x\n".to_vec());
+    repo.add_file("y", b"y\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let main = txn.write().open_or_create_channel("main")?;
+    let other = txn.write().open_or_create_channel("other")?;
+    txn.write().add_file("x", 0)?;
+    let hx = record_all(&repo, &changes, &txn, &main, "x")?;
+    txn.write().add_file("y", 0)?;
+    let hy = record_all(&repo, &changes, &txn, &main, "y")?;
+
+    let txn = txn.read();
+    let x = *txn.get_internal(&hx.into())?.unwrap();
+    let y = *txn.get_internal(&hy.into())?.unwrap();
+
+    // On `main`, changes are ordered by application time.
+    assert_eq!(
+        pristine::change_order(&*txn, &main.read(), &x, &y)?,
+        std::cmp::Ordering::Less
+    );
+    // Neither change is on `other`, so they are ordered by hash.
+    assert_eq!(
+        pristine::change_order(&*txn, &other.read(), &x, &y)?,
+        hx.cmp(&hy)
+    );
+    assert_eq!(
+        pristine::change_order(&*txn, &other.read(), &y, &x)?,
+        hy.cmp(&hx)
+    );
+    assert_eq!(
+        pristine::change_order(&*txn, &other.read(), &x, &x)?,
+        std::cmp::Ordering::Equal
+    );
+    Ok(())
+}
+
+#[test]
+fn remote_tip() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+    let mut remote = txn
+        .write()
+        .open_or_create_remote(RemoteId::nil(), "remote")?;
+    assert!(pristine::remote_tip(&*txn.read(), &remote)?.is_none());
+    assert!(pristine::channel_tip(&*txn.read(), &channel.read())?.is_none());
+
+    repo.add_file("file", b"a\n".to_vec());
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    let s0 = txn.read().current_state(&*channel.read())?;
+    repo.add_file("file2", b"b\n".to_vec());
+    txn.write().add_file("file2", 0)?;
+    let h1 = record_all(&repo, &changes, &txn, &channel, "")?;
+    let s1 = txn.read().current_state(&*channel.read())?;
+
+    txn.write().put_remote(&mut remote, 0, (h0, s0))?;
+    txn.write().put_remote(&mut remote, 1, (h1, s1))?;
+    let tip = pristine::remote_tip(&*txn.read(), &remote)?;
+    assert_eq!(tip, Some((h1, s1, 1)));
+    assert_eq!(pristine::channel_tip(&*txn.read(), &channel.read())?, tip);
+    Ok(())
+}
+
+#[test]
+fn channel_at_state() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main")?;
+
+    repo.add_file("file", b"a\n".to_vec());
+    txn.write().add_file("file", 0)?;
+    record_all(&repo, &changes, &txn, &channel, "")?;
+    let s0 = txn.read().current_state(&*channel.read())?;
+    txn.write().fork(&channel, "b")?;
+    txn.write().fork(&channel, "c")?;
+
+    repo.add_file("file2", b"b\n".to_vec());
+    txn.write().add_file("file2", 0)?;
+    record_all(&repo, &changes, &txn, &channel, "")?;
+    let s1 = txn.read().current_state(&*channel.read())?;
+
+    let prefix = &s0.to_base32()[..10];
+    let at = pristine::channel_at_state(&*txn.read(), prefix)?;
+    assert_eq!(
+        at.iter().map(|(n, s)| (n.as_str(), *s)).collect::<Vec<_>>(),
+        vec![("b", s0), ("c", s0)]
+    );
+    let at = pristine::channel_at_state(&*txn.read(), &s1.to_base32())?;
+    assert_eq!(
+        at.iter().map(|(n, s)| (n.as_str(), *s)).collect::<Vec<_>>(),
+        vec![("main", s1)]
+    );
+    Ok(())
+}
+
+#[test]
+fn pristine_version() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.mut_txn_begin()?;
+    txn.commit()?;
+    let version = env.on_disk_version()?;
+    assert_eq!(version, 1);
+    env.upgrade(version)?;
+    assert_eq!(env.on_disk_version()?, version);
+    assert!(env.arc_txn_begin().is_ok());
+
+    assert!(matches!(
+        env.upgrade(version + 1),
+        Err(pristine::sanakirja::UpgradeError::Newer { .. })
+    ));
+    // `from` must be the version on disk.
+    assert!(matches!(
+        env.upgrade(0),
+        Err(pristine::sanakirja::UpgradeError::VersionMismatch {
+            expected: 0,
+            found: 1
+        })
+    ));
+    env.migrate(version, version)?;
+    assert!(matches!(
+        env.migrate(version, 0),
+        Err(pristine::sanakirja::UpgradeError::Downgrade { from: 1, to: 0 })
+    ));
+
+    // There is no migration from a pristine that was never
+    // initialised.
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    assert_eq!(env.on_disk_version()?, 0);
+    assert!(matches!(
+        env.upgrade(0),
+        Err(pristine::sanakirja::UpgradeError::NoMigration { version: 0 })
+    ));
+    Ok(())
+}
+
+#[test]
+fn all_tags() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let main = txn.write().open_or_create_channel("main")?;
+
+    repo.add_file("file", b"a\n".to_vec());
+    txn.write().add_file("file", 0)?;
+    record_all(&repo, &changes, &txn, &main, "")?;
+    let s0 = txn.read().current_state(&*main.read())?;
+    let b = txn.write().fork(&main, "b")?;
+
+    repo.add_file("file2", b"b\n".to_vec());
+    txn.write().add_file("file2", 0)?;
+    record_all(&repo, &changes, &txn, &b, "")?;
+    let s1 = txn.read().current_state(&*b.read())?;
+
+    txn.write().put_tags(&mut main.write().tags, 0, &s0)?;
+    txn.write().put_tags(&mut b.write().tags, 1, &s1)?;
+
+    let tags = pristine::all_tags(&*txn.read())?;
+    assert_eq!(
+        tags.iter()
+            .map(|(n, t, s)| (n.as_str(), *t, *s))
+            .collect::<Vec<_>>(),
+        vec![("main", 0, s0), ("b", 1, s1)]
+    );
+    Ok(())
+}
+
+#[test]
+fn rename_current_channel() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let mut a = txn.write().open_or_create_channel("a")?;
+    let mut other = txn.write().open_or_create_channel("other")?;
+    txn.write().set_current_channel("a")?;
+    txn.write().rename_channel(&mut a, "b")?;
+    txn.write().rename_channel(&mut other, "other2")?;
+    txn.commit()?;
+
+    let txn = env.txn_begin()?;
+    assert_eq!(txn.current_channel()?, "b");
+    assert!(txn.load_channel("b")?.is_some());
+    Ok(())
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn open_handles() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    txn.open_or_create_channel("main")?;
+    txn.open_or_create_channel("a")?;
+    txn.commit()?;
+
+    let mut txn = env.mut_txn_begin()?;
+    assert!(txn.open_handles().0.is_empty());
+    let a = txn.load_channel("a")?.unwrap();
+    let (channels, remotes) = txn.open_handles();
+    assert_eq!(
+        channels.iter().map(|c| c.as_str()).collect::<Vec<_>>(),
+        vec!["a"]
+    );
+    assert!(remotes.is_empty());
+
+    std::mem::drop(a);
+    assert!(txn.drop_channel("a")?);
+    assert!(txn.open_handles().0.is_empty());
+    Ok(())
+}
+
+#[test]
+fn drop_current_channel() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    txn.open_or_create_channel("main")?;
+    txn.open_or_create_channel("a")?;
+    txn.set_current_channel("a")?;
+    assert!(matches!(
+        txn.drop_channel("a"),
+        Err(pristine::sanakirja::SanakirjaError::CannotDropCurrentChannel { .. })
+    ));
+    assert!(txn.load_channel("a")?.is_some());
+
+    assert!(txn.force_drop_channel("a")?);
+    assert_eq!(txn.current_channel()?, "main");
+    assert!(txn.load_channel("a")?.is_none());
+
+    // "main" is only the default, it can be dropped if no current
+    // channel was set.
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    txn.open_or_create_channel("main")?;
+    assert_eq!(txn.current_channel()?, "main");
+    assert!(txn.drop_channel("main")?);
+    Ok(())
+}
+
+#[test]
+fn channel_source_url() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    {
+        // What `pijul clone` does with the remote it cloned from.
+        let mut txn = env.mut_txn_begin()?;
+        let main = txn.open_or_create_channel("main")?;
+        txn.set_source_url(&mut *main.write(), Some("https://nest.pijul.com/a/b"));
+        txn.open_or_create_channel("other")?;
+        txn.commit()?;
+    }
+    {
+        let mut txn = env.mut_txn_begin()?;
+        let main = txn.load_channel("main")?.unwrap();
+        assert_eq!(
+            txn.source_url(&*main.read()),
+            Some("https://nest.pijul.com/a/b")
+        );
+        let fork = txn.fork(&main, "fork")?;
+        assert_eq!(
+            txn.source_url(&*fork.read()),
+            Some("https://nest.pijul.com/a/b")
+        );
+        txn.commit()?;
+    }
+    let txn = env.txn_begin()?;
+    let fork = txn.load_channel("fork")?.unwrap();
+    assert_eq!(
+        txn.source_url(&*fork.read()),
+        Some("https://nest.pijul.com/a/b")
+    );
+    let other = txn.load_channel("other")?.unwrap();
+    assert_eq!(txn.source_url(&*other.read()), None);
+    Ok(())
+}
+
+#[test]
+fn put_changes_batch() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    let a = txn.open_or_create_channel("a")?;
+    let b = txn.open_or_create_channel("b")?;
+    let changes: Vec<_> = (1..4u8)
+        .map(|i| (ChangeId(L64(i as u64)), i as u64, Hash::Blake3([i; 32])))
+        .collect();
+
+    let mut one_by_one = Vec::new();
+    for (p, t, h) in changes.iter() {
+        one_by_one.push(txn.put_changes(&mut *a.write(), *p, *t, h)?);
+    }
+    let batch = txn.put_changes_batch(&mut *b.write(), &changes)?;
+    assert_eq!(batch, one_by_one);
+    assert!(batch.iter().all(|m| m.is_some()));
+    assert_eq!(
+        pristine::current_state(&txn, &*a.read())?,
+        pristine::current_state(&txn, &*b.read())?
+    );
+
+    // Changes already on the channel are skipped.
+    assert_eq!(
+        txn.put_changes_batch(&mut *b.write(), &changes[..1])?,
+        vec![None]
+    );
+    Ok(())
+}
+
+#[test]
+fn render_states() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    use crate::display::*;
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    let channel = txn.open_or_create_channel("main")?;
+    let m1 = txn
+        .put_changes(
+            &mut *channel.write(),
+            ChangeId(L64(1)),
+            1,
+            &Hash::Blake3([1; 32]),
+        )?
+        .unwrap();
+    let m2 = txn
+        .put_changes(
+            &mut *channel.write(),
+            ChangeId(L64(2)),
+            2,
+            &Hash::Blake3([2; 32]),
+        )?
+        .unwrap();
+
+    let full = render_state(&m1, false);
+    let short = render_state(&m1, true);
+    assert_eq!(full, m1.to_base32());
+    assert_eq!(short.len(), SHORT_STATE_LEN);
+    assert!(full.starts_with(&short));
+
+    txn.put_tags(&mut channel.write().tags, 2, &m2)?;
+    let channel = channel.read();
+    assert_eq!(state_status(&txn, &channel, &m1)?, StateStatus::Known);
+    assert_eq!(state_status(&txn, &channel, &m2)?, StateStatus::Tagged);
+    assert_eq!(
+        render_channel_state(&txn, &channel, &m2, true)?,
+        format!("{} (tagged)", render_state(&m2, true))
+    );
+    assert_eq!(
+        state_status(&txn, &channel, &Merkle::zero())?,
+        StateStatus::Unknown
+    );
+    Ok(())
+}
+
+#[test]
+fn abort_txn() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let f = tempfile::tempdir()?;
+    let path = f.path().join("pristine");
+    {
+        let env = pristine::sanakirja::Pristine::new(&path)?;
+        let mut txn = env.mut_txn_begin()?;
+        txn.open_or_create_channel("main")?;
+        txn.commit()?;
+
+        let mut txn = env.mut_txn_begin()?;
+        let main = txn.load_channel("main")?.unwrap();
+        txn.put_changes(
+            &mut *main.write(),
+            ChangeId(L64(1)),
+            1,
+            &Hash::Blake3([1; 32]),
+        )?;
+        txn.open_or_create_channel("other")?;
+        txn.set_current_channel("other")?;
+        txn.abort();
+    }
+    let env = pristine::sanakirja::Pristine::new(&path)?;
+    let txn = env.txn_begin()?;
+    let names: Vec<_> = txn
+        .channels("")?
+        .iter()
+        .map(|c| c.read().name.to_string())
+        .collect();
+    assert_eq!(names, vec!["main"]);
+    let main = txn.load_channel("main")?.unwrap();
+    assert_eq!(txn.log(&*main.read(), 0)?.count(), 0);
+    assert_eq!(txn.current_channel()?, "main");
+    Ok(())
+}
+
+#[test]
+fn changes_at_state() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    let channel = txn.open_or_create_channel("main")?;
+    let mut hashes = Vec::new();
+    let mut states = Vec::new();
+    for i in 1..5u8 {
+        let h = Hash::Blake3([i; 32]);
+        let p = ChangeId(L64(i as u64));
+        txn.put_external(&p, &h.into())?;
+        txn.put_internal(&h.into(), &p)?;
+        states.push(
+            txn.put_changes(&mut *channel.write(), p, i as u64, &h)?
+                .unwrap(),
+        );
+        hashes.push(h);
+    }
+    let channel = channel.read();
+    let channel = &*channel;
+    assert_eq!(
+        pristine::changes_at_state(&txn, channel, &states[1])?,
+        Some(hashes[..2].to_vec())
+    );
+    assert_eq!(
+        pristine::changes_at_state(&txn, channel, &states[3])?,
+        Some(hashes.clone())
+    );
+    assert_eq!(
+        pristine::changes_at_state(&txn, channel, &Merkle::zero())?,
+        Some(Vec::new())
+    );
+    let other = Merkle::zero().next(&Hash::Blake3([9; 32]));
+    assert_eq!(pristine::changes_at_state(&txn, channel, &other)?, None);
+    Ok(())
+}
+
+#[test]
+fn channel_names() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let mut txn = env.mut_txn_begin()?;
+    for name in ["main", "dev", "feature"] {
+        txn.open_or_create_channel(name)?;
+    }
+    txn.commit()?;
+
+    let txn = env.txn_begin()?;
+    let names = |start: &str| -> Result<Vec<String>, anyhow::Error> {
+        let mut names = Vec::new();
+        for name in txn.channel_names(start)? {
+            names.push(name?.as_str().to_string())
+        }
+        Ok(names)
+    };
+    assert_eq!(names("")?, vec!["dev", "feature", "main"]);
+    assert_eq!(names("e")?, vec!["feature", "main"]);
+    assert!(names("zzz")?.is_empty());
+    Ok(())
+}
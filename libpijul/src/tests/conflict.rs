@@ -1280,6 +1280,132 @@ fn zombie_last_line() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[test]
+fn zombie_conflict_report() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo_alice = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo_alice.add_file("file", b"a\nb".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel_alice = txn.write().open_or_create_channel("alice")?;
+    txn.write().add_file("file", 0)?;
+    let init_h = record_all(&repo_alice, &changes, &txn, &channel_alice, "")?;
+
+    let repo_bob = working_copy::memory::Memory::new();
+    let channel_bob = txn.write().open_or_create_channel("bob")?;
+    apply::apply_change_arc(&changes, &txn, &channel_bob, &init_h)?;
+    output::output_repository_no_pending(
+        &repo_bob,
+        &changes,
+        &txn,
+        &channel_bob,
+        "",
+        true,
+        None,
+        1,
+        0,
+    )?;
+    repo_bob.write_file("file", Inode::ROOT)?.write_all(b"")?;
+    let bob_h = record_all(&repo_bob, &changes, &txn, &channel_bob, "")?;
+
+    repo_alice
+        .write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx")?;
+    record_all(&repo_alice, &changes, &txn, &channel_alice, "")?;
+
+    apply::apply_change_arc(&changes, &txn, &channel_alice, &bob_h)?;
+    let conflicts = output::output_repository_no_pending(
+        &repo_alice,
+        &changes,
+        &txn,
+        &channel_alice,
+        "",
+        true,
+        None,
+        1,
+        0,
+    )?;
+    let zombie = conflicts
+        .iter()
+        .find(|c| c.kind() == output::ConflictKind::Zombie)
+        .expect("no zombie conflict");
+    let report = zombie.to_report(&*txn.read())?;
+    assert_eq!(report.path, "file");
+    assert_eq!(report.line, Some(1));
+    assert_eq!(report.changes.len(), zombie.changes().len());
+    Ok(())
+}
+
+#[test]
+fn group_conflicts_by_path() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo_alice = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo_alice.add_file("a", b"x\ny\n".to_vec());
+    repo_alice.add_file("b", b"x\ny\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel_alice = txn.write().open_or_create_channel("alice")?;
+    txn.write().add_file("a", 0)?;
+    txn.write().add_file("b", 0)?;
+    let init_h = record_all(&repo_alice, &changes, &txn, &channel_alice, "")?;
+
+    let repo_bob = working_copy::memory::Memory::new();
+    let channel_bob = txn.write().open_or_create_channel("bob")?;
+    apply::apply_change_arc(&changes, &txn, &channel_bob, &init_h)?;
+    output::output_repository_no_pending(
+        &repo_bob,
+        &changes,
+        &txn,
+        &channel_bob,
+        "",
+        true,
+        None,
+        1,
+        0,
+    )?;
+    for f in ["a", "b"] {
+        repo_bob
+            .write_file(f, Inode::ROOT)?
+            .write_all(b"x\nbob\ny\n")?;
+        repo_alice
+            .write_file(f, Inode::ROOT)?
+            .write_all(b"x\nalice\ny\n")?;
+    }
+    let bob_h = record_all(&repo_bob, &changes, &txn, &channel_bob, "")?;
+    record_all(&repo_alice, &changes, &txn, &channel_alice, "")?;
+
+    apply::apply_change_arc(&changes, &txn, &channel_alice, &bob_h)?;
+    let conflicts = output::output_repository_no_pending(
+        &repo_alice,
+        &changes,
+        &txn,
+        &channel_alice,
+        "",
+        true,
+        None,
+        1,
+        0,
+    )?;
+    let groups = output::group_conflicts_by_path(&conflicts);
+    assert_eq!(groups.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+    for (path, g) in groups.iter() {
+        assert_eq!(g.len(), 1);
+        assert_eq!(g[0].path(), path);
+        assert_eq!(g[0].kind(), output::ConflictKind::Order);
+    }
+    assert_eq!(
+        groups.values().map(|g| g.len()).sum::<usize>(),
+        conflicts.len()
+    );
+    Ok(())
+}
+
 #[test]
 fn edit_post_conflict() -> Result<(), anyhow::Error> {
     edit_post_conflict_(
@@ -2727,3 +2853,63 @@ fn check_tree_inodes<T: TxnT>(txn: &T, channel: &T::Channel) {
         }
     }
 }
+
+#[test]
+fn conflict_regions() {
+    use crate::vertex_buffer::{has_unresolved_conflicts, list_conflict_regions};
+    let resolved = b"a\n=======\nb\n>>>>>>> not a marker\n";
+    assert!(!has_unresolved_conflicts(resolved));
+    assert!(list_conflict_regions(resolved).is_empty());
+
+    let conflict = b"a\n>>>>>>> 1 [AAAAAAAA message]\nx\n>>>>>>> 2\ny\n<<<<<<< 2\n======= 1 [BBBBBBBB other]\nz\n<<<<<<< 1\nb\n";
+    assert!(has_unresolved_conflicts(conflict));
+    let regions = list_conflict_regions(conflict);
+    assert_eq!(regions.len(), 1);
+    assert!(conflict[regions[0].clone()].starts_with(b">>>>>>> 1 "));
+    assert!(conflict[regions[0].clone()].ends_with(b"<<<<<<< 1\n"));
+    assert_eq!(&conflict[regions[0].end..], b"b\n");
+
+    // A half-resolved conflict.
+    let partial = b"a\n>>>>>>> 3\nx\n";
+    assert!(has_unresolved_conflicts(partial));
+    assert_eq!(list_conflict_regions(partial), vec![2..partial.len()]);
+}
+
+#[test]
+fn merge_inputs_order_conflict() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let alice = txn.write().open_or_create_channel("alice")?;
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &changes, &txn, &alice, "")?;
+    assert_eq!(output::merge_inputs(&txn, &alice, &changes, "file")?, None);
+
+    let repo_bob = working_copy::memory::Memory::new();
+    let bob = txn.write().open_or_create_channel("bob")?;
+    apply::apply_change_arc(&changes, &txn, &bob, &h0)?;
+    output::output_repository_no_pending(&repo_bob, &changes, &txn, &bob, "", true, None, 1, 0)?;
+    repo_bob
+        .write_file("file", Inode::ROOT)?
+        .write_all(b"a\nu\nb\n")?;
+    let bob_h = record_all(&repo_bob, &changes, &txn, &bob, "")?;
+    repo.write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx\nb\n")?;
+    record_all(&repo, &changes, &txn, &alice, "")?;
+    apply::apply_change_arc(&changes, &txn, &alice, &bob_h)?;
+
+    let inputs = output::merge_inputs(&txn, &alice, &changes, "file")?.unwrap();
+    debug!("inputs = {:?}", inputs);
+    assert_eq!(inputs.base, b"a\nb\n");
+    assert_ne!(inputs.ours, inputs.theirs);
+    let mut sides = [inputs.ours, inputs.theirs];
+    sides.sort();
+    assert_eq!(sides, [b"a\nu\nb\n".to_vec(), b"a\nx\nb\n".to_vec()]);
+
+    assert!(output::merge_inputs(&txn, &alice, &changes, "nonexistent").is_err());
+    Ok(())
+}
@@ -87,6 +87,7 @@ fn bin_diff_test() -> Result<(), anyhow::Error> {
             contents.as_bytes(),
             &None,
             &crate::DEFAULT_SEPARATOR,
+            Default::default(),
         )?;
         debug!("{:#?}", rec.lock().actions);
         record_all(&repo, &changes, &txn, &channel, "").unwrap();
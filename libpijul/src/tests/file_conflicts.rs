@@ -302,6 +302,71 @@ fn same_name_test() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[test]
+fn predict_name_conflict() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let contents = b"a\nb\nc\n";
+
+    let repo_alice = working_copy::memory::Memory::new();
+    let repo_bob = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo_alice.add_file("file1", contents.to_vec());
+    repo_alice.add_file("file2", contents.to_vec());
+
+    let env_alice = pristine::sanakirja::Pristine::new_anon()?;
+    let txn_alice = env_alice.arc_txn_begin().unwrap();
+    let env_bob = pristine::sanakirja::Pristine::new_anon()?;
+    let txn_bob = env_bob.arc_txn_begin().unwrap();
+
+    let channel_alice = txn_alice.write().open_or_create_channel("alice")?;
+    txn_alice.write().add_file("file1", 0)?;
+    txn_alice.write().add_file("file2", 0)?;
+    let init_h = record_all(&repo_alice, &changes, &txn_alice, &channel_alice, "")?;
+
+    // Bob clones
+    let channel_bob = txn_bob.write().open_or_create_channel("bob").unwrap();
+    apply::apply_change_arc(&changes, &txn_bob, &channel_bob, &init_h).unwrap();
+    output::output_repository_no_pending(
+        &repo_bob,
+        &changes,
+        &txn_bob,
+        &channel_bob,
+        "",
+        true,
+        None,
+        1,
+        0,
+    )?;
+
+    // Alice renames "file1" to "file"
+    repo_alice.rename("file1", "file")?;
+    txn_alice.write().move_file("file1", "file", 0)?;
+    record_all(&repo_alice, &changes, &txn_alice, &channel_alice, "")?;
+
+    // Bob renames "file2" to "file"
+    repo_bob.rename("file2", "file")?;
+    txn_bob.write().move_file("file2", "file", 0)?;
+    let bob_h = record_all(&repo_bob, &changes, &txn_bob, &channel_bob, "")?;
+
+    std::mem::drop(channel_alice);
+    txn_alice.commit()?;
+
+    // Alice checks what Bob's change would do.
+    let txn = env_alice.mut_txn_begin()?;
+    let channel = txn.load_channel("alice")?.unwrap();
+    let conflicts = output::conflicts_after_apply(&changes, txn, &channel, &bob_h)?;
+    assert!(conflicts
+        .iter()
+        .any(|c| matches!(c, output::Conflict::Name { path, .. } if path == "file")));
+
+    // Nothing was applied to Alice's channel.
+    let txn = env_alice.txn_begin()?;
+    let channel = txn.load_channel("alice")?.unwrap();
+    assert!(txn.has_change(&channel, &bob_h)?.is_none());
+    Ok(())
+}
+
 #[test]
 fn file_conflicts_same_name_and_two_names() -> Result<(), anyhow::Error> {
     env_logger::try_init().unwrap_or(());
@@ -1,4 +1,5 @@
 use super::*;
+use crate::working_copy::WorkingCopyRead;
 use std::io::Write;
 
 const MAX_FILES: usize = 10;
@@ -147,3 +148,553 @@ fn overwrite_dead_symlink() -> Result<(), anyhow::Error> {
     txn.commit().unwrap();
     Ok(())
 }
+
+#[test]
+#[cfg(unix)]
+fn filesystem_available_space() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let repo = working_copy::filesystem::FileSystem::from_root(r.path());
+    let available = repo.available_space()?;
+    assert!(available.is_some());
+    assert!(available.unwrap() > 0);
+    Ok(())
+}
+
+#[test]
+fn verify_change_files() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx\nb\n")?;
+    let h1 = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let mut listed = changes.iter_changes()?;
+    listed.sort();
+    let mut expected = vec![h0, h1];
+    expected.sort();
+    assert_eq!(listed, expected);
+    assert!(changestore::verify_all(&changes)?.is_empty());
+
+    // Overwrite the file of `h1` with the contents of `h0`.
+    let buf = std::fs::read(changes.filename(&h0))?;
+    changes.save_from_buf_unchecked(&buf, &h1, None)?;
+
+    assert!(changestore::verify_change_file(&changes, &h0)?);
+    assert!(!changestore::verify_change_file(&changes, &h1)?);
+    assert_eq!(changestore::verify_all(&changes)?, vec![h1]);
+
+    // A truncated file doesn't verify, but a missing one is an error.
+    std::fs::write(changes.filename(&h1), &buf[..10])?;
+    assert!(!changestore::verify_change_file(&changes, &h1)?);
+    std::fs::remove_file(changes.filename(&h1))?;
+    assert!(changestore::verify_change_file(&changes, &h1).is_err());
+    Ok(())
+}
+
+#[test]
+fn filesystem_os_paths() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let repo = working_copy::filesystem::FileSystem::from_root(r.path());
+    repo.write_file("dir/file", Inode::ROOT)?
+        .write_all(b"a\nb\n")?;
+
+    let path = std::path::Path::new("dir").join("file");
+    let mut buf = Vec::new();
+    repo.read_file_os(&path, &mut buf)?;
+    assert_eq!(buf, b"a\nb\n");
+    assert!(!repo.file_metadata_os(&path)?.is_dir());
+
+    let path = std::path::Path::new(".").join("dir").join("file");
+    assert!(!repo.file_metadata_os(&path)?.is_dir());
+    let path = std::path::Path::new("..").join("file");
+    let err = repo.file_metadata_os(&path).unwrap_err();
+    assert!(matches!(
+        err.get_ref()
+            .and_then(|e| e.downcast_ref::<working_copy::InvalidPath>()),
+        Some(working_copy::InvalidPath::NotRelative { .. })
+    ));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::path::Path::new(std::ffi::OsStr::from_bytes(b"dir/\xff"));
+        let err = repo.read_file_os(path, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(
+            err.get_ref()
+                .and_then(|e| e.downcast_ref::<working_copy::InvalidPath>()),
+            Some(working_copy::InvalidPath::NonUtf8(_))
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn preserve_full_mode() -> Result<(), anyhow::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let repo =
+        working_copy::filesystem::FileSystem::from_root(r.path()).with_preserve_full_mode(true);
+    repo.write_file("file", Inode::ROOT)?.write_all(b"a\nb\n")?;
+    std::fs::set_permissions(
+        r.path().join("file"),
+        std::fs::Permissions::from_mode(0o640),
+    )?;
+
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let r2 = tempfile::tempdir()?;
+    let repo2 =
+        working_copy::filesystem::FileSystem::from_root(r2.path()).with_preserve_full_mode(true);
+    let env2 = pristine::sanakirja::Pristine::new_anon()?;
+    let txn2 = env2.arc_txn_begin().unwrap();
+    let channel2 = txn2.write().open_or_create_channel("main")?;
+    apply::apply_change_arc(&changes, &txn2, &channel2, &h)?;
+    output::output_repository_no_pending(&repo2, &changes, &txn2, &channel2, "", true, None, 1, 0)?;
+
+    let mode = std::fs::metadata(r2.path().join("file"))?
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o640);
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn toggle_preserve_full_mode() -> Result<(), anyhow::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let repo = working_copy::filesystem::FileSystem::from_root(r.path());
+    repo.write_file("file", Inode::ROOT)?.write_all(b"a\nb\n")?;
+    std::fs::set_permissions(
+        r.path().join("file"),
+        std::fs::Permissions::from_mode(0o644),
+    )?;
+
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    record_all(&repo, &changes, &txn, &channel, "")?;
+
+    // Recording the unchanged tree with the full mode finds nothing,
+    // since the recorded mode only has the executable bit.
+    let repo = repo.with_preserve_full_mode(true);
+    let mut state = crate::record::Builder::new();
+    state.record(
+        txn.clone(),
+        Algorithm::default(),
+        false,
+        &crate::DEFAULT_SEPARATOR,
+        channel.clone(),
+        &repo,
+        &changes,
+        "",
+        1,
+    )?;
+    assert!(state.finish().actions.is_empty());
+    Ok(())
+}
+
+#[test]
+fn snapshot_restore() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let repo = working_copy::filesystem::FileSystem::from_root(r.path());
+    repo.write_file("dir/file", Inode::ROOT)?
+        .write_all(b"a\nb\n")?;
+    let snapshot = repo.snapshot(&["dir/file", "new"])?;
+    assert_eq!(
+        snapshot.paths().collect::<Vec<_>>(),
+        vec!["dir/file", "new"]
+    );
+
+    repo.write_file("dir/file", Inode::ROOT)?
+        .write_all(b"x\n")?;
+    repo.write_file("new", Inode::ROOT)?.write_all(b"y\n")?;
+    repo.restore(snapshot)?;
+
+    let mut buf = Vec::new();
+    repo.read_file("dir/file", &mut buf)?;
+    assert_eq!(buf, b"a\nb\n");
+    assert!(!r.path().join("new").exists());
+
+    let repo = working_copy::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+    let snapshot = repo.snapshot(&["file"])?;
+    repo.write_file("file", Inode::ROOT)?.write_all(b"x\n")?;
+    repo.restore(snapshot)?;
+    let mut buf = Vec::new();
+    repo.read_file("file", &mut buf)?;
+    assert_eq!(buf, b"a\nb\n");
+    Ok(())
+}
+
+#[test]
+fn copy_changes() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx\nb\n")?;
+    let h1 = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let mem = changestore::memory::Memory::new();
+    changestore::copy_change(&changes, &mem, &h0)?;
+    assert_eq!(mem.iter_changes()?, vec![h0]);
+    assert_eq!(mem.get_change(&h0)?.hash()?, h0);
+
+    let mem = changestore::memory::Memory::new();
+    let mut copied = changestore::copy_all(&changes, &mem)?;
+    copied.sort();
+    let mut expected = vec![h0, h1];
+    expected.sort();
+    assert_eq!(copied, expected);
+    assert_eq!(
+        mem.get_header(&h1)?.message,
+        changes.get_header(&h1)?.message
+    );
+    Ok(())
+}
+
+#[test]
+fn change_bytes() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    let bytes = changes.read_change_bytes(&h0)?;
+    assert_eq!(bytes, std::fs::read(changes.filename(&h0))?);
+
+    // Proxy the change to another store, without parsing it.
+    let g = tempfile::tempdir()?;
+    let changes2 = changestore::filesystem::FileSystem::from_root(g.path(), MAX_FILES);
+    changes2.write_change_bytes(&h0, &bytes)?;
+    assert_eq!(changes2.get_change(&h0)?.hash()?, h0);
+
+    // The in-memory store goes through the parsed change.
+    let mem = changestore::memory::Memory::new();
+    mem.write_change_bytes(&h0, &bytes)?;
+    assert_eq!(mem.get_change(&h0)?.hash()?, h0);
+    let bytes = mem.read_change_bytes(&h0)?;
+    changes2.write_change_bytes(&h0, &bytes)?;
+
+    let mut h1 = h0;
+    if let Hash::Blake3(ref mut h) = h1 {
+        h[0] = h[0].wrapping_add(1)
+    }
+    assert!(mem.write_change_bytes(&h1, &bytes).is_err());
+    assert!(changes2.write_change_bytes(&h1, &bytes).is_err());
+    Ok(())
+}
+
+#[test]
+fn migrate_changestore_layout() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    use changestore::filesystem::LayoutVersion;
+
+    let repo = working_copy::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx\nb\n")?;
+    let h1 = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let changes_dir = f.path().join(DOT_DIR).join("changes");
+    assert_eq!(
+        changestore::filesystem::migrate_changestore_layout(
+            f.path(),
+            LayoutVersion::TwoCharPrefix,
+            LayoutVersion::Flat
+        )?,
+        2
+    );
+    for h in [h0, h1] {
+        assert!(!changes.has_change(&h));
+        assert!(changes_dir
+            .join(format!("{}.change", h.to_base32()))
+            .exists());
+    }
+
+    assert_eq!(
+        changestore::filesystem::migrate_changestore_layout(
+            f.path(),
+            LayoutVersion::Flat,
+            LayoutVersion::TwoCharPrefix
+        )?,
+        2
+    );
+    changes.get_header(&h1)?;
+    assert!(changestore::verify_all(&changes)?.is_empty());
+    let mut listed = changes.iter_changes()?;
+    listed.sort();
+    let mut expected = vec![h0, h1];
+    expected.sort();
+    assert_eq!(listed, expected);
+
+    // A file that doesn't match its name isn't moved.
+    let buf = std::fs::read(changes.filename(&h0))?;
+    std::fs::write(changes_dir.join(format!("{}.change", h1.to_base32())), &buf)?;
+    assert!(changestore::filesystem::migrate_changestore_layout(
+        f.path(),
+        LayoutVersion::Flat,
+        LayoutVersion::TwoCharPrefix
+    )
+    .is_err());
+    assert!(changestore::verify_change_file(&changes, &h1)?);
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn snapshot_symlinks() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let outside = tempfile::tempdir()?;
+    let repo = working_copy::filesystem::FileSystem::from_root(r.path());
+    repo.write_file("file", Inode::ROOT)?.write_all(b"a\n")?;
+    std::fs::write(outside.path().join("target"), b"outside\n")?;
+    std::os::unix::fs::symlink(outside.path().join("target"), r.path().join("link"))?;
+
+    let snapshot = repo.snapshot(&["file", "link"])?;
+    // Replace the file by a link to a file outside the repository,
+    // and the link by a regular file.
+    std::fs::remove_file(r.path().join("file"))?;
+    std::os::unix::fs::symlink(outside.path().join("target"), r.path().join("file"))?;
+    std::fs::remove_file(r.path().join("link"))?;
+    std::fs::write(r.path().join("link"), b"x\n")?;
+    repo.restore(snapshot)?;
+
+    assert_eq!(std::fs::read(outside.path().join("target"))?, b"outside\n");
+    let file = std::fs::symlink_metadata(r.path().join("file"))?;
+    assert!(file.file_type().is_file());
+    assert_eq!(std::fs::read(r.path().join("file"))?, b"a\n");
+    assert_eq!(
+        std::fs::read_link(r.path().join("link"))?,
+        outside.path().join("target")
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn symlink_round_trip() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let repo = working_copy::filesystem::FileSystem::from_root(r.path());
+    let f = tempfile::tempdir()?;
+    let changes = changestore::filesystem::FileSystem::from_root(f.path(), MAX_FILES);
+
+    repo.write_file("dir/file", Inode::ROOT)?
+        .write_all(b"a\nb\n")?;
+    std::os::unix::fs::symlink("file", r.path().join("dir/link"))?;
+    let meta = repo.file_metadata("dir/link")?;
+    assert!(meta.is_symlink());
+    let mut target = Vec::new();
+    repo.read_file("dir/link", &mut target)?;
+    assert_eq!(target, b"file");
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("dir/file", 0)?;
+    txn.write().add_file("dir/link", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    // Output the change into a fresh working copy.
+    let r2 = tempfile::tempdir()?;
+    let repo2 = working_copy::filesystem::FileSystem::from_root(r2.path());
+    let env2 = pristine::sanakirja::Pristine::new_anon()?;
+    let txn2 = env2.arc_txn_begin().unwrap();
+    let channel2 = txn2.write().open_or_create_channel("main")?;
+    apply::apply_change_arc(&changes, &txn2, &channel2, &h)?;
+    output::output_repository_no_pending(&repo2, &changes, &txn2, &channel2, "", true, None, 1, 0)
+        .unwrap();
+
+    let link = r2.path().join("dir/link");
+    assert!(std::fs::symlink_metadata(&link)?.file_type().is_symlink());
+    assert_eq!(std::fs::read_link(&link)?, std::path::Path::new("file"));
+    assert_eq!(std::fs::read(&link)?, b"a\nb\n");
+    assert!(repo2.file_metadata("dir/link")?.is_symlink());
+
+    // Nothing to record after outputting.
+    let mut state = crate::record::Builder::new();
+    state.record(
+        txn2.clone(),
+        Algorithm::default(),
+        false,
+        &crate::DEFAULT_SEPARATOR,
+        channel2.clone(),
+        &repo2,
+        &changes,
+        "",
+        1,
+    )?;
+    assert!(state.finish().actions.is_empty());
+    Ok(())
+}
+
+#[test]
+fn git_like_conflict_markers() -> Result<(), anyhow::Error> {
+    use crate::vertex_buffer::{has_unresolved_conflicts, ConflictMarkerStyle};
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let alice = txn.write().open_or_create_channel("alice")?;
+    txn.write().add_file("file", 0)?;
+    let h0 = record_all(&repo, &changes, &txn, &alice, "")?;
+
+    let repo_bob = working_copy::memory::Memory::new();
+    let bob = txn.write().open_or_create_channel("bob")?;
+    apply::apply_change_arc(&changes, &txn, &bob, &h0)?;
+    output::output_repository_no_pending(&repo_bob, &changes, &txn, &bob, "", true, None, 1, 0)?;
+    repo_bob
+        .write_file("file", Inode::ROOT)?
+        .write_all(b"a\nu\nb\n")?;
+    let bob_h = record_all(&repo_bob, &changes, &txn, &bob, "")?;
+    repo.write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx\nb\n")?;
+    let alice_h = record_all(&repo, &changes, &txn, &alice, "")?;
+
+    // Output both sides to a working copy using Git's markers.
+    let r = tempfile::tempdir()?;
+    let fs = working_copy::filesystem::FileSystem::from_root(r.path())
+        .with_conflict_marker_style(ConflictMarkerStyle::GitLike);
+    let env2 = pristine::sanakirja::Pristine::new_anon()?;
+    let txn2 = env2.arc_txn_begin().unwrap();
+    let channel2 = txn2.write().open_or_create_channel("main")?;
+    for h in [h0, alice_h, bob_h] {
+        apply::apply_change_arc(&changes, &txn2, &channel2, &h)?;
+    }
+    let conflicts =
+        output::output_repository_no_pending(&fs, &changes, &txn2, &channel2, "", true, None, 1, 0)
+            .unwrap();
+    assert_eq!(conflicts.len(), 1);
+
+    let contents = String::from_utf8(std::fs::read(r.path().join("file"))?)?;
+    debug!("contents = {:?}", contents);
+    let lines: Vec<_> = contents.lines().collect();
+    assert!(lines[1].starts_with("<<<<<<< "));
+    assert!(lines.iter().any(|l| l.starts_with("======= ")));
+    assert!(lines[lines.len() - 2].starts_with(">>>>>>> "));
+    assert_eq!(lines.last(), Some(&"b"));
+    assert!(has_unresolved_conflicts(contents.as_bytes()));
+
+    // The Git-like markers are recognised as the conflict when
+    // recording, so there is nothing to record.
+    let mut state = crate::record::Builder::new();
+    state.record(
+        txn2.clone(),
+        Algorithm::default(),
+        false,
+        &crate::DEFAULT_SEPARATOR,
+        channel2.clone(),
+        &fs,
+        &changes,
+        "",
+        1,
+    )?;
+    assert!(state.finish().actions.is_empty());
+    Ok(())
+}
+
+#[test]
+fn encoding_hints() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let r = tempfile::tempdir()?;
+    let contents = b"\xe1\xe2\xe3 \xe4\xe5\n";
+    std::fs::create_dir_all(r.path().join("greek"))?;
+    std::fs::write(r.path().join("greek/a.txt"), contents)?;
+    std::fs::write(r.path().join("b.txt"), contents)?;
+    std::fs::write(r.path().join("c.utf8"), contents)?;
+
+    let plain = working_copy::filesystem::FileSystem::from_root(r.path());
+    let hinted = working_copy::filesystem::FileSystem::from_root(r.path()).with_encoding_hints(
+        [("greek/", "iso-8859-7"), ("*.utf8", "utf-8")]
+            .iter()
+            .cloned(),
+    )?;
+
+    let mut buf = Vec::new();
+    assert_eq!(
+        hinted.decode_file("greek/a.txt", &mut buf)?,
+        Some(Encoding(encoding_rs::ISO_8859_7))
+    );
+    assert_eq!(buf, contents);
+    // Files outside the patterns, and files that aren't valid in the
+    // encoding of their pattern, are detected as without hints.
+    for file in ["b.txt", "c.utf8"] {
+        assert_eq!(
+            hinted.decode_file(file, &mut Vec::new())?,
+            plain.decode_file(file, &mut Vec::new())?
+        );
+    }
+
+    assert!(working_copy::filesystem::FileSystem::from_root(r.path())
+        .with_encoding_hints([("*.c", "no-such-encoding")].iter().cloned())
+        .is_err());
+    Ok(())
+}
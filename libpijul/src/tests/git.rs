@@ -0,0 +1,44 @@
+use crate::working_copy::git::GitTreeWorkingCopy;
+use crate::working_copy::WorkingCopyRead;
+
+/// Read files out of a Git tree.
+#[test]
+fn git_tree_working_copy() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let dir = tempfile::tempdir()?;
+    let repo = git2::Repository::init(dir.path())?;
+
+    let file = repo.blob(b"a\nb\n")?;
+    let script = repo.blob(b"#!/bin/sh\n")?;
+    let mut sub = repo.treebuilder(None)?;
+    sub.insert("script", script, 0o100755)?;
+    let sub = sub.write()?;
+    let mut root = repo.treebuilder(None)?;
+    root.insert("file", file, 0o100644)?;
+    root.insert("dir", sub, 0o040000)?;
+    let tree = repo.find_tree(root.write()?)?;
+
+    let mut modified = std::collections::HashSet::new();
+    modified.insert("file".to_string());
+    let w = GitTreeWorkingCopy::new(&repo, tree).with_modified(modified);
+
+    let mut buf = Vec::new();
+    w.read_file("file", &mut buf)?;
+    assert_eq!(buf, b"a\nb\n");
+    buf.clear();
+    w.read_file("dir/script", &mut buf)?;
+    assert_eq!(buf, b"#!/bin/sh\n");
+
+    assert!(w.file_metadata("dir")?.is_dir());
+    assert!(!w.file_metadata("file")?.is_dir());
+    assert_eq!(w.file_metadata("file")?.permissions() & 0o100, 0);
+    assert_eq!(w.file_metadata("dir/script")?.permissions() & 0o100, 0o100);
+    assert!(w.read_file("missing", &mut buf).is_err());
+
+    assert!(w.modified_time("file")? > std::time::SystemTime::UNIX_EPOCH);
+    assert_eq!(
+        w.modified_time("dir/script")?,
+        std::time::SystemTime::UNIX_EPOCH
+    );
+    Ok(())
+}
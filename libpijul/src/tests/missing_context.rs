@@ -294,3 +294,82 @@ fn missing_context_newedges() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[test]
+fn apply_changes_batch() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo_alice = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo_alice.add_file("file", b"a\nb\nc\nd\ne\nf\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let alice = txn.write().open_or_create_channel("alice")?;
+    txn.write().add_file("file", 0)?;
+    let init_h = record_all(&repo_alice, &changes, &txn, &alice, "")?;
+
+    let repo_bob = working_copy::memory::Memory::new();
+    let bob = txn.write().open_or_create_channel("bob")?;
+    apply::apply_change_arc(&changes, &txn, &bob, &init_h)?;
+    output::output_repository_no_pending(&repo_bob, &changes, &txn, &bob, "", true, None, 1, 0)?;
+    repo_bob
+        .write_file("file", Inode::ROOT)?
+        .write_all(b"a\nb\nc\nx\nz\nd\ne\nf\n")?;
+    let bob_h = record_all(&repo_bob, &changes, &txn, &bob, "")?;
+    repo_bob
+        .write_file("file", Inode::ROOT)?
+        .write_all(b"a\nb\nc\nx\ny\nz\nd\ne\nf\n")?;
+    let bob_h2 = record_all(&repo_bob, &changes, &txn, &bob, "")?;
+
+    // Alice deletes the context of Bob's lines, creating zombies.
+    repo_alice
+        .write_file("file", Inode::ROOT)?
+        .write_all(b"a\nf\n")?;
+    let alice_h = record_all(&repo_alice, &changes, &txn, &alice, "")?;
+
+    let hashes = [init_h, alice_h, bob_h, bob_h2];
+    let one_by_one = txn.write().open_or_create_channel("one_by_one")?;
+    for h in hashes.iter() {
+        apply::apply_change_arc(&changes, &txn, &one_by_one, h)?;
+    }
+    let batch = txn.write().open_or_create_channel("batch")?;
+    let applied = apply::apply_changes(&changes, &mut *txn.write(), &mut *batch.write(), &hashes)?;
+    assert_eq!(applied.len(), hashes.len());
+    assert_eq!(
+        applied.last().map(|x| x.1),
+        Some(crate::pristine::current_state(
+            &*txn.read(),
+            &*one_by_one.read()
+        )?)
+    );
+
+    {
+        let txn = txn.read();
+        let graph = |c: &ChannelRef<_>| -> Vec<_> {
+            let c = c.read();
+            txn.iter_graph(txn.graph(&*c), None)
+                .unwrap()
+                .map(|x| {
+                    let (v, e) = x.unwrap();
+                    (*v, *e)
+                })
+                .collect()
+        };
+        assert_eq!(graph(&batch), graph(&one_by_one));
+    }
+    let (files, _) = output::output_read_only(&changes, &txn, &batch)?;
+    let (files_, _) = output::output_read_only(&changes, &txn, &one_by_one)?;
+    assert_eq!(files, files_);
+
+    // A missing dependency is an error.
+    let other = txn.write().open_or_create_channel("other")?;
+    assert!(apply::apply_changes(
+        &changes,
+        &mut *txn.write(),
+        &mut *other.write(),
+        &[init_h, bob_h2]
+    )
+    .is_err());
+    Ok(())
+}
@@ -7,16 +7,22 @@ use crate::*;
 use chrono::*;
 
 mod add_file;
+mod bundle;
 mod change;
+mod channel;
 mod clone;
 mod conflict;
 mod diff;
 mod file_conflicts;
 mod filesystem;
+#[cfg(feature = "git")]
+mod git;
 mod missing_context;
 mod partial;
 mod performance;
 mod rm_file;
+#[cfg(feature = "tarball")]
+mod tar;
 // mod rollback;
 mod text;
 // mod text_changes;
@@ -33,6 +39,24 @@ fn record_all_change<
     channel: &ChannelRef<T>,
     prefix: &str,
 ) -> Result<(Hash, Change), anyhow::Error>
+where
+    R::Error: Send + Sync + 'static,
+{
+    record_all_change_with_dependencies(repo, store, txn, channel, prefix, &[])
+}
+
+fn record_all_change_with_dependencies<
+    T: MutTxnT + Send + Sync + 'static,
+    R: WorkingCopy + Clone + Send + Sync + 'static,
+    P: ChangeStore + Clone + Send + 'static,
+>(
+    repo: &R,
+    store: &P,
+    txn: &ArcTxn<T>,
+    channel: &ChannelRef<T>,
+    prefix: &str,
+    extra_dependencies: &[Hash],
+) -> Result<(Hash, Change), anyhow::Error>
 where
     R::Error: Send + Sync + 'static,
 {
@@ -55,7 +79,7 @@ where
         .into_iter()
         .map(|rec| rec.globalize(&*txn.read()).unwrap())
         .collect();
-    let mut change0 = crate::change::Change::make_change(
+    let mut change0 = crate::change::Change::make_change_with_dependencies(
         &*txn.read(),
         &channel.clone(),
         changes,
@@ -70,8 +94,8 @@ where
             timestamp: Utc::now(),
         },
         Vec::new(),
-    )
-    .unwrap();
+        extra_dependencies,
+    )?;
     let hash = store.save_change(&mut change0, |_, _| Ok::<_, anyhow::Error>(()))?;
     if log_enabled!(log::Level::Debug) {
         change0
@@ -88,7 +112,7 @@ where
     Ok((hash, change0))
 }
 
-fn record_all<T: MutTxnT, R: WorkingCopy, P: ChangeStore>(
+pub(crate) fn record_all<T: MutTxnT, R: WorkingCopy, P: ChangeStore>(
     repo: &R,
     store: &P,
     txn: &ArcTxn<T>,
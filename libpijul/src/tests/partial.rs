@@ -113,3 +113,49 @@ fn clone_prefixes() -> Result<(), anyhow::Error> {
     txn2.open_or_create_channel("main2").unwrap();
     Ok(())
 }
+
+#[test]
+fn output_two_prefixes() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let contents = b"a\nb\nc\n";
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("a/b/c", contents.to_vec());
+    repo.add_file("d/e/f", contents.to_vec());
+    repo.add_file("g/h/i", contents.to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    let channel = txn.write().open_or_create_channel("main").unwrap();
+    txn.write().add_file("a/b/c", 0)?;
+    txn.write().add_file("d/e/f", 0)?;
+    txn.write().add_file("g/h/i", 0)?;
+    let h = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let repo2 = working_copy::memory::Memory::new();
+    let env2 = pristine::sanakirja::Pristine::new_anon()?;
+    let txn2 = env2.arc_txn_begin().unwrap();
+    let channel2 = txn2.write().open_or_create_channel("main").unwrap();
+    apply::apply_change_arc(&changes, &txn2, &channel2, &h)?;
+    let output = output::output_prefixes(
+        &repo2,
+        &changes,
+        &txn2,
+        &channel2,
+        &["a/b", "d/e/f", "x/y"],
+        true,
+        None,
+        1,
+        0,
+    )?;
+    assert_eq!(output.complete, vec!["a/b", "d/e/f"]);
+    assert_eq!(output.not_found, vec!["x/y"]);
+    assert!(!output.is_complete());
+    assert!(output.conflicts.is_empty());
+    let mut files = repo2.list_files();
+    files.sort();
+    assert_eq!(files, vec!["a", "a/b", "a/b/c", "d", "d/e", "d/e/f"]);
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::working_copy::tar::TarWorkingCopy;
+use crate::working_copy::WorkingCopyRead;
+
+/// Read files out of a tar archive.
+#[test]
+fn tar_working_copy() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |path: &str, mode: u32, contents: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(1_000_000);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents)
+    };
+    append("./file", 0o644, b"a\nb\n")?;
+    append("dir/script", 0o755, b"#!/bin/sh\n")?;
+    let archive = builder.into_inner()?;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("archive.tar.gz");
+    let mut gz = flate2::write::GzEncoder::new(
+        std::fs::File::create(&path)?,
+        flate2::Compression::default(),
+    );
+    std::io::Write::write_all(&mut gz, &archive)?;
+    gz.finish()?;
+
+    for w in [
+        TarWorkingCopy::from_reader(&archive[..])?,
+        TarWorkingCopy::open(&path)?,
+    ] {
+        assert_eq!(
+            w.paths().collect::<Vec<_>>(),
+            vec!["dir", "dir/script", "file"]
+        );
+        let mut buf = Vec::new();
+        w.read_file("file", &mut buf)?;
+        assert_eq!(buf, b"a\nb\n");
+        buf.clear();
+        w.read_file("dir/script", &mut buf)?;
+        assert_eq!(buf, b"#!/bin/sh\n");
+
+        assert!(w.file_metadata("dir")?.is_dir());
+        assert!(!w.file_metadata("file")?.is_dir());
+        assert_eq!(w.file_metadata("file")?.permissions() & 0o100, 0);
+        assert_eq!(w.file_metadata("dir/script")?.permissions() & 0o100, 0o100);
+        assert!(w.read_file("missing", &mut buf).is_err());
+
+        assert_eq!(
+            w.modified_time("file")?,
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000)
+        );
+    }
+    Ok(())
+}
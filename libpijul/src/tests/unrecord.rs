@@ -825,3 +825,46 @@ fn double_file() -> Result<(), anyhow::Error> {
     assert!(inodes.next().is_none());
     Ok(())
 }
+
+/// Apply two changes to a channel, then unapply the last one.
+#[test]
+fn unapply_change() -> Result<(), anyhow::Error> {
+    env_logger::try_init().unwrap_or(());
+
+    let repo = working_copy::memory::Memory::new();
+    let changes = changestore::memory::Memory::new();
+    repo.add_file("file", b"a\nb\n".to_vec());
+
+    let env = pristine::sanakirja::Pristine::new_anon()?;
+    let txn = env.arc_txn_begin().unwrap();
+    txn.write().add_file("file", 0)?;
+    let channel = txn.write().open_or_create_channel("main")?;
+    let h0 = record_all(&repo, &changes, &txn, &channel, "")?;
+    repo.write_file("file", Inode::ROOT)?
+        .write_all(b"a\nx\nb\n")?;
+    let h1 = record_all(&repo, &changes, &txn, &channel, "")?;
+
+    let other = txn.write().open_or_create_channel("other")?;
+    apply::apply_change_arc(&changes, &txn, &other, &h0)?;
+    let state0 = pristine::current_state(&*txn.read(), &*other.read())?;
+    let (files0, _) = output::output_read_only(&changes, &txn, &other)?;
+    apply::apply_change_arc(&changes, &txn, &other, &h1)?;
+
+    match crate::unapply_change(&changes, &mut *txn.write(), &mut *other.write(), &h0) {
+        Err(crate::unrecord::UnrecordError::ChangeIsDependedUpon { .. }) => {}
+        _ => panic!("Should not be able to unapply a change with dependents"),
+    }
+    crate::unapply_change(&changes, &mut *txn.write(), &mut *other.write(), &h1)?;
+
+    assert_eq!(
+        pristine::current_state(&*txn.read(), &*other.read())?,
+        state0
+    );
+    let (files, _) = output::output_read_only(&changes, &txn, &other)?;
+    assert_eq!(files, files0);
+    assert_eq!(files["file"], b"a\nb\n");
+    // The change is still applied on `main`.
+    let (files, _) = output::output_read_only(&changes, &txn, &channel)?;
+    assert_eq!(files["file"], b"a\nx\nb\n");
+    Ok(())
+}
@@ -14,6 +14,13 @@ impl Encoding {
         Encoding(encoding_rs::Encoding::for_label_no_replacement(label.as_bytes()).unwrap())
     }
 
+    /// The encoding called `label` (such as `"latin1"`), if it is one
+    /// of the labels of the [Encoding
+    /// Standard](https://encoding.spec.whatwg.org/#names-and-labels).
+    pub fn from_label(label: &str) -> Option<Encoding> {
+        encoding_rs::Encoding::for_label_no_replacement(label.as_bytes()).map(Encoding)
+    }
+
     pub(crate) fn label(&self) -> &str {
         self.0.name()
     }
@@ -21,6 +21,8 @@ pub enum UnrecordError<ChangestoreError: std::error::Error + 'static, T: GraphTx
     InconsistentChange(#[from] crate::pristine::InconsistentChange<T::GraphError>),
     #[error("Change not in channel: {}", hash.to_base32())]
     ChangeNotInChannel { hash: ChangeId },
+    #[error("Unknown change: {}", hash.to_base32())]
+    UnknownChange { hash: Hash },
     #[error("Change {} is depended upon by {}", change_id.to_base32(), dependent.to_base32())]
     ChangeIsDependedUpon {
         change_id: ChangeId,
@@ -45,6 +47,9 @@ impl<C: std::error::Error, T: GraphTxnT + TreeTxnT> std::fmt::Debug for Unrecord
             UnrecordError::ChangeNotInChannel { hash } => {
                 write!(fmt, "Change not in channel: {}", hash.to_base32())
             }
+            UnrecordError::UnknownChange { hash } => {
+                write!(fmt, "Unknown change: {}", hash.to_base32())
+            }
             UnrecordError::ChangeIsDependedUpon {
                 change_id,
                 dependent,
@@ -99,6 +104,32 @@ pub fn unrecord<T: MutTxnT, P: ChangeStore>(
     }
 }
 
+/// Reverse the effect of change `hash` on `channel`, and remove it
+/// from the log of `channel`. Fails with
+/// [`UnrecordError::ChangeIsDependedUpon`] if another change on
+/// `channel` depends on it.
+///
+/// Contrarily to [`MutTxnTExt::unrecord`](crate::MutTxnTExt::unrecord),
+/// this only needs the channel itself, and never removes the change
+/// from the pristine, even if no other channel contains it.
+pub fn unapply_change<T: MutTxnT, P: ChangeStore>(
+    changes: &P,
+    txn: &mut T,
+    channel: &mut T::Channel,
+    hash: &Hash,
+) -> Result<(), UnrecordError<P::Error, T>> {
+    let change_id = if let Some(&h) = txn.get_internal(&hash.into())? {
+        h
+    } else {
+        return Err(UnrecordError::UnknownChange { hash: *hash });
+    };
+    del_channel_changes::<T, P>(txn, channel, change_id)?;
+    let change = changes
+        .get_change(hash)
+        .map_err(UnrecordError::Changestore)?;
+    unapply(txn, channel, changes, change_id, &change, 0)
+}
+
 fn del_channel_changes<
     T: ChannelMutTxnT + DepsTxnT<DepsError = <T as GraphTxnT>::GraphError> + TreeTxnT,
     P: ChangeStore,
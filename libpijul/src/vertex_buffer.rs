@@ -6,6 +6,31 @@ pub const SEPARATOR: &str = "=======";
 
 pub const END_MARKER: &str = "<<<<<<<";
 
+/// The syntax of the conflict markers written in files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictMarkerStyle {
+    /// Conflicts start with [`START_MARKER`] and end with
+    /// [`END_MARKER`].
+    #[default]
+    Pijul,
+    /// Conflicts start with `<<<<<<<` and end with `>>>>>>>`, as in
+    /// Git, for tools expecting Git's markers.
+    GitLike,
+}
+
+impl ConflictMarkerStyle {
+    /// The marker written in this style for `marker`, one of
+    /// [`START_MARKER`], [`SEPARATOR`] and [`END_MARKER`].
+    pub fn marker(self, marker: &str) -> &str {
+        match self {
+            ConflictMarkerStyle::Pijul => marker,
+            ConflictMarkerStyle::GitLike if marker == START_MARKER => END_MARKER,
+            ConflictMarkerStyle::GitLike if marker == END_MARKER => START_MARKER,
+            ConflictMarkerStyle::GitLike => marker,
+        }
+    }
+}
+
 /// A trait for outputting keys and their contents. This trait allows
 /// to retain more information about conflicts than directly
 /// outputting as bytes to a `Write`. The diff algorithm uses that
@@ -65,6 +90,7 @@ pub(crate) struct ConflictsWriter<'a, 'b, W: std::io::Write> {
     pub inode_vertex: Position<ChangeId>,
     pub conflicts: &'a mut Vec<crate::output::Conflict>,
     pub buf: Vec<u8>,
+    pub style: ConflictMarkerStyle,
 }
 
 impl<'a, 'b, W: std::io::Write> ConflictsWriter<'a, 'b, W> {
@@ -82,8 +108,15 @@ impl<'a, 'b, W: std::io::Write> ConflictsWriter<'a, 'b, W> {
             path,
             conflicts,
             buf: Vec::new(),
+            style: ConflictMarkerStyle::default(),
         }
     }
+
+    /// Write conflict markers in `style`.
+    pub fn with_style(mut self, style: ConflictMarkerStyle) -> Self {
+        self.style = style;
+        self
+    }
 }
 
 impl<'a, 'b, W: std::io::Write> std::ops::Deref for ConflictsWriter<'a, 'b, W> {
@@ -126,6 +159,7 @@ impl<'a, 'b, W: std::io::Write> VertexBuffer for ConflictsWriter<'a, 'b, W> {
         sides: Option<(&C, &[&Hash])>,
     ) -> Result<(), std::io::Error> {
         debug!("output_conflict_marker {:?}", self.new_line);
+        let s = self.style.marker(s);
         if !self.new_line {
             self.lines += 2;
             self.w.write_all(b"\n")?;
@@ -237,6 +271,76 @@ pub fn change_message<C: ChangeStore>(changes: &C, hash: &Hash) -> String {
     }
 }
 
+/// The kind of conflict marker on `line`, with the id of its
+/// conflict, if `line` is a marker as written by the output.
+pub(crate) fn parse_conflict_marker(line: &[u8]) -> Option<(&'static str, &[u8])> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    for marker in [START_MARKER, SEPARATOR, END_MARKER] {
+        if let Some(rest) = line.strip_prefix(marker.as_bytes()) {
+            let rest = rest.strip_prefix(b" ")?;
+            let id_len = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+            let (id, rest) = rest.split_at(id_len);
+            if !id.is_empty() && (rest.is_empty() || rest.starts_with(b" ")) {
+                return Some((marker, id));
+            }
+        }
+    }
+    None
+}
+
+/// Whether `bytes` still contains conflict markers.
+pub fn has_unresolved_conflicts(bytes: &[u8]) -> bool {
+    bytes
+        .split_inclusive(|&c| c == b'\n')
+        .any(|line| parse_conflict_marker(line).is_some())
+}
+
+/// The byte ranges of the conflicts in `bytes`, from the start of
+/// their first marker to the end of their last one, in any
+/// [`ConflictMarkerStyle`]. Nested conflicts are included in the
+/// range of the outermost one. A separator that doesn't belong to a
+/// conflict is a region on its own, and a conflict that isn't closed
+/// extends to the end of `bytes`.
+pub fn list_conflict_regions(bytes: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut regions = Vec::new();
+    // The start of the outermost open conflict, and the ids of all
+    // open conflicts.
+    let mut start = 0;
+    let mut open: Vec<&[u8]> = Vec::new();
+    let mut pos = 0;
+    for line in bytes.split_inclusive(|&c| c == b'\n') {
+        let line_start = pos;
+        pos += line.len();
+        let (marker, id) = if let Some(m) = parse_conflict_marker(line) {
+            m
+        } else {
+            continue;
+        };
+        if marker == SEPARATOR {
+            if open.is_empty() {
+                regions.push(line_start..pos)
+            }
+        } else if let Some(i) = open.iter().rposition(|&o| o == id) {
+            // Since the start and end markers depend on the style,
+            // a marker closes the open conflict with the same id.
+            open.truncate(i);
+            if open.is_empty() {
+                regions.push(start..pos)
+            }
+        } else {
+            if open.is_empty() {
+                start = line_start
+            }
+            open.push(id);
+        }
+    }
+    if !open.is_empty() {
+        regions.push(start..bytes.len())
+    }
+    regions
+}
+
 pub struct Writer<W: std::io::Write> {
     w: W,
     buf: Vec<u8>,
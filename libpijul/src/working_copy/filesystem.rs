@@ -8,6 +8,9 @@ use std::path::{Path, PathBuf};
 #[derive(Clone)]
 pub struct FileSystem {
     root: PathBuf,
+    preserve_full_mode: bool,
+    conflict_marker_style: crate::vertex_buffer::ConflictMarkerStyle,
+    encoding_hints: std::sync::Arc<Vec<(ignore::gitignore::Gitignore, Encoding)>>,
 }
 
 /// Returns whether `path` is a child of `root_` (or `root_` itself).
@@ -151,9 +154,59 @@ impl FileSystem {
     pub fn from_root<P: AsRef<Path>>(root: P) -> Self {
         FileSystem {
             root: root.as_ref().to_path_buf(),
+            preserve_full_mode: false,
+            conflict_marker_style: Default::default(),
+            encoding_hints: Default::default(),
         }
     }
 
+    /// Record and output all the permission bits of files (such as
+    /// 0o640), instead of just the executable bit. Modes recorded
+    /// without this option (i.e. without the owner read bit) are still
+    /// output as before.
+    pub fn with_preserve_full_mode(mut self, preserve_full_mode: bool) -> Self {
+        self.preserve_full_mode = preserve_full_mode;
+        self
+    }
+
+    /// Write conflict markers in `style`. Default:
+    /// [`ConflictMarkerStyle::Pijul`](crate::vertex_buffer::ConflictMarkerStyle::Pijul).
+    pub fn with_conflict_marker_style(
+        mut self,
+        style: crate::vertex_buffer::ConflictMarkerStyle,
+    ) -> Self {
+        self.conflict_marker_style = style;
+        self
+    }
+
+    /// Give the encodings of files matching gitignore-style patterns,
+    /// as `(pattern, encoding label)` pairs. When several patterns
+    /// match a file, the first one wins. Files that aren't valid in
+    /// the encoding of their pattern have their encoding detected as
+    /// usual.
+    pub fn with_encoding_hints<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(
+        mut self,
+        hints: I,
+    ) -> Result<Self, std::io::Error> {
+        let mut encoding_hints = Vec::new();
+        for (pattern, label) in hints {
+            let encoding = Encoding::from_label(label).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Unknown encoding {:?} for {:?}", label, pattern),
+                )
+            })?;
+            let mut builder = ignore::gitignore::GitignoreBuilder::new(&self.root);
+            builder
+                .add_line(None, pattern)
+                .and_then(|b| b.build())
+                .map(|g| encoding_hints.push((g, encoding)))
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        }
+        self.encoding_hints = std::sync::Arc::new(encoding_hints);
+        Ok(self)
+    }
+
     pub fn record_prefixes<
         T: crate::MutTxnTExt + crate::TxnTExt + Send + Sync + 'static,
         C: crate::changestore::ChangeStore + Clone + Send + 'static,
@@ -359,29 +412,82 @@ impl FileSystem {
         path.extend(crate::path::components(file));
         path
     }
+
+    /// Same as [`WorkingCopyRead::read_file`], but takes a path
+    /// relative to the root. Fails with an `InvalidData` error
+    /// wrapping [`InvalidPath`] if `file` isn't valid UTF-8 or isn't
+    /// relative.
+    pub fn read_file_os(&self, file: &Path, buffer: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        self.read_file(&path_to_utf8(file)?, buffer)
+    }
+
+    /// Same as [`WorkingCopyRead::file_metadata`], but takes a path
+    /// relative to the root. Fails with an `InvalidData` error
+    /// wrapping [`InvalidPath`] if `file` isn't valid UTF-8 or isn't
+    /// relative.
+    pub fn file_metadata_os(&self, file: &Path) -> Result<InodeMetadata, std::io::Error> {
+        self.file_metadata(&path_to_utf8(file)?)
+    }
 }
 
 impl WorkingCopyRead for FileSystem {
     type Error = std::io::Error;
     fn file_metadata(&self, file: &str) -> Result<InodeMetadata, Self::Error> {
         debug!("metadata {:?}", file);
-        let attr = std::fs::metadata(&self.path(file))?;
+        let attr = std::fs::symlink_metadata(&self.path(file))?;
+        if attr.file_type().is_symlink() {
+            let mut meta = InodeMetadata::new(0, false);
+            meta.set_symlink();
+            return Ok(meta);
+        }
         let permissions = permissions(&attr).unwrap_or(0o700);
         debug!("permissions = {:?}", permissions);
-        Ok(InodeMetadata::new(permissions & 0o100, attr.is_dir()))
+        let mask = if self.preserve_full_mode {
+            0o777
+        } else {
+            0o100
+        };
+        Ok(InodeMetadata::new(permissions & mask, attr.is_dir()))
     }
     fn read_file(&self, file: &str, buffer: &mut Vec<u8>) -> Result<(), Self::Error> {
         use std::io::Read;
         debug!("read_file {:?}", file);
-        let mut f = std::fs::File::open(&self.path(file))?;
+        let path = self.path(file);
+        if std::fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            let target = target.to_str().ok_or_else(|| NonUtf8Path {
+                path: target.clone(),
+            })?;
+            buffer.extend(target.as_bytes());
+            return Ok(());
+        }
+        let mut f = std::fs::File::open(&path)?;
         f.read_to_end(buffer)?;
         Ok(())
     }
 
+    fn encoding_hint(&self, file: &str) -> Option<Encoding> {
+        let path = self.path(file);
+        self.encoding_hints.iter().find_map(|(pattern, encoding)| {
+            if pattern
+                .matched_path_or_any_parents(&path, false)
+                .is_ignore()
+            {
+                Some(encoding.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn conflict_marker_style(&self) -> crate::vertex_buffer::ConflictMarkerStyle {
+        self.conflict_marker_style
+    }
+
     #[cfg(not(unix))]
     fn modified_time(&self, file: &str) -> Result<std::time::SystemTime, Self::Error> {
         debug!("modified_time {:?}", file);
-        let attr = std::fs::metadata(&self.path(file))?;
+        let attr = std::fs::symlink_metadata(&self.path(file))?;
         Ok(attr.modified()?)
     }
 
@@ -389,7 +495,7 @@ impl WorkingCopyRead for FileSystem {
     fn modified_time(&self, file: &str) -> Result<std::time::SystemTime, Self::Error> {
         debug!("modified_time {:?}", file);
         use std::os::unix::fs::MetadataExt;
-        let attr = std::fs::metadata(&self.path(file))?;
+        let attr = std::fs::symlink_metadata(&self.path(file))?;
         let ctime = std::time::SystemTime::UNIX_EPOCH
             + std::time::Duration::from_millis(
                 attr.ctime() as u64 * 1000 + attr.ctime_nsec() as u64 / 1_000_000,
@@ -399,6 +505,9 @@ impl WorkingCopyRead for FileSystem {
 }
 
 impl WorkingCopy for FileSystem {
+    fn is_not_found(&self, err: &Self::Error) -> bool {
+        err.kind() == std::io::ErrorKind::NotFound
+    }
     fn create_dir_all(&self, file: &str) -> Result<(), Self::Error> {
         debug!("create_dir_all {:?}", file);
         Ok(std::fs::create_dir_all(&self.path(file))?)
@@ -407,7 +516,7 @@ impl WorkingCopy for FileSystem {
     fn remove_path(&self, path: &str, rec: bool) -> Result<(), Self::Error> {
         debug!("remove_path {:?}", path);
         let path = self.path(path);
-        if let Ok(meta) = std::fs::metadata(&path) {
+        if let Ok(meta) = std::fs::symlink_metadata(&path) {
             if let Err(e) = if meta.is_dir() {
                 if rec {
                     std::fs::remove_dir_all(&path)
@@ -437,13 +546,19 @@ impl WorkingCopy for FileSystem {
         use std::os::unix::fs::PermissionsExt;
         let name = self.path(name);
         debug!("set_permissions: {:?}", name);
-        let metadata = std::fs::metadata(&name)?;
+        let metadata = std::fs::symlink_metadata(&name)?;
+        if metadata.file_type().is_symlink() {
+            // Symbolic links don't have permissions of their own.
+            return Ok(());
+        }
         let mut current = metadata.permissions();
         debug!(
             "setting mode for {:?} to {:?} (currently {:?})",
             name, permissions, current
         );
-        if permissions & 0o100 != 0 {
+        if self.preserve_full_mode && permissions & 0o400 != 0 {
+            current.set_mode((current.mode() & !0o777) | (permissions as u32 & 0o777));
+        } else if permissions & 0o100 != 0 {
             current.set_mode(current.mode() | 0o100);
         } else {
             current.set_mode(current.mode() & ((!0o777) | 0o666));
@@ -457,6 +572,21 @@ impl WorkingCopy for FileSystem {
     fn set_permissions(&self, _name: &str, _permissions: u16) -> Result<(), Self::Error> {
         Ok(())
     }
+    #[cfg(unix)]
+    fn available_space(&self) -> Result<Option<u64>, Self::Error> {
+        use std::os::unix::ffi::OsStrExt;
+        let root = std::ffi::CString::new(self.root.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(root.as_ptr(), &mut stat) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        debug!(
+            "available_space: {:?} {:?} {:?}",
+            self.root, stat.f_bavail, stat.f_frsize
+        );
+        Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+    }
 
     type Writer = std::io::BufWriter<std::fs::File>;
     fn write_file(&self, file: &str, _: Inode) -> Result<Self::Writer, Self::Error> {
@@ -471,6 +601,104 @@ impl WorkingCopy for FileSystem {
         debug!("file");
         Ok(file)
     }
+
+    fn write_symlink(&self, link: &str, target: &str) -> Result<(), Self::Error> {
+        let path = self.path(link);
+        debug!("write_symlink {:?} -> {:?}", path, target);
+        if let Some(p) = path.parent() {
+            std::fs::create_dir_all(p)?
+        }
+        if let Ok(meta) = std::fs::symlink_metadata(&path) {
+            if meta.is_dir() {
+                std::fs::remove_dir_all(&path)?
+            } else {
+                std::fs::remove_file(&path)?
+            }
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &path)?;
+        #[cfg(windows)]
+        if let Err(e) = std::os::windows::fs::symlink_file(target, &path) {
+            // Creating symbolic links needs extra privileges on
+            // Windows, write the target to a regular file instead.
+            warn!("could not create symbolic link {:?}: {:?}", path, e);
+            std::fs::write(&path, target)?
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self, paths: &[&str]) -> Result<Snapshot, Self::Error> {
+        let tmp = tempfile::tempdir()?;
+        let mut entries = Vec::with_capacity(paths.len());
+        for (i, path) in paths.iter().enumerate() {
+            let entry = match self.file_metadata(path) {
+                Ok(meta) if meta.is_dir() => SnapshotEntry::Directory { meta },
+                // Save links as links: copying them would copy their
+                // target instead.
+                Ok(meta) if meta.is_symlink() => {
+                    let mut target = Vec::new();
+                    self.read_file(path, &mut target)?;
+                    SnapshotEntry::Symlink {
+                        target: String::from_utf8(target)
+                            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+                    }
+                }
+                Ok(meta) => {
+                    let copy = tmp.path().join(i.to_string());
+                    debug!("snapshot {:?} to {:?}", path, copy);
+                    std::fs::copy(&self.path(path), &copy)?;
+                    SnapshotEntry::Copy { meta, copy }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => SnapshotEntry::Missing,
+                Err(e) => return Err(e),
+            };
+            entries.push((path.to_string(), entry))
+        }
+        Ok(Snapshot {
+            entries,
+            tmp: Some(tmp),
+        })
+    }
+
+    fn restore(&self, snapshot: Snapshot) -> Result<(), Self::Error> {
+        for (path, entry) in snapshot.entries.iter() {
+            debug!("restore {:?}", path);
+            match entry {
+                SnapshotEntry::Missing => self.remove_path(path, true)?,
+                SnapshotEntry::Directory { meta } => {
+                    self.create_dir_all(path)?;
+                    self.set_permissions(path, meta.permissions())?
+                }
+                SnapshotEntry::File { meta, contents } => {
+                    use std::io::Write;
+                    // Don't write through a link that replaced the file.
+                    self.remove_path(path, true)?;
+                    let mut w = self.write_file(path, Inode::ROOT)?;
+                    w.write_all(contents)?;
+                    w.flush()?;
+                    self.set_permissions(path, meta.permissions())?
+                }
+                SnapshotEntry::Symlink { target } => self.write_symlink(path, target)?,
+                SnapshotEntry::Copy { copy, .. } => {
+                    let path = self.path(path);
+                    // Remove whatever is there now, in particular
+                    // links, which `copy` would follow.
+                    if let Ok(meta) = std::fs::symlink_metadata(&path) {
+                        if meta.is_dir() {
+                            std::fs::remove_dir_all(&path)?
+                        } else {
+                            std::fs::remove_file(&path)?
+                        }
+                    } else if let Some(p) = path.parent() {
+                        std::fs::create_dir_all(p)?
+                    }
+                    // `copy` also copies the permissions.
+                    std::fs::copy(copy, &path)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(not(windows))]
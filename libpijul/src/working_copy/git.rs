@@ -0,0 +1,78 @@
+//! A read-only working copy over a Git tree, for importing Git
+//! commits into Pijul.
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::WorkingCopyRead;
+use crate::pristine::InodeMetadata;
+
+/// The files of a Git tree, read from the object database of a Git
+/// repository.
+#[derive(Clone)]
+pub struct GitTreeWorkingCopy<'a> {
+    repo: &'a git2::Repository,
+    tree: git2::Tree<'a>,
+    modified: HashSet<String>,
+}
+
+impl<'a> GitTreeWorkingCopy<'a> {
+    /// A working copy over `tree`, whose objects are in `repo`.
+    pub fn new(repo: &'a git2::Repository, tree: git2::Tree<'a>) -> Self {
+        GitTreeWorkingCopy {
+            repo,
+            tree,
+            modified: HashSet::new(),
+        }
+    }
+
+    /// A working copy over the tree of `commit`.
+    pub fn from_commit(
+        repo: &'a git2::Repository,
+        commit: &git2::Commit<'a>,
+    ) -> Result<Self, git2::Error> {
+        Ok(Self::new(repo, commit.tree()?))
+    }
+
+    /// Git trees don't have modification times: by default, all
+    /// paths are reported as last modified at the Unix epoch, and
+    /// hence ignored by the recording algorithm. This marks
+    /// `modified` (for example the paths changed since the parent
+    /// commit) as modified now.
+    pub fn with_modified(mut self, modified: HashSet<String>) -> Self {
+        self.modified = modified;
+        self
+    }
+}
+
+impl<'a> WorkingCopyRead for GitTreeWorkingCopy<'a> {
+    type Error = git2::Error;
+
+    fn file_metadata(&self, file: &str) -> Result<InodeMetadata, Self::Error> {
+        debug!("metadata {:?}", file);
+        let entry = self.tree.get_path(Path::new(file))?;
+        if entry.kind() == Some(git2::ObjectType::Tree) {
+            Ok(InodeMetadata::new(0o100, true))
+        } else {
+            let permissions = entry.filemode();
+            debug!("permissions = {:o}", permissions);
+            Ok(InodeMetadata::new(permissions as usize & 0o100, false))
+        }
+    }
+
+    fn read_file(&self, file: &str, buffer: &mut Vec<u8>) -> Result<(), Self::Error> {
+        debug!("read file {:?}", file);
+        let entry = self.tree.get_path(Path::new(file))?;
+        if let Ok(b) = entry.to_object(self.repo)?.peel_to_blob() {
+            buffer.extend(b.content());
+        }
+        Ok(())
+    }
+
+    fn modified_time(&self, file: &str) -> Result<std::time::SystemTime, Self::Error> {
+        if self.modified.contains(file) {
+            Ok(std::time::SystemTime::now())
+        } else {
+            Ok(std::time::SystemTime::UNIX_EPOCH)
+        }
+    }
+}
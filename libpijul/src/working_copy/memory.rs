@@ -211,6 +211,8 @@ impl Memory_ {
 pub enum Error {
     #[error("Path not found: {path}")]
     NotFound { path: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
 
 impl WorkingCopyRead for Memory {
@@ -249,6 +251,9 @@ impl WorkingCopyRead for Memory {
 }
 
 impl WorkingCopy for Memory {
+    fn is_not_found(&self, err: &Self::Error) -> bool {
+        matches!(err, Error::NotFound { .. })
+    }
     fn create_dir_all(&self, file: &str) -> Result<(), Self::Error> {
         let not_already_exists = {
             let m = self.0.lock();
@@ -330,6 +335,23 @@ impl WorkingCopy for Memory {
         );
         Ok(Writer { w: contents })
     }
+
+    fn snapshot(&self, paths: &[&str]) -> Result<Snapshot, Self::Error> {
+        let m = self.0.lock();
+        let mut snapshot = Snapshot::default();
+        for path in paths {
+            let entry = match m.get_file(path) {
+                Some(Inode::File { meta, contents, .. }) => SnapshotEntry::File {
+                    meta: *meta,
+                    contents: contents.lock().clone(),
+                },
+                Some(Inode::Directory { meta, .. }) => SnapshotEntry::Directory { meta: *meta },
+                None => SnapshotEntry::Missing,
+            };
+            snapshot.entries.push((path.to_string(), entry))
+        }
+        Ok(snapshot)
+    }
 }
 
 pub struct Writer {
@@ -1,4 +1,5 @@
 use chardetng::EncodingDetector;
+use std::borrow::Cow;
 
 use crate::pristine::{Inode, InodeMetadata};
 use crate::text_encoding::Encoding;
@@ -11,11 +12,29 @@ pub use filesystem::FileSystem;
 pub mod memory;
 pub use memory::Memory;
 
+#[cfg(feature = "git")]
+pub mod git;
+
+#[cfg(feature = "tarball")]
+pub mod tar;
+#[cfg(feature = "tarball")]
+pub use self::tar::TarWorkingCopy;
+
 pub trait WorkingCopyRead {
     type Error: std::error::Error + Send;
     fn file_metadata(&self, file: &str) -> Result<InodeMetadata, Self::Error>;
     fn read_file(&self, file: &str, buffer: &mut Vec<u8>) -> Result<(), Self::Error>;
     fn modified_time(&self, file: &str) -> Result<std::time::SystemTime, Self::Error>;
+    /// The encoding `file` is known to have, tried before detecting
+    /// its encoding in [`WorkingCopyRead::decode_file`].
+    fn encoding_hint(&self, _file: &str) -> Option<Encoding> {
+        None
+    }
+    /// The syntax of the conflict markers in this working copy, used
+    /// both when writing conflicts and when recording them.
+    fn conflict_marker_style(&self) -> crate::vertex_buffer::ConflictMarkerStyle {
+        crate::vertex_buffer::ConflictMarkerStyle::default()
+    }
     /// Read the file into the buffer
     ///
     /// Returns the file's text encoding or None if it was a binary file
@@ -28,7 +47,10 @@ pub trait WorkingCopyRead {
         self.read_file(&file, buffer)?;
         let mut detector = EncodingDetector::new();
         detector.feed(&buffer[init..], true);
-        if let Some(e) = crate::get_valid_encoding(&detector, None, true, &buffer[init..]) {
+        let hint = self.encoding_hint(file);
+        if let Some(e) =
+            crate::get_valid_encoding(&detector, None, true, &buffer[init..], hint.as_ref())
+        {
             Ok(Some(Encoding(e)))
         } else {
             Ok(None)
@@ -36,7 +58,7 @@ pub trait WorkingCopyRead {
     }
 }
 
-pub trait WorkingCopy: WorkingCopyRead {
+pub trait WorkingCopy: WorkingCopyRead<Error: From<std::io::Error>> {
     fn is_writable(&self, _path: &str) -> Result<bool, Self::Error> {
         Ok(true)
     }
@@ -44,9 +66,201 @@ pub trait WorkingCopy: WorkingCopyRead {
     fn remove_path(&self, name: &str, rec: bool) -> Result<(), Self::Error>;
     fn rename(&self, former: &str, new: &str) -> Result<(), Self::Error>;
     fn set_permissions(&self, name: &str, permissions: u16) -> Result<(), Self::Error>;
-
+    /// Number of bytes available for writing to this working copy,
+    /// or `None` if this is unknown.
+    fn available_space(&self) -> Result<Option<u64>, Self::Error> {
+        Ok(None)
+    }
     type Writer: std::io::Write;
     fn write_file(&self, file: &str, inode: Inode) -> Result<Self::Writer, Self::Error>;
+
+    /// Create a symbolic link at `link`, pointing to `target`.
+    ///
+    /// The default implementation is for working copies without
+    /// symbolic links, and writes a regular file containing `target`
+    /// instead.
+    fn write_symlink(&self, link: &str, target: &str) -> Result<(), Self::Error> {
+        use std::io::Write;
+        warn!(
+            "symbolic links are not supported, writing {:?} as a file",
+            link
+        );
+        let mut w = self.write_file(link, Inode::ROOT)?;
+        w.write_all(target.as_bytes())?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Whether `err`, returned by this working copy, means that the
+    /// path doesn't exist. The default implementation never does,
+    /// so that snapshots treat all errors as errors.
+    fn is_not_found(&self, _err: &Self::Error) -> bool {
+        false
+    }
+
+    /// Save the contents and permissions of `paths`, so that they can
+    /// be put back with [`WorkingCopy::restore`], for instance after
+    /// an experimental output. Paths that don't exist are recorded as
+    /// such, and removed when restoring. Directories are not
+    /// snapshotted recursively.
+    ///
+    /// The default implementation keeps a copy of the files in
+    /// memory.
+    fn snapshot(&self, paths: &[&str]) -> Result<Snapshot, Self::Error> {
+        let mut snapshot = Snapshot::default();
+        for path in paths {
+            let entry = match self.file_metadata(path) {
+                Ok(meta) if meta.is_dir() => SnapshotEntry::Directory { meta },
+                Ok(meta) if meta.is_symlink() => {
+                    let mut target = Vec::new();
+                    self.read_file(path, &mut target)?;
+                    SnapshotEntry::Symlink {
+                        target: String::from_utf8_lossy(&target).into_owned(),
+                    }
+                }
+                Ok(meta) => {
+                    let mut contents = Vec::new();
+                    self.read_file(path, &mut contents)?;
+                    SnapshotEntry::File { meta, contents }
+                }
+                Err(e) if self.is_not_found(&e) => SnapshotEntry::Missing,
+                Err(e) => return Err(e),
+            };
+            snapshot.entries.push((path.to_string(), entry))
+        }
+        Ok(snapshot)
+    }
+
+    /// Put back the files saved in `snapshot`.
+    fn restore(&self, snapshot: Snapshot) -> Result<(), Self::Error> {
+        use std::io::Write;
+        for (path, entry) in snapshot.entries.iter() {
+            let (meta, contents) = match entry {
+                SnapshotEntry::Missing => {
+                    self.remove_path(path, true)?;
+                    continue;
+                }
+                SnapshotEntry::Directory { meta } => {
+                    self.create_dir_all(path)?;
+                    self.set_permissions(path, meta.permissions())?;
+                    continue;
+                }
+                SnapshotEntry::Symlink { target } => {
+                    self.remove_path(path, true)?;
+                    self.write_symlink(path, target)?;
+                    continue;
+                }
+                SnapshotEntry::File { meta, contents } => (*meta, Cow::Borrowed(contents)),
+                #[cfg(feature = "ondisk-repos")]
+                SnapshotEntry::Copy { meta, copy } => (*meta, Cow::Owned(std::fs::read(copy)?)),
+            };
+            self.remove_path(path, true)?;
+            let mut w = self.write_file(path, Inode::ROOT)?;
+            w.write_all(&contents)?;
+            w.flush()?;
+            std::mem::drop(w);
+            self.set_permissions(path, meta.permissions())?
+        }
+        Ok(())
+    }
+}
+
+/// Files saved by [`WorkingCopy::snapshot`].
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    entries: Vec<(String, SnapshotEntry)>,
+    #[cfg(feature = "ondisk-repos")]
+    tmp: Option<tempfile::TempDir>,
+}
+
+impl Snapshot {
+    /// The paths saved in this snapshot.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(p, _)| p.as_str())
+    }
+}
+
+#[derive(Debug)]
+enum SnapshotEntry {
+    Missing,
+    Directory {
+        meta: InodeMetadata,
+    },
+    File {
+        meta: InodeMetadata,
+        contents: Vec<u8>,
+    },
+    /// A symbolic link, saved as a link rather than as its target.
+    Symlink {
+        target: String,
+    },
+    /// A copy of the file on disk, made by
+    /// [`FileSystem`](filesystem::FileSystem).
+    #[cfg(feature = "ondisk-repos")]
+    Copy {
+        meta: InodeMetadata,
+        copy: std::path::PathBuf,
+    },
+}
+
+/// Paths in the pristine are UTF-8 strings, this error is returned
+/// when converting a working copy path that isn't valid UTF-8.
+#[derive(Debug, Error)]
+#[error("Path is not valid UTF-8: {:?}", path)]
+pub struct NonUtf8Path {
+    pub path: std::path::PathBuf,
+}
+
+impl From<NonUtf8Path> for std::io::Error {
+    fn from(e: NonUtf8Path) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Errors converting a working copy path into a pristine path, see
+/// [`path_to_utf8`].
+#[derive(Debug, Error)]
+pub enum InvalidPath {
+    #[error(transparent)]
+    NonUtf8(#[from] NonUtf8Path),
+    #[error("Path is not relative to the repository root: {:?}", path)]
+    NotRelative { path: std::path::PathBuf },
+}
+
+impl From<InvalidPath> for std::io::Error {
+    fn from(e: InvalidPath) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Convert a relative path into a pristine path, i.e. with its
+/// components separated by `/`. `.` components are skipped, and
+/// paths going up (`..`) or starting at a root or prefix are
+/// rejected.
+pub fn path_to_utf8(path: &std::path::Path) -> Result<String, InvalidPath> {
+    use std::path::Component;
+    let mut result = String::new();
+    for c in path.components() {
+        match c {
+            Component::Normal(c) => {
+                if let Some(c) = c.to_str() {
+                    crate::path::push(&mut result, c)
+                } else {
+                    return Err(NonUtf8Path {
+                        path: path.to_path_buf(),
+                    }
+                    .into());
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(InvalidPath::NotRelative {
+                    path: path.to_path_buf(),
+                })
+            }
+        }
+    }
+    Ok(result)
 }
 
 #[derive(Clone)]
@@ -90,4 +304,17 @@ impl WorkingCopy for Sink {
     fn write_file(&self, _file: &str, _inode: Inode) -> Result<Self::Writer, Self::Error> {
         Ok(std::io::sink())
     }
+
+    fn snapshot(&self, _paths: &[&str]) -> Result<Snapshot, Self::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot snapshot a sink",
+        ))
+    }
+    fn restore(&self, _snapshot: Snapshot) -> Result<(), Self::Error> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Cannot restore a sink",
+        ))
+    }
 }
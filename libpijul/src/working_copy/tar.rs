@@ -0,0 +1,126 @@
+//! A read-only working copy over a tar archive, for recording the
+//! contents of a tarball without unpacking it.
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::{path_to_utf8, WorkingCopyRead};
+use crate::pristine::InodeMetadata;
+
+/// The files of a tar archive, indexed and kept in memory.
+#[derive(Clone, Default)]
+pub struct TarWorkingCopy {
+    entries: Arc<BTreeMap<String, TarEntry>>,
+}
+
+struct TarEntry {
+    meta: InodeMetadata,
+    mtime: SystemTime,
+    contents: Vec<u8>,
+}
+
+impl TarEntry {
+    /// A directory that is only implied by the paths of other entries.
+    fn implicit_dir() -> Self {
+        TarEntry {
+            meta: InodeMetadata::new(0o100, true),
+            mtime: SystemTime::UNIX_EPOCH,
+            contents: Vec::new(),
+        }
+    }
+}
+
+impl TarWorkingCopy {
+    /// Read the archive `r`. Directories don't need to have their own
+    /// entries in the archive. Symbolic links and other special
+    /// entries are skipped.
+    pub fn from_reader<R: Read>(r: R) -> Result<Self, std::io::Error> {
+        let mut entries = BTreeMap::new();
+        let mut archive = tar::Archive::new(r);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = path_to_utf8(&entry.path()?)?;
+            if path.is_empty() {
+                continue;
+            }
+            let header = entry.header();
+            let kind = header.entry_type();
+            let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(header.mtime().unwrap_or(0));
+            let meta = if kind.is_dir() {
+                InodeMetadata::new(0o100, true)
+            } else if kind.is_file() {
+                InodeMetadata::new(header.mode()? as usize & 0o100, false)
+            } else {
+                debug!("skipping {:?} ({:?})", path, kind);
+                continue;
+            };
+            let mut contents = Vec::new();
+            if !meta.is_dir() {
+                entry.read_to_end(&mut contents)?;
+            }
+            for (i, _) in path.match_indices('/') {
+                entries
+                    .entry(path[..i].to_string())
+                    .or_insert_with(TarEntry::implicit_dir);
+            }
+            entries.insert(
+                path,
+                TarEntry {
+                    meta,
+                    mtime,
+                    contents,
+                },
+            );
+        }
+        Ok(TarWorkingCopy {
+            entries: Arc::new(entries),
+        })
+    }
+
+    /// Read the archive at `path`, which may be compressed with gzip.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
+        let mut r = std::io::BufReader::new(std::fs::File::open(path)?);
+        let is_gzip = std::io::BufRead::fill_buf(&mut r)?.starts_with(&[0x1f, 0x8b]);
+        if is_gzip {
+            Self::from_reader(flate2::read::GzDecoder::new(r))
+        } else {
+            Self::from_reader(r)
+        }
+    }
+
+    /// The paths of all the files and directories of the archive,
+    /// each directory before its contents.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|p| p.as_str())
+    }
+
+    fn get(&self, file: &str) -> Result<&TarEntry, std::io::Error> {
+        self.entries.get(file).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{:?} not found in the archive", file),
+            )
+        })
+    }
+}
+
+impl WorkingCopyRead for TarWorkingCopy {
+    type Error = std::io::Error;
+
+    fn file_metadata(&self, file: &str) -> Result<InodeMetadata, Self::Error> {
+        debug!("metadata {:?}", file);
+        Ok(self.get(file)?.meta)
+    }
+
+    fn read_file(&self, file: &str, buffer: &mut Vec<u8>) -> Result<(), Self::Error> {
+        debug!("read file {:?}", file);
+        buffer.extend(&self.get(file)?.contents);
+        Ok(())
+    }
+
+    fn modified_time(&self, file: &str) -> Result<SystemTime, Self::Error> {
+        Ok(self.get(file)?.mtime)
+    }
+}
@@ -4,10 +4,13 @@ use std::path::PathBuf;
 
 use anyhow::bail;
 use dialoguer::theme;
-use log::debug;
+use indexmap::IndexMap;
+use libpijul::changestore::ChangeStore;
+use libpijul::{ChannelTxnT, Hash, TxnT};
+use log::{debug, warn};
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Global {
     pub author: Author,
     pub unrecord_changes: Option<usize>,
@@ -16,6 +19,10 @@ pub struct Global {
     pub pager: Option<Choice>,
     pub template: Option<Templates>,
     pub ignore_kinds: Option<HashMap<String, Vec<String>>>,
+    /// Store the passwords of identities in the system keyring, and
+    /// read them from there. Default: `true`. When `false`, the
+    /// keyring is never accessed and passwords are always prompted.
+    pub use_keyring: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -56,6 +63,18 @@ pub enum Choice {
     Never,
 }
 
+impl Choice {
+    /// Parse a choice from its name in the configuration file.
+    fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(Choice::Auto),
+            "always" => Some(Choice::Always),
+            "never" => Some(Choice::Never),
+            _ => None,
+        }
+    }
+}
+
 impl Default for Choice {
     fn default() -> Self {
         Self::Auto
@@ -85,44 +104,117 @@ pub fn global_config_dir() -> Option<PathBuf> {
 
 impl Global {
     pub fn load() -> Result<(Global, u64), anyhow::Error> {
-        if let Some(mut dir) = global_config_dir() {
-            dir.push("config.toml");
-            let (s, meta) = std::fs::read(&dir)
-                .and_then(|x| Ok((x, std::fs::metadata(&dir)?)))
-                .or_else(|e| {
-                    // Read from `$HOME/.config/pijul` dir
-                    if let Some(mut dir) = dirs_next::home_dir() {
-                        dir.push(".config");
-                        dir.push(CONFIG_DIR);
-                        dir.push("config.toml");
-                        std::fs::read(&dir).and_then(|x| Ok((x, std::fs::metadata(&dir)?)))
-                    } else {
-                        Err(e.into())
-                    }
-                })
-                .or_else(|e| {
-                    // Read from `$HOME/.pijulconfig`
-                    if let Some(mut dir) = dirs_next::home_dir() {
-                        dir.push(GLOBAL_CONFIG_DIR);
-                        std::fs::read(&dir).and_then(|x| Ok((x, std::fs::metadata(&dir)?)))
-                    } else {
-                        Err(e.into())
-                    }
-                })?;
+        Global::load_from(global_config_dir(), dirs_next::home_dir(), |name| {
+            std::env::var(name).ok()
+        })
+    }
+
+    /// Load `config.toml` from `config_dir`, falling back to the
+    /// former locations under `home`, and overlay the variables
+    /// returned by `var` as in [`Global::from_env_overlay`]. If none
+    /// of these files exists, start from the default configuration,
+    /// with timestamp 0.
+    fn load_from<V: Fn(&str) -> Option<String>>(
+        config_dir: Option<PathBuf>,
+        home: Option<PathBuf>,
+        var: V,
+    ) -> Result<(Global, u64), anyhow::Error> {
+        let mut paths = Vec::new();
+        if let Some(dir) = config_dir {
+            paths.push(dir.join("config.toml"))
+        }
+        if let Some(home) = home {
+            // `$HOME/.config/pijul/config.toml`, then `$HOME/.pijulconfig`.
+            paths.push(home.join(".config").join(CONFIG_DIR).join("config.toml"));
+            paths.push(home.join(GLOBAL_CONFIG_DIR));
+        }
+        for path in paths.iter() {
+            let s = match std::fs::read(path) {
+                Ok(s) => s,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
             debug!("s = {:?}", s);
             if let Ok(t) = toml::from_slice(&s) {
-                let ts = meta
+                let ts = std::fs::metadata(path)?
                     .modified()?
                     .duration_since(std::time::SystemTime::UNIX_EPOCH)
                     .unwrap()
                     .as_secs();
-                Ok((t, ts))
+                return Ok((Global::overlay(t, var), ts));
             } else {
-                bail!("Could not read configuration file at {:?}", dir)
+                bail!("Could not read configuration file at {:?}", path)
+            }
+        }
+        debug!("no global configuration file, using the defaults");
+        Ok((Global::overlay(Global::default(), var), 0))
+    }
+}
+
+impl Global {
+    /// Whether the system keyring should be used, see
+    /// [`Global::use_keyring`].
+    pub fn keyring_enabled(&self) -> bool {
+        self.use_keyring.unwrap_or(true)
+    }
+
+    /// Override the fields of `base` with the values of the following
+    /// environment variables, when they are set:
+    ///
+    /// - `PIJUL_AUTHOR_NAME`: `author.username`
+    /// - `PIJUL_AUTHOR_DISPLAY_NAME`: `author.display_name`
+    /// - `PIJUL_AUTHOR_EMAIL`: `author.email`
+    /// - `PIJUL_AUTHOR_ORIGIN`: `author.origin`
+    /// - `PIJUL_UNRECORD_CHANGES`: `unrecord_changes`
+    /// - `PIJUL_RESET_OVERWRITES_CHANGES`: `reset_overwrites_changes`
+    /// - `PIJUL_COLORS`: `colors`
+    /// - `PIJUL_PAGER`: `pager`
+    ///
+    /// The last four take the same values as in the configuration
+    /// file (a number, or one of `auto`, `always` and `never`).
+    /// Variables with invalid values are ignored. [`Global::load`]
+    /// applies this overlay to the file it reads, or to the default
+    /// configuration if there is no such file.
+    pub fn from_env_overlay(base: Global) -> Global {
+        Global::overlay(base, |name| std::env::var(name).ok())
+    }
+
+    /// Override the fields of `base` as in [`Global::from_env_overlay`],
+    /// reading the variables with `var`.
+    fn overlay<V: Fn(&str) -> Option<String>>(mut base: Global, var: V) -> Global {
+        if let Some(v) = var("PIJUL_AUTHOR_NAME") {
+            base.author.username = v
+        }
+        if let Some(v) = var("PIJUL_AUTHOR_DISPLAY_NAME") {
+            base.author.display_name = v
+        }
+        if let Some(v) = var("PIJUL_AUTHOR_EMAIL") {
+            base.author.email = v
+        }
+        if let Some(v) = var("PIJUL_AUTHOR_ORIGIN") {
+            base.author.origin = v
+        }
+        if let Some(v) = var("PIJUL_UNRECORD_CHANGES") {
+            match v.parse() {
+                Ok(n) => base.unrecord_changes = Some(n),
+                Err(_) => warn!("Ignoring invalid PIJUL_UNRECORD_CHANGES={:?}", v),
             }
-        } else {
-            bail!("Global configuration file missing")
         }
+        let choice = |name: &str, field: &mut Option<Choice>| {
+            if let Some(v) = var(name) {
+                match Choice::from_name(&v) {
+                    Some(c) => *field = Some(c),
+                    None => warn!("Ignoring invalid {}={:?}", name, v),
+                }
+            }
+        };
+        choice(
+            "PIJUL_RESET_OVERWRITES_CHANGES",
+            &mut base.reset_overwrites_changes,
+        );
+        choice("PIJUL_COLORS", &mut base.colors);
+        choice("PIJUL_PAGER", &mut base.pager);
+        base
     }
 }
 
@@ -139,6 +231,149 @@ pub struct Config {
     pub reset_overwrites_changes: Option<Choice>,
     pub colors: Option<Choice>,
     pub pager: Option<Choice>,
+    /// Record and restore all the permission bits of files, rather
+    /// than just the executable bit.
+    pub preserve_full_mode: Option<bool>,
+    /// Encodings of the files matching gitignore-style patterns, for
+    /// files whose encoding isn't detected reliably, for example
+    /// `"legacy/*.c" = "latin1"`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub file_encodings: HashMap<String, String>,
+    /// Initial size of the pristine database when it is opened, in
+    /// bytes or with a unit, for example `"64MiB"`. The database still
+    /// grows past that size when needed: this only saves remapping it
+    /// repeatedly in very large repositories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pristine_initial_size: Option<Size>,
+}
+
+/// The smallest allowed initial size of the pristine, in bytes.
+pub const MIN_PRISTINE_SIZE: u64 = 1 << 20;
+
+/// A size in bytes, either as a number of bytes or as a string with a
+/// unit, such as `"512KiB"`, `"64MB"` or `"2G"`. Units `K`, `M`, `G`
+/// and `T`, with or without an `iB` suffix, are powers of 1024, while
+/// `KB`, `MB`, `GB` and `TB` are powers of 1000.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Size {
+    Bytes(u64),
+    Human(String),
+}
+
+impl Size {
+    /// The number of bytes of this size.
+    pub fn bytes(&self) -> Result<u64, ConfigError> {
+        match self {
+            Size::Bytes(b) => Ok(*b),
+            Size::Human(s) => parse_size(s).ok_or_else(|| ConfigError::InvalidSize(s.clone())),
+        }
+    }
+}
+
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n: u64 = s[..digits].parse().ok()?;
+    let multiplier: u64 = match s[digits..].trim_start().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return None,
+    };
+    n.checked_mul(multiplier)
+}
+
+/// Errors found by [`Config::validate`].
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Several remotes are named {0:?}")]
+    DuplicateRemote(String),
+    #[error("The default remote {0:?} is neither a configured remote nor an address")]
+    UnknownDefaultRemote(String),
+    #[error("Invalid size {0:?}")]
+    InvalidSize(String),
+    #[error("The initial size of the pristine must be at least 1MiB, got {0} bytes")]
+    PristineSizeTooSmall(u64),
+}
+
+impl Config {
+    /// Check that remote names are unique, and that `default_remote`
+    /// is the name of one of them. `default_remote` may also be the
+    /// address of a remote (a URL, an SSH address or a path), which
+    /// isn't checked: addresses are recognised by containing a `:` or
+    /// a path separator. The initial size of the pristine, if any,
+    /// must be at least [`MIN_PRISTINE_SIZE`].
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut names = std::collections::HashSet::new();
+        for r in self.remotes.iter() {
+            if !names.insert(r.name()) {
+                return Err(ConfigError::DuplicateRemote(r.name().to_string()));
+            }
+        }
+        if let Some(ref def) = self.default_remote {
+            let is_address = def.contains([':', '/', '\\']);
+            if !is_address && !names.contains(def.as_str()) {
+                return Err(ConfigError::UnknownDefaultRemote(def.clone()));
+            }
+        }
+        self.pristine_initial_size()?;
+        Ok(())
+    }
+
+    /// The initial size of the pristine in bytes, if configured.
+    pub fn pristine_initial_size(&self) -> Result<Option<u64>, ConfigError> {
+        if let Some(ref size) = self.pristine_initial_size {
+            let size = size.bytes()?;
+            if size < MIN_PRISTINE_SIZE {
+                return Err(ConfigError::PristineSizeTooSmall(size));
+            }
+            Ok(Some(size))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Resolve the entries of `extra_dependencies` (full hashes or
+    /// unambiguous prefixes) to the changes they designate, dropping
+    /// duplicates. Each change must be on `channel` and in `store`.
+    /// If any entry fails to resolve, return why each of the failing
+    /// entries is invalid, in their original order.
+    pub fn validate_extra_dependencies<T: TxnT, P: ChangeStore>(
+        &self,
+        txn: &T,
+        channel: &T::Channel,
+        store: &P,
+    ) -> Result<Vec<Hash>, Vec<String>> {
+        let mut valid = Vec::new();
+        let mut invalid = Vec::new();
+        for h in self.extra_dependencies.iter() {
+            let (hash, c) = if let Ok(x) = txn.hash_from_prefix(h) {
+                x
+            } else {
+                invalid.push(format!("{:?} is not a known change", h));
+                continue;
+            };
+            if !matches!(txn.get_changeset(txn.changes(channel), &c), Ok(Some(_))) {
+                invalid.push(format!("{:?} is not on the channel", h))
+            } else if store.get_header(&hash).is_err() {
+                invalid.push(format!("{:?} is missing from the change store", h))
+            } else if !valid.contains(&hash) {
+                valid.push(hash)
+            }
+        }
+        if invalid.is_empty() {
+            Ok(valid)
+        } else {
+            Err(invalid)
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -151,8 +386,20 @@ pub enum RemoteConfig {
     Http {
         name: String,
         http: String,
+        /// Extra headers, in the order of the configuration file, so
+        /// that the last of several headers with the same name always
+        /// wins.
         #[serde(default)]
-        headers: HashMap<String, RemoteHttpHeader>,
+        headers: IndexMap<String, RemoteHttpHeader>,
+        /// Maximal download rate from this remote, in bytes per second.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_bytes_per_sec: Option<u64>,
+        /// SHA-256 fingerprint of the certificate this remote must
+        /// present, in hexadecimal (colons between bytes are allowed).
+        /// Connections to servers presenting another certificate are
+        /// rejected, whatever the certificate authorities say.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pinned_cert_sha256: Option<String>,
     },
 }
 
@@ -170,6 +417,35 @@ impl RemoteConfig {
 pub enum RemoteHttpHeader {
     String(String),
     Shell(Shell),
+    /// The value of an environment variable, read when the header is
+    /// sent.
+    Env {
+        env: String,
+    },
+}
+
+impl RemoteHttpHeader {
+    /// The value to send for this header, running its shell command
+    /// or reading its environment variable if needed.
+    pub fn resolve(&self) -> Result<String, anyhow::Error> {
+        self.resolve_with(|name| std::env::var(name))
+    }
+
+    /// Same as [`Self::resolve`], reading environment variables with
+    /// `var`.
+    fn resolve_with<V: Fn(&str) -> Result<String, std::env::VarError>>(
+        &self,
+        var: V,
+    ) -> Result<String, anyhow::Error> {
+        match self {
+            RemoteHttpHeader::String(s) => Ok(s.clone()),
+            RemoteHttpHeader::Shell(shell) => shell_cmd(&shell.shell),
+            RemoteHttpHeader::Env { env } => match var(env) {
+                Ok(v) => Ok(v),
+                Err(e) => bail!("Could not read environment variable {:?}: {}", env, e),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -181,6 +457,10 @@ pub struct Shell {
 pub struct Hooks {
     #[serde(default)]
     pub record: Vec<HookEntry>,
+    /// Hooks run before recording, whose standard output is appended
+    /// to the description of the change.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub description: Vec<HookEntry>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -190,12 +470,99 @@ pub struct HookEntry(toml::Value);
 struct RawHook {
     command: String,
     args: Vec<String>,
+    /// Kill the hook and fail if it runs for longer than this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+    /// Directory to run the hook in, relative to the root of the
+    /// repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<PathBuf>,
+}
+
+/// Run `cmd` with its standard output and error captured, killing it
+/// if it is still running after `timeout`.
+fn output_with_timeout(
+    mut cmd: std::process::Command,
+    name: &str,
+    timeout: std::time::Duration,
+) -> Result<std::process::Output, anyhow::Error> {
+    use std::io::Read;
+    use std::process::Stdio;
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read the pipes on other threads, so that the child doesn't
+    // block on a full pipe. The output is shared with these threads
+    // rather than returned by them, since processes started in the
+    // background by the hook may keep the pipes open after it exits.
+    let (done, finished) = std::sync::mpsc::channel();
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        let buf = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let shared = buf.clone();
+        let done = done.clone();
+        std::thread::spawn(move || {
+            if let Some(mut pipe) = pipe {
+                let mut chunk = [0; 4096];
+                loop {
+                    match pipe.read(&mut chunk) {
+                        Ok(0) => break,
+                        Ok(n) => shared.lock().unwrap().extend_from_slice(&chunk[..n]),
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                        Err(_) => break,
+                    }
+                }
+            }
+            done.send(()).unwrap_or(());
+        });
+        buf
+    };
+    let stdout = read(
+        child
+            .stdout
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+    let stderr = read(
+        child
+            .stderr
+            .take()
+            .map(|p| Box::new(p) as Box<dyn Read + Send>),
+    );
+    let start = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            bail!("Hook {:?} timed out after {:?}", name, timeout)
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    };
+    // Wait for both pipes to be closed, but no longer than the
+    // timeout, and keep whatever was read until then.
+    for _ in 0..2 {
+        let left = timeout.saturating_sub(start.elapsed());
+        if finished.recv_timeout(left).is_err() {
+            break;
+        }
+    }
+    let take =
+        |buf: std::sync::Arc<std::sync::Mutex<Vec<u8>>>| std::mem::take(&mut *buf.lock().unwrap());
+    Ok(std::process::Output {
+        status,
+        stdout: take(stdout),
+        stderr: take(stderr),
+    })
 }
 
 pub fn shell_cmd(s: &str) -> Result<String, anyhow::Error> {
     let out = if cfg!(target_os = "windows") {
         std::process::Command::new("cmd")
-            .args(&["/C", s])
+            .args(["/C", s])
             .output()
             .expect("failed to execute process")
     } else {
@@ -205,54 +572,129 @@ pub fn shell_cmd(s: &str) -> Result<String, anyhow::Error> {
             .output()
             .expect("failed to execute process")
     };
+    if !out.status.success() {
+        bail!(
+            "Command {:?} exited with code {:?}: {}",
+            s,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )
+    }
     Ok(String::from_utf8(out.stdout)?.trim().to_string())
 }
 
+/// A hook that exited with a non-zero code.
+#[derive(Debug)]
+pub struct HookFailed {
+    pub command: String,
+    pub status: std::process::ExitStatus,
+    /// The standard output of the hook.
+    pub stdout: String,
+    /// The standard error of the hook, trimmed.
+    pub stderr: String,
+}
+
+impl std::fmt::Display for HookFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Hook {:?} exited with code {:?}",
+            self.command, self.status
+        )?;
+        if !self.stderr.is_empty() {
+            write!(f, ":\n{}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HookFailed {}
+
 impl HookEntry {
     pub fn run(&self, path: PathBuf) -> Result<(), anyhow::Error> {
-        let (proc, s) = match &self.0 {
+        self.output(path)?;
+        Ok(())
+    }
+
+    /// Same as [`Self::run`], but returns the standard output of the
+    /// hook, trimmed.
+    pub fn run_captured(&self, path: PathBuf) -> Result<String, anyhow::Error> {
+        if let Some(out) = self.output(path)? {
+            Ok(String::from_utf8(out.stdout)?.trim().to_string())
+        } else {
+            Ok(String::new())
+        }
+    }
+
+    /// Run the hook, exiting with its code if it fails, after printing
+    /// what it wrote.
+    fn output(&self, path: PathBuf) -> Result<Option<std::process::Output>, anyhow::Error> {
+        match self.checked_output(path) {
+            Err(e) => {
+                if let Some(failed) = e.downcast_ref::<HookFailed>() {
+                    let mut stdout = std::io::stdout();
+                    stdout.write_all(failed.stdout.as_bytes())?;
+                    let mut stderr = std::io::stderr();
+                    writeln!(stderr, "{}", failed)?;
+                    std::process::exit(failed.status.code().unwrap_or(1))
+                }
+                Err(e)
+            }
+            out => out,
+        }
+    }
+
+    fn checked_output(&self, path: PathBuf) -> Result<Option<std::process::Output>, anyhow::Error> {
+        let (mut cmd, s, timeout) = match &self.0 {
             toml::Value::String(ref s) => {
                 if s.is_empty() {
-                    return Ok(());
+                    return Ok(None);
                 }
-                (
-                    if cfg!(target_os = "windows") {
-                        std::process::Command::new("cmd")
-                            .current_dir(path)
-                            .args(&["/C", s])
-                            .output()
-                            .expect("failed to execute process")
-                    } else {
-                        std::process::Command::new(
-                            std::env::var("SHELL").unwrap_or("sh".to_string()),
-                        )
-                        .current_dir(path)
-                        .arg("-c")
-                        .arg(s)
-                        .output()
-                        .expect("failed to execute process")
-                    },
-                    s.clone(),
-                )
+                let mut cmd = if cfg!(target_os = "windows") {
+                    let mut cmd = std::process::Command::new("cmd");
+                    cmd.args(["/C", s]);
+                    cmd
+                } else {
+                    let mut cmd = std::process::Command::new(
+                        std::env::var("SHELL").unwrap_or("sh".to_string()),
+                    );
+                    cmd.arg("-c").arg(s);
+                    cmd
+                };
+                cmd.current_dir(path);
+                (cmd, s.clone(), None)
             }
             v => {
                 let hook = v.clone().try_into::<RawHook>()?;
+                let mut cmd = std::process::Command::new(&hook.command);
+                cmd.args(&hook.args);
+                if let Some(ref cwd) = hook.cwd {
+                    cmd.current_dir(path.join(cwd));
+                } else {
+                    cmd.current_dir(path);
+                }
                 (
-                    std::process::Command::new(&hook.command)
-                        .current_dir(path)
-                        .args(&hook.args)
-                        .output()
-                        .expect("failed to execute process"),
+                    cmd,
                     hook.command,
+                    hook.timeout_secs.map(std::time::Duration::from_secs),
                 )
             }
         };
+        let proc = if let Some(timeout) = timeout {
+            output_with_timeout(cmd, &s, timeout)?
+        } else {
+            cmd.output().expect("failed to execute process")
+        };
         if !proc.status.success() {
-            let mut stderr = std::io::stderr();
-            writeln!(stderr, "Hook {:?} exited with code {:?}", s, proc.status)?;
-            std::process::exit(proc.status.code().unwrap_or(1))
+            return Err(HookFailed {
+                command: s,
+                status: proc.status,
+                stdout: String::from_utf8_lossy(&proc.stdout).into_owned(),
+                stderr: String::from_utf8_lossy(&proc.stderr).trim().to_string(),
+            }
+            .into());
         }
-        Ok(())
+        Ok(Some(proc))
     }
 }
 
@@ -338,3 +780,299 @@ pub fn load_theme() -> Result<Box<dyn theme::Theme>, anyhow::Error> {
         Ok(Box::new(theme::ColorfulTheme::default()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_remotes() {
+        let mut config: Config = toml::from_str(
+            r#"
+            default_remote = "origin"
+            [[remotes]]
+            name = "origin"
+            http = "https://nest.pijul.com/pijul/pijul"
+            [[remotes]]
+            name = "mirror"
+            ssh = "me@nest.pijul.com:pijul/pijul"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        config.default_remote = Some("https://nest.pijul.com/pijul/pijul".to_string());
+        assert!(config.validate().is_ok());
+
+        config.default_remote = Some("upstream".to_string());
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::UnknownDefaultRemote(r)) if r == "upstream"
+        ));
+
+        config.default_remote = None;
+        config.remotes.push(RemoteConfig::Ssh {
+            name: "origin".to_string(),
+            ssh: "me@example.com:repo".to_string(),
+        });
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::DuplicateRemote(r)) if r == "origin"
+        ));
+    }
+
+    #[test]
+    fn pristine_initial_size() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.pristine_initial_size().unwrap(), None);
+
+        for (size, bytes) in [
+            ("pristine_initial_size = 8388608", 8 << 20),
+            ("pristine_initial_size = \"8MiB\"", 8 << 20),
+            ("pristine_initial_size = \"2 G\"", 2 << 30),
+            ("pristine_initial_size = \"10MB\"", 10_000_000),
+        ] {
+            let config: Config = toml::from_str(size).unwrap();
+            assert!(config.validate().is_ok());
+            assert_eq!(config.pristine_initial_size().unwrap(), Some(bytes));
+        }
+
+        let config: Config = toml::from_str("pristine_initial_size = \"512KiB\"").unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::PristineSizeTooSmall(524288))
+        ));
+        let config: Config = toml::from_str("pristine_initial_size = \"lots\"").unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidSize(s)) if s == "lots"
+        ));
+    }
+
+    #[test]
+    fn http_headers_order() {
+        let config: Config = toml::from_str(
+            r#"
+            [[remotes]]
+            name = "nest"
+            http = "https://nest.pijul.com/pijul/pijul"
+            [remotes.headers]
+            X-Token = "first"
+            Accept = "text/plain"
+            x-token = "second"
+            "#,
+        )
+        .unwrap();
+        let names: Vec<_> = if let RemoteConfig::Http { ref headers, .. } = config.remotes[0] {
+            headers.keys().map(String::as_str).collect()
+        } else {
+            panic!("expected an HTTP remote")
+        };
+        assert_eq!(names, ["X-Token", "Accept", "x-token"]);
+    }
+
+    #[test]
+    fn env_header() {
+        #[derive(Deserialize)]
+        struct Headers {
+            headers: IndexMap<String, RemoteHttpHeader>,
+        }
+        let h: Headers = toml::from_str(
+            r#"
+            [headers]
+            Literal = "value"
+            Authorization = { env = "PIJUL_TEST_ENV_HEADER" }
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            h.headers["Authorization"],
+            RemoteHttpHeader::Env { .. }
+        ));
+        assert_eq!(h.headers["Literal"].resolve().unwrap(), "value");
+
+        let unset = |_: &str| Err(std::env::VarError::NotPresent);
+        assert!(h.headers["Authorization"].resolve_with(unset).is_err());
+        let set = |name: &str| {
+            assert_eq!(name, "PIJUL_TEST_ENV_HEADER");
+            Ok("Bearer 1234".to_string())
+        };
+        assert_eq!(
+            h.headers["Authorization"].resolve_with(set).unwrap(),
+            "Bearer 1234"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn failing_hook_stderr() {
+        let hook = HookEntry(toml::Value::String(
+            "echo partial; echo 'lint failed: src/lib.rs' >&2; exit 1".to_string(),
+        ));
+        let e = hook.checked_output(std::env::temp_dir()).unwrap_err();
+        let failed = e.downcast_ref::<HookFailed>().unwrap();
+        assert_eq!(failed.status.code(), Some(1));
+        assert_eq!(failed.stderr, "lint failed: src/lib.rs");
+        assert_eq!(failed.stdout, "partial\n");
+        assert!(e.to_string().ends_with(":\nlint failed: src/lib.rs"));
+
+        let e = shell_cmd("echo 'no token' >&2; exit 1").unwrap_err();
+        assert!(e.to_string().ends_with(": no token"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hook_timeout() {
+        let hook: HookEntry = toml::from_str::<Hooks>(
+            r#"record = [{ command = "sleep", args = ["10"], timeout_secs = 1 }]"#,
+        )
+        .unwrap()
+        .record
+        .pop()
+        .unwrap();
+        let start = std::time::Instant::now();
+        let e = hook.checked_output(std::env::temp_dir()).unwrap_err();
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert!(e.to_string().contains("timed out"));
+
+        let hook: HookEntry = toml::from_str::<Hooks>(
+            r#"record = [{ command = "echo", args = ["on time"], timeout_secs = 10 }]"#,
+        )
+        .unwrap()
+        .record
+        .pop()
+        .unwrap();
+        assert_eq!(hook.run_captured(std::env::temp_dir()).unwrap(), "on time");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hook_timeout_background_process() {
+        // The background sleep keeps the standard output of the hook
+        // open after the hook exits.
+        let hook: HookEntry = toml::from_str::<Hooks>(
+            r#"record = [{ command = "sh", args = ["-c", "echo done; sleep 10 &"], timeout_secs = 1 }]"#,
+        )
+        .unwrap()
+        .record
+        .pop()
+        .unwrap();
+        let start = std::time::Instant::now();
+        assert_eq!(hook.run_captured(std::env::temp_dir()).unwrap(), "done");
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hook_cwd() {
+        let root = std::env::temp_dir().join(format!("pijul-hook-cwd-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        let hook: HookEntry =
+            toml::from_str::<Hooks>(r#"record = [{ command = "pwd", args = [], cwd = "sub" }]"#)
+                .unwrap()
+                .record
+                .pop()
+                .unwrap();
+        let out = hook.run_captured(root.clone()).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(out.ends_with("/sub"));
+    }
+
+    #[test]
+    fn hook_output_in_description() {
+        let hook = HookEntry(toml::Value::String("echo Ticket: PIJ-12".to_string()));
+        let output = hook.run_captured(std::env::temp_dir()).unwrap();
+        assert_eq!(output, "Ticket: PIJ-12");
+
+        let mut header = libpijul::change::ChangeHeader {
+            description: Some("Fix the parser.".to_string()),
+            ..Default::default()
+        };
+        header.append_description(&output);
+        assert_eq!(
+            header.description.as_deref(),
+            Some("Fix the parser.\n\nTicket: PIJ-12")
+        );
+    }
+
+    #[test]
+    fn load_global() {
+        let root = std::env::temp_dir().join(format!("pijul-global-{}", std::process::id()));
+        let missing = root.join("missing");
+        let var = |name: &str| (name == "PIJUL_AUTHOR_EMAIL").then(|| "ci@example.org".to_string());
+
+        // Without a file, the variables are overlaid on the defaults.
+        let (global, ts) = Global::load_from(Some(missing.clone()), Some(missing), var).unwrap();
+        assert_eq!(ts, 0);
+        assert_eq!(global.author.email, "ci@example.org");
+        assert!(global.colors.is_none());
+
+        // The variables override the values of the file.
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            root.join("config.toml"),
+            "colors = \"never\"\n[author]\nemail = \"me@example.org\"\n",
+        )
+        .unwrap();
+        let (file, _) = Global::load_from(Some(root.clone()), None, |_| None).unwrap();
+        let (global, ts) = Global::load_from(Some(root.clone()), None, var).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(file.author.email, "me@example.org");
+        assert!(ts > 0);
+        assert_eq!(global.author.email, "ci@example.org");
+        assert!(matches!(global.colors, Some(Choice::Never)));
+    }
+
+    #[test]
+    fn validate_extra_dependencies() {
+        use libpijul::pristine::Base32;
+        use libpijul::{MutTxnT, MutTxnTExt};
+        let env = libpijul::pristine::sanakirja::Pristine::new_anon().unwrap();
+        let txn = env.arc_txn_begin().unwrap();
+        let main = txn.write().open_or_create_channel("main").unwrap();
+        let other = txn.write().open_or_create_channel("other").unwrap();
+        let store = libpijul::changestore::memory::Memory::new();
+        let mut hashes = Vec::new();
+        for (message, channel) in [("on main", &main), ("elsewhere", &other)] {
+            let mut change = libpijul::change::Change::make_change(
+                &*txn.read(),
+                channel,
+                Vec::new(),
+                Vec::new(),
+                libpijul::change::ChangeHeader {
+                    message: message.to_string(),
+                    ..Default::default()
+                },
+                Vec::new(),
+            )
+            .unwrap();
+            let hash = store
+                .save_change(&mut change, |_, _| Ok::<_, anyhow::Error>(()))
+                .unwrap();
+            txn.write()
+                .apply_change(&store, &mut *channel.write(), &hash)
+                .unwrap();
+            hashes.push(hash)
+        }
+
+        let valid = hashes[0].to_base32();
+        let mut config = Config {
+            extra_dependencies: vec![valid.clone(), valid[..20].to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate_extra_dependencies(&*txn.read(), &*main.read(), &store),
+            Ok(vec![hashes[0]])
+        );
+
+        let elsewhere = hashes[1].to_base32();
+        config.extra_dependencies = vec![valid, "bogus".to_string(), elsewhere.clone()];
+        assert_eq!(
+            config.validate_extra_dependencies(&*txn.read(), &*main.read(), &store),
+            Err(vec![
+                "\"bogus\" is not a known change".to_string(),
+                format!("{:?} is not on the channel", elsewhere),
+            ])
+        );
+    }
+}
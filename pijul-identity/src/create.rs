@@ -294,8 +294,12 @@ impl Complete {
             new_identity.write()?;
 
             // Delete the existing password
-            if let Err(e) = Entry::new("pijul", &self.name).and_then(|x| x.delete_password()) {
-                warn!("Unable to delete password: {e:?}");
+            if crate::keyring_enabled() {
+                if let Err(e) = Entry::new(crate::keyring_service(), &self.name)
+                    .and_then(|x| x.delete_password())
+                {
+                    warn!("Unable to delete password: {e:?}");
+                }
             }
         } else {
             // Write only the new data
@@ -309,14 +313,16 @@ impl Complete {
         }
 
         // Update the password
-        if let Some(password) = new_identity.credentials.clone().unwrap().password.get() {
-            if let Err(e) =
-                Entry::new("pijul", &new_identity.name).and_then(|x| x.set_password(&password))
+        if !crate::keyring_enabled() {
+            debug!("Keyring disabled, not storing the password");
+        } else if let Some(password) = new_identity.credentials.clone().unwrap().password.get() {
+            if let Err(e) = Entry::new(crate::keyring_service(), &new_identity.name)
+                .and_then(|x| x.set_password(&password))
             {
                 warn!("Unable to set password: {e:?}");
             }
-        } else if let Err(e) =
-            Entry::new("pijul", &new_identity.name).and_then(|x| x.delete_password())
+        } else if let Err(e) = Entry::new(crate::keyring_service(), &new_identity.name)
+            .and_then(|x| x.delete_password())
         {
             warn!("Unable to delete password: {e:?}");
         }
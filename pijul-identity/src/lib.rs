@@ -50,12 +50,103 @@ use libpijul::key::{PublicKey, SKey, SecretKey};
 use std::fmt::Display;
 use std::fs;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use pijul_interaction::Password;
 use serde::{Deserialize, Serialize};
 use std::sync::OnceLock;
 
+/// The keyring service name used when none was set with [`set_keyring_service`].
+pub const DEFAULT_KEYRING_SERVICE: &str = "pijul";
+
+static KEYRING_SERVICE: OnceLock<String> = OnceLock::new();
+
+/// Set the service name under which passwords are stored in the system keyring,
+/// for instance to keep the passwords of different Pijul-based tools apart.
+/// This can only be set once, and should be set before any password is read.
+///
+/// Passwords stored under a different service name (including the default) are
+/// not looked up anymore, so users will be prompted for them again.
+///
+/// # Errors
+/// Returns the name back if a service name was already set.
+pub fn set_keyring_service(name: String) -> Result<(), String> {
+    KEYRING_SERVICE.set(name)
+}
+
+/// The service name under which passwords are stored in the system keyring,
+/// [`DEFAULT_KEYRING_SERVICE`] unless it was changed with [`set_keyring_service`].
+#[must_use]
+pub fn keyring_service() -> &'static str {
+    KEYRING_SERVICE
+        .get()
+        .map_or(DEFAULT_KEYRING_SERVICE, String::as_str)
+}
+
+static USE_KEYRING: OnceLock<bool> = OnceLock::new();
+
+/// Set whether passwords should be read from and written to the system
+/// keyring, for instance from a global configuration the caller has
+/// already loaded. This can only be set once, and should be set before
+/// any password is read.
+///
+/// # Errors
+/// Returns the value back if it was already set, or already read from
+/// the global configuration by [`keyring_enabled`].
+pub fn set_keyring_enabled(enabled: bool) -> Result<(), bool> {
+    USE_KEYRING.set(enabled)
+}
+
+/// Whether passwords should be read from and written to the system
+/// keyring. Unless it was set with [`set_keyring_enabled`], this is
+/// read once from the `use_keyring` field of the global configuration,
+/// and defaults to `true`, including when the global configuration
+/// can't be read.
+#[must_use]
+pub fn keyring_enabled() -> bool {
+    *USE_KEYRING
+        .get_or_init(|| config::Global::load().map_or(true, |(global, _)| global.keyring_enabled()))
+}
+
+/// Delete the keyring passwords of the identities named in `candidates` that
+/// don't exist on disk anymore, for instance because their directory was
+/// removed by hand. Keyrings can't always enumerate their entries, so the
+/// names to check must be supplied by the caller.
+///
+/// Returns the names whose passwords were deleted, which is always empty
+/// if the keyring is disabled (see [`keyring_enabled`]).
+///
+/// # Errors
+/// Returns an error if the identities directory can't be located.
+pub fn prune_keyring(candidates: &[String]) -> Result<Vec<String>, anyhow::Error> {
+    let identities = config::global_config_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not find global config directory"))?
+        .join("identities");
+    Ok(prune_keyring_with(
+        &identities,
+        candidates,
+        keyring_enabled(),
+    ))
+}
+
+fn prune_keyring_with(identities: &Path, candidates: &[String], use_keyring: bool) -> Vec<String> {
+    let mut pruned = Vec::new();
+    if !use_keyring {
+        return pruned;
+    }
+    for name in candidates {
+        if name.is_empty() || identities.join(name).exists() {
+            continue;
+        }
+        match keyring::Entry::new(keyring_service(), name).and_then(|x| x.delete_password()) {
+            Ok(()) => pruned.push(name.clone()),
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => warn!("Unable to delete password for {name}: {e:?}"),
+        }
+    }
+    pruned
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Config {
     #[serde(flatten)]
@@ -111,7 +202,23 @@ impl From<SecretKey> for Credentials {
 }
 
 impl Credentials {
+    /// Decrypts the secret key, returning it along with the password that worked.
+    ///
+    /// The system keyring is only accessed if it is enabled (see [`keyring_enabled`]),
+    /// the secret key is encrypted and the password held by these credentials (if any)
+    /// doesn't decrypt it.
+    ///
+    /// # Errors
+    /// Fails if the key cannot be loaded, or if prompting for a password fails.
     pub fn decrypt(&mut self, name: &str) -> Result<(SKey, Option<String>), anyhow::Error> {
+        self.decrypt_with(name, keyring_enabled())
+    }
+
+    fn decrypt_with(
+        &mut self,
+        name: &str,
+        use_keyring: bool,
+    ) -> Result<(SKey, Option<String>), anyhow::Error> {
         if self.secret_key.encryption.is_none() {
             // Don't mind what the given password is, the secret key has no encryption
             // Make sure to revoke the password
@@ -126,13 +233,18 @@ impl Credentials {
         } else {
             // Password does not match secret key
             let mut stderr = std::io::stderr();
-            let mut password_attempt = String::new();
+            let entry = if use_keyring {
+                Some(keyring::Entry::new(keyring_service(), name))
+            } else {
+                None
+            };
 
             // Try a password stored in the keychain
-            if let Ok(password) = keyring::Entry::new("pijul", name).and_then(|x| x.get_password())
-            {
-                password_attempt = password;
-            }
+            let stored_password = entry
+                .as_ref()
+                .and_then(|x| x.as_ref().ok())
+                .and_then(|x| x.get_password().ok());
+            let mut password_attempt = stored_password.clone().unwrap_or_default();
 
             // Re-prompt as long as the password doesn't work
             while self.secret_key.load(Some(&password_attempt)).is_err() {
@@ -144,13 +256,15 @@ impl Credentials {
                     .interact()?;
             }
 
-            // Update the password
-            if let Err(e) =
-                keyring::Entry::new("pijul", name).and_then(|x| x.set_password(&password_attempt))
-            {
-                warn!("Unable to set password: {e:?}");
+            // Update the password, writing to the keychain only if it didn't come from there
+            if stored_password.as_ref() != Some(&password_attempt) {
+                if let Some(entry) = entry {
+                    if let Err(e) = entry.and_then(|x| x.set_password(&password_attempt)) {
+                        warn!("Unable to set password: {e:?}");
+                    }
+                }
             }
-            self.password.set(password_attempt.clone()).unwrap();
+            self.password = OnceLock::from(password_attempt.clone());
 
             Ok((
                 self.secret_key.load(Some(&password_attempt))?,
@@ -268,10 +382,12 @@ impl Complete {
             OnceLock::new()
         } else {
             // User has entered a password, add it to the keyring
-            if let Err(e) = keyring::Entry::new("pijul", &self.name)
-                .and_then(|x| x.set_password(&user_password))
-            {
-                warn!("Unable to set password: {e:?}");
+            if keyring_enabled() {
+                if let Err(e) = keyring::Entry::new(keyring_service(), &self.name)
+                    .and_then(|x| x.set_password(&user_password))
+                {
+                    warn!("Unable to set password: {e:?}");
+                }
             }
 
             OnceLock::from(user_password)
@@ -311,3 +427,205 @@ impl Display for Complete {
         write!(f, "{}{}", self.name, remote_details.unwrap_or_default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+    use std::any::Any;
+    use std::collections::BTreeMap;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// The contents of the mock keyring, shared by all its entries.
+    struct MockKeyring {
+        /// Passwords, by service and user.
+        passwords: BTreeMap<(String, String), String>,
+        /// The service and user of every entry created.
+        entries: Vec<(String, String)>,
+        /// The service and user of every password set.
+        writes: Vec<(String, String)>,
+    }
+
+    static MOCK_KEYRING: Mutex<MockKeyring> = Mutex::new(MockKeyring {
+        passwords: BTreeMap::new(),
+        entries: Vec::new(),
+        writes: Vec::new(),
+    });
+
+    struct MockBuilder;
+
+    struct MockCredential {
+        key: (String, String),
+    }
+
+    impl CredentialBuilderApi for MockBuilder {
+        fn build(
+            &self,
+            _target: Option<&str>,
+            service: &str,
+            user: &str,
+        ) -> keyring::Result<Box<Credential>> {
+            let key = (service.to_string(), user.to_string());
+            MOCK_KEYRING.lock().unwrap().entries.push(key.clone());
+            Ok(Box::new(MockCredential { key }))
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    impl CredentialApi for MockCredential {
+        fn set_password(&self, password: &str) -> keyring::Result<()> {
+            let mut keyring = MOCK_KEYRING.lock().unwrap();
+            keyring
+                .passwords
+                .insert(self.key.clone(), password.to_string());
+            keyring.writes.push(self.key.clone());
+            drop(keyring);
+            Ok(())
+        }
+
+        fn get_password(&self) -> keyring::Result<String> {
+            MOCK_KEYRING
+                .lock()
+                .unwrap()
+                .passwords
+                .get(&self.key)
+                .cloned()
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn delete_password(&self) -> keyring::Result<()> {
+            MOCK_KEYRING
+                .lock()
+                .unwrap()
+                .passwords
+                .remove(&self.key)
+                .map(|_| ())
+                .ok_or(keyring::Error::NoEntry)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    const TEST_SERVICE: &str = "pijul-test";
+
+    /// Use an empty mock keyring, with service name [`TEST_SERVICE`]. The
+    /// returned guard keeps other tests from using the keyring until it is
+    /// dropped.
+    fn mock_keyring() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let guard = LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        keyring::set_default_credential_builder(Box::new(MockBuilder));
+        // The service name can only be set once, by the first test to run.
+        let _ = set_keyring_service(TEST_SERVICE.to_string());
+        let mut keyring = MOCK_KEYRING.lock().unwrap();
+        keyring.passwords.clear();
+        keyring.entries.clear();
+        keyring.writes.clear();
+        guard
+    }
+
+    fn entries() -> Vec<(String, String)> {
+        MOCK_KEYRING.lock().unwrap().entries.clone()
+    }
+
+    #[test]
+    fn custom_keyring_service() {
+        let _guard = mock_keyring();
+        assert_eq!(keyring_service(), TEST_SERVICE);
+
+        let secret_key = SKey::generate(None).save(Some("password"));
+        keyring::Entry::new(TEST_SERVICE, "identity")
+            .unwrap()
+            .set_password("password")
+            .unwrap();
+
+        // The password is only found under the custom service name.
+        let mut credentials = Credentials::new(secret_key, None);
+        let (_, password) = credentials.decrypt_with("identity", true).unwrap();
+        assert_eq!(password.as_deref(), Some("password"));
+        assert!(entries().iter().all(|(service, _)| service == TEST_SERVICE));
+    }
+
+    #[test]
+    fn prune_keyring_removed_identities() {
+        let _guard = mock_keyring();
+        let identities = tempfile::tempdir().unwrap();
+        std::fs::create_dir(identities.path().join("kept")).unwrap();
+        for name in ["kept", "removed"] {
+            keyring::Entry::new(TEST_SERVICE, name)
+                .unwrap()
+                .set_password("password")
+                .unwrap();
+        }
+
+        let candidates = ["kept", "removed", "unknown", ""].map(String::from);
+        let pruned = prune_keyring_with(identities.path(), &candidates, true);
+        assert_eq!(pruned, ["removed"]);
+
+        // Only the password of the removed identity is gone.
+        let password = |name: &str| {
+            keyring::Entry::new(TEST_SERVICE, name)
+                .unwrap()
+                .get_password()
+                .ok()
+        };
+        assert_eq!(password("kept").as_deref(), Some("password"));
+        assert_eq!(password("removed"), None);
+    }
+
+    #[test]
+    fn decrypt_cached_password() {
+        let _guard = mock_keyring();
+        let secret_key = SKey::generate(None).save(Some("password"));
+        let mut credentials = Credentials::new(secret_key, Some("password".to_string()));
+        let (_, password) = credentials.decrypt_with("identity", true).unwrap();
+        assert_eq!(password.as_deref(), Some("password"));
+        assert!(entries().is_empty());
+    }
+
+    #[test]
+    fn decrypt_keyring_password_not_written_back() {
+        let _guard = mock_keyring();
+        let secret_key = SKey::generate(None).save(Some("password"));
+        keyring::Entry::new(TEST_SERVICE, "identity")
+            .unwrap()
+            .set_password("password")
+            .unwrap();
+        MOCK_KEYRING.lock().unwrap().writes.clear();
+
+        let mut credentials = Credentials::new(secret_key, Some("wrong".to_string()));
+        let (_, password) = credentials.decrypt_with("identity", true).unwrap();
+        assert_eq!(password.as_deref(), Some("password"));
+        assert!(MOCK_KEYRING.lock().unwrap().writes.is_empty());
+
+        // The password is now cached, so the keyring isn't read again.
+        MOCK_KEYRING.lock().unwrap().entries.clear();
+        credentials.decrypt_with("identity", true).unwrap();
+        assert!(entries().is_empty());
+    }
+
+    #[test]
+    fn disabled_keyring_is_never_accessed() {
+        let _guard = mock_keyring();
+
+        let identities = tempfile::tempdir().unwrap();
+        let pruned = prune_keyring_with(identities.path(), &["removed".to_string()], false);
+        assert!(pruned.is_empty());
+
+        // The cached password doesn't work, and there is nothing to
+        // prompt for a password, so decrypting fails without falling
+        // back to the keyring.
+        let secret_key = SKey::generate(None).save(Some("password"));
+        let mut credentials = Credentials::new(secret_key, Some("wrong".to_string()));
+        assert!(credentials.decrypt_with("identity", false).is_err());
+
+        assert!(entries().is_empty());
+    }
+}
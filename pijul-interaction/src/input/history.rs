@@ -0,0 +1,125 @@
+use crate::InteractionError;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// The number of entries kept by a [`FileHistory`] by default.
+const DEFAULT_MAX_LEN: usize = 100;
+
+/// Previous answers to a text prompt, recalled with the arrow keys.
+pub trait HistoryStore: Send {
+    /// The entry `pos` steps back in the history, 0 being the most
+    /// recent one.
+    fn read(&self, pos: usize) -> Option<String>;
+    /// Add `entry` to the history, as the most recent one.
+    fn write(&mut self, entry: &str);
+}
+
+/// A history stored in a file, one entry per line, oldest first.
+/// Entries are saved as soon as they are written.
+pub struct FileHistory {
+    path: PathBuf,
+    entries: VecDeque<String>,
+    max_len: usize,
+}
+
+impl FileHistory {
+    /// Open the history called `name`, in the `history` directory of
+    /// the global configuration directory.
+    pub fn open(name: &str) -> Result<Self, InteractionError> {
+        let dir = pijul_config::global_config_dir().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "global configuration directory not found",
+            )
+        })?;
+        Self::open_path(dir.join("history").join(name))
+    }
+
+    /// Open the history stored at `path`, which is created when the
+    /// first entry is written.
+    pub fn open_path(path: PathBuf) -> Result<Self, InteractionError> {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(s) => s.lines().rev().map(String::from).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(FileHistory {
+            path,
+            entries,
+            max_len: DEFAULT_MAX_LEN,
+        })
+    }
+
+    /// Keep at most `max_len` entries, dropping the oldest ones.
+    /// Default: 100.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self.entries.truncate(max_len);
+        self
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut s = String::new();
+        for e in self.entries.iter().rev() {
+            s.push_str(e);
+            s.push('\n');
+        }
+        std::fs::write(&self.path, s)
+    }
+}
+
+impl HistoryStore for FileHistory {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.entries.get(pos).cloned()
+    }
+
+    /// Empty entries, entries spanning several lines and repetitions
+    /// of the last entry aren't recorded.
+    fn write(&mut self, entry: &str) {
+        if entry.is_empty()
+            || entry.contains('\n')
+            || self.entries.front().map(String::as_str) == Some(entry)
+        {
+            return;
+        }
+        self.entries.push_front(entry.to_string());
+        self.entries.truncate(self.max_len);
+        if let Err(e) = self.save() {
+            log::warn!("Could not save the history to {:?}: {}", self.path, e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_history() {
+        let path = std::env::temp_dir()
+            .join(format!("pijul-history-{}", std::process::id()))
+            .join("messages");
+        let mut history = FileHistory::open_path(path.clone()).unwrap();
+        assert_eq!(history.read(0), None);
+        history.write("Fix the parser");
+        history.write("Fix the parser");
+        history.write("");
+        history.write("Add tests");
+
+        let mut history = FileHistory::open_path(path.clone())
+            .unwrap()
+            .with_max_len(2);
+        assert_eq!(history.read(0).as_deref(), Some("Add tests"));
+        assert_eq!(history.read(1).as_deref(), Some("Fix the parser"));
+        assert_eq!(history.read(2), None);
+        history.write("Update the changelog");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Add tests\nUpdate the changelog\n"
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+//! Prompts answered by another program, through JSON lines on stdin
+//! and stdout.
+//!
+//! Each prompt is written as an object such as
+//! `{"type":"select","prompt":"…","default":0,"items":["a","b"]}`, and
+//! answered by a single JSON value on its own line: a boolean, a
+//! string, an item index or an array of item indices depending on
+//! the prompt, or `null` to use the default. An answer that can't be
+//! used is reported with an object `{"type":"error","message":"…"}`,
+//! and the prompt is asked again.
+use super::{
+    BasePrompt, ConfirmPrompt, DefaultPrompt, HistoryStore, InteractionError, MultiSelectionPrompt,
+    PasswordPrompt, PromptType, SelectResult, SelectionPrompt, TextPrompt, ValidationPrompt,
+};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+/// The values exchanged with the program answering prompts.
+pub trait JsonAnswer: Sized {
+    fn to_json(&self) -> Value;
+    fn from_json(value: &Value) -> Option<Self>;
+    fn is_empty(&self) -> bool {
+        false
+    }
+    /// Check that the answer refers to existing items.
+    fn check_items(&self, _items: &[String]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl JsonAnswer for bool {
+    fn to_json(&self) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl JsonAnswer for usize {
+    fn to_json(&self) -> Value {
+        json!(self)
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_u64().and_then(|x| x.try_into().ok())
+    }
+
+    fn check_items(&self, items: &[String]) -> Result<(), String> {
+        if *self < items.len() {
+            Ok(())
+        } else {
+            Err(format!("no item {self}"))
+        }
+    }
+}
+
+impl JsonAnswer for Vec<usize> {
+    fn to_json(&self) -> Value {
+        json!(self)
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_array()?.iter().map(usize::from_json).collect()
+    }
+
+    fn check_items(&self, items: &[String]) -> Result<(), String> {
+        self.iter().try_for_each(|i| i.check_items(items))
+    }
+}
+
+impl JsonAnswer for String {
+    fn to_json(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+
+    fn is_empty(&self) -> bool {
+        str::is_empty(self)
+    }
+}
+
+/// Holds the state of a prompt until it is written out.
+pub struct JsonInteractive<T> {
+    prompt_type: PromptType,
+    prompt: Option<String>,
+    default: Option<T>,
+    items: Vec<String>,
+    allow_custom: bool,
+    validator: Option<Box<dyn Fn(&T) -> Result<(), String>>>,
+    confirmation: bool,
+    allow_empty: bool,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl<T> JsonInteractive<T> {
+    pub fn new(prompt_type: PromptType) -> Self {
+        Self::with_io(
+            prompt_type,
+            Box::new(std::io::stdin().lock()),
+            Box::new(std::io::stdout()),
+        )
+    }
+
+    pub fn with_io(
+        prompt_type: PromptType,
+        input: Box<dyn BufRead>,
+        output: Box<dyn Write>,
+    ) -> Self {
+        Self {
+            prompt_type,
+            prompt: None,
+            default: None,
+            items: Vec::new(),
+            allow_custom: false,
+            validator: None,
+            confirmation: false,
+            allow_empty: false,
+            input,
+            output,
+        }
+    }
+
+    fn prompt(&self) -> String {
+        self.prompt.clone().unwrap_or_default()
+    }
+
+    fn write(&mut self, value: &Value) -> Result<(), InteractionError> {
+        writeln!(self.output, "{value}")?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Write the prompt, and read answers until `parse` accepts one.
+    fn exchange<R, F: Fn(&Self, &Value) -> Result<R, String>>(
+        &mut self,
+        description: Value,
+        parse: F,
+    ) -> Result<R, InteractionError> {
+        loop {
+            self.write(&description)?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(InteractionError::NotInteractive(
+                    self.prompt_type,
+                    self.prompt(),
+                ));
+            }
+            let result = match serde_json::from_str(line.trim()) {
+                Ok(value) => parse(self, &value),
+                Err(e) => Err(e.to_string()),
+            };
+            match result {
+                Ok(result) => return Ok(result),
+                Err(message) => self.write(&json!({ "type": "error", "message": message }))?,
+            }
+        }
+    }
+}
+
+impl<T: JsonAnswer + Clone> JsonInteractive<T> {
+    fn describe(&self) -> Value {
+        let mut description = json!({
+            "type": match self.prompt_type {
+                PromptType::Confirm => "confirm",
+                PromptType::Input => "input",
+                PromptType::Select => "select",
+                PromptType::Password => "password",
+                PromptType::MultiSelect => "multiselect",
+            },
+            "prompt": self.prompt(),
+        });
+        if let PromptType::Password = self.prompt_type {
+            // Passwords are never echoed, only the fact that one is needed.
+            description["confirmation"] = Value::Bool(self.confirmation);
+            return description;
+        }
+        if let Some(ref default) = self.default {
+            description["default"] = default.to_json();
+        }
+        if let PromptType::Select | PromptType::MultiSelect = self.prompt_type {
+            description["items"] = json!(self.items);
+        }
+        if let PromptType::Select = self.prompt_type {
+            description["allow_custom"] = Value::Bool(self.allow_custom);
+        }
+        description
+    }
+
+    fn parse(&self, value: &Value) -> Result<T, String> {
+        let answer = match (value, &self.default) {
+            (Value::Null, Some(default)) => default.clone(),
+            (Value::Null, None) => return Err("no default value".to_string()),
+            (value, _) => T::from_json(value).ok_or_else(|| {
+                format!("unexpected answer {value} to {} prompt", self.prompt_type)
+            })?,
+        };
+        if answer.is_empty() && !self.allow_empty {
+            return Err("empty answer".to_string());
+        }
+        answer.check_items(&self.items)?;
+        if let Some(ref validator) = self.validator {
+            validator(&answer)?
+        }
+        Ok(answer)
+    }
+}
+
+impl<T: JsonAnswer + Clone> BasePrompt<T> for JsonInteractive<T> {
+    fn set_prompt(&mut self, prompt: String) {
+        self.prompt = Some(prompt);
+    }
+
+    fn interact(&mut self) -> Result<T, InteractionError> {
+        let description = self.describe();
+        self.exchange(description, Self::parse)
+    }
+}
+
+impl<T: JsonAnswer + Clone> DefaultPrompt<T> for JsonInteractive<T> {
+    fn set_default(&mut self, value: T) {
+        self.default = Some(value);
+    }
+
+    /// Answers come from a program, which is expected to reply.
+    fn set_timeout(&mut self, _timeout: std::time::Duration) {}
+}
+
+impl ConfirmPrompt<bool> for JsonInteractive<bool> {
+    fn set_single_keypress(&mut self, _single: bool) {}
+}
+
+impl SelectionPrompt<usize> for JsonInteractive<usize> {
+    fn add_items(&mut self, items: &[String]) {
+        self.items.extend(items.iter().cloned());
+    }
+
+    fn set_allow_custom(&mut self, allow: bool) {
+        self.allow_custom = allow;
+    }
+
+    /// Custom values are answered with a string, items with their index.
+    fn interact_custom(&mut self) -> Result<SelectResult, InteractionError> {
+        let description = self.describe();
+        self.exchange(description, |this, value| match value {
+            Value::String(s) if this.allow_custom => Ok(SelectResult::Custom(s.clone())),
+            value => Ok(SelectResult::Item(this.parse(value)?)),
+        })
+    }
+}
+
+impl MultiSelectionPrompt<Vec<usize>> for JsonInteractive<Vec<usize>> {
+    fn add_items(&mut self, items: &[String]) {
+        self.items.extend(items.iter().cloned());
+    }
+}
+
+impl<T: JsonAnswer + Clone> ValidationPrompt<T> for JsonInteractive<T> {
+    fn allow_empty(&mut self, empty: bool) {
+        self.allow_empty = empty;
+    }
+
+    fn set_validator(&mut self, validator: Box<dyn Fn(&T) -> Result<(), String>>) {
+        self.validator = Some(validator);
+    }
+}
+
+impl<T: JsonAnswer + Clone> PasswordPrompt<T> for JsonInteractive<T> {
+    /// The answering program doesn't make typos, so the password is
+    /// only read once.
+    fn set_confirmation(&mut self, _confirm_prompt: String, _mismatch_err: String) {
+        self.confirmation = true;
+    }
+}
+
+impl TextPrompt<String> for JsonInteractive<String> {
+    fn set_inital_text(&mut self, text: String) {
+        self.default = Some(text);
+    }
+
+    fn set_completions(&mut self, _candidates: Vec<String>) {}
+
+    fn set_partial_mask(&mut self, _keep_visible: Option<usize>) {}
+
+    fn set_history(&mut self, _history: Box<dyn HistoryStore>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// An output that can be read back after the prompt is gone.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn lines(&self) -> Vec<Value> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect()
+        }
+    }
+
+    fn prompt<T>(prompt_type: PromptType, answers: &str) -> (JsonInteractive<T>, Output) {
+        let output = Output::default();
+        let mut prompt = JsonInteractive::with_io(
+            prompt_type,
+            Box::new(std::io::Cursor::new(answers.as_bytes().to_vec())),
+            Box::new(output.clone()),
+        );
+        prompt.prompt = Some("Question".to_string());
+        (prompt, output)
+    }
+
+    #[test]
+    fn json_select() {
+        let (mut select, output) = prompt::<usize>(PromptType::Select, "5\n1\n");
+        select.add_items(&["a".to_string(), "b".to_string()]);
+        select.set_allow_custom(true);
+        assert_eq!(select.interact_custom().unwrap(), SelectResult::Item(1));
+        let lines = output.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "select");
+        assert_eq!(lines[0]["items"], json!(["a", "b"]));
+        assert_eq!(lines[1]["type"], "error");
+    }
+
+    #[test]
+    fn json_multi_select() {
+        let (mut select, output) = prompt::<Vec<usize>>(PromptType::MultiSelect, "[0, 3]\n[]\n");
+        select.add_items(&["a".to_string(), "b".to_string()]);
+        select.set_default(vec![1]);
+        assert_eq!(select.interact().unwrap(), Vec::<usize>::new());
+        let lines = output.lines();
+        assert_eq!(lines[0]["type"], "multiselect");
+        assert_eq!(lines[0]["default"], json!([1]));
+        assert_eq!(lines[1]["type"], "error");
+    }
+
+    #[test]
+    fn json_confirm_default() {
+        let (mut confirm, output) = prompt::<bool>(PromptType::Confirm, "null\n");
+        confirm.set_default(true);
+        assert!(confirm.interact().unwrap());
+        assert_eq!(output.lines()[0]["default"], true);
+
+        let (mut confirm, _) = prompt::<bool>(PromptType::Confirm, "");
+        assert!(matches!(
+            confirm.interact(),
+            Err(InteractionError::NotInteractive(..))
+        ));
+    }
+
+    #[test]
+    fn json_password_not_echoed() {
+        let (mut password, output) = prompt::<String>(PromptType::Password, "\"hunter2\"\n");
+        password.set_default("hunter2".to_string());
+        password.set_confirmation("Confirm".to_string(), "Mismatch".to_string());
+        assert_eq!(password.interact().unwrap(), "hunter2");
+        let lines = output.lines();
+        assert_eq!(
+            lines,
+            vec![json!({ "type": "password", "prompt": "Question", "confirmation": true })]
+        );
+    }
+}
@@ -1,14 +1,39 @@
 //! Implement the various prompt types defined in `lib.rs`
+mod history;
+mod json;
 mod non_interactive;
+#[cfg(feature = "terminal")]
 mod terminal;
 
-use crate::{Confirm, Input, Password, Select};
+use crate::{Confirm, Input, MultiSelect, Password, Select, SelectResult};
 use crate::{InteractionError, InteractiveContext, PromptType};
+#[cfg(feature = "terminal")]
 use dialoguer::theme;
 use duplicate::duplicate_item;
+pub use history::{FileHistory, HistoryStore};
+use json::JsonInteractive;
+#[cfg(feature = "terminal")]
 use lazy_static::lazy_static;
 use non_interactive::PseudoInteractive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+/// Whether non-interactive prompts log their answers, see `set_prompt_logging`.
+static PROMPT_LOGGING: AtomicBool = AtomicBool::new(false);
+
+/// Log (at the `info` level) the text of each prompt answered without
+/// interaction, and the default value it was answered with. This helps
+/// understanding the choices made in scripted runs. Off by default.
+pub fn set_prompt_logging(enabled: bool) {
+    PROMPT_LOGGING.store(enabled, Ordering::Relaxed)
+}
+
+/// Whether non-interactive prompts log their answers.
+pub fn prompt_logging() -> bool {
+    PROMPT_LOGGING.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "terminal")]
 lazy_static! {
     static ref THEME: Box<dyn theme::Theme + Send + Sync> = {
         use dialoguer::theme;
@@ -37,6 +62,12 @@ pub trait BasePrompt<T> {
 /// A trait for prompts that allow a default selection.
 pub trait DefaultPrompt<T>: BasePrompt<T> {
     fn set_default(&mut self, value: T);
+    fn set_timeout(&mut self, timeout: Duration);
+}
+
+/// A trait for yes/no prompts.
+pub trait ConfirmPrompt<T>: DefaultPrompt<T> {
+    fn set_single_keypress(&mut self, single: bool);
 }
 
 /// A trait for prompts that may need validation of user input.
@@ -57,26 +88,42 @@ pub trait PasswordPrompt<T>: ValidationPrompt<T> {
 /// Notably, this does NOT include passwords.
 pub trait TextPrompt<T>: ValidationPrompt<T> + DefaultPrompt<T> {
     fn set_inital_text(&mut self, text: String);
+    fn set_completions(&mut self, candidates: Vec<String>);
+    fn set_partial_mask(&mut self, keep_visible: Option<usize>);
+    fn set_history(&mut self, history: Box<dyn HistoryStore>);
 }
 
 /// A trait for prompts where the user may choose from a selection of items.
 pub trait SelectionPrompt<T>: DefaultPrompt<T> {
     fn add_items(&mut self, items: &[String]);
+    fn set_allow_custom(&mut self, allow: bool);
+    fn interact_custom(&mut self) -> Result<SelectResult, InteractionError>;
+}
+
+/// A trait for prompts where the user may choose several items from a
+/// selection. The default value is the items chosen initially.
+pub trait MultiSelectionPrompt<T>: DefaultPrompt<T> {
+    fn add_items(&mut self, items: &[String]);
 }
 
 #[duplicate_item(
-    handler         prompt_type                 return_type;
-    [Confirm]       [PromptType::Confirm]       [bool];
-    [Input]         [PromptType::Input]         [String];
-    [Select]        [PromptType::Select]        [usize];
-    [Password]      [PromptType::Password]      [String];
+    handler         prompt_type                 return_type     pseudo_interactive;
+    [Confirm]       [PromptType::Confirm]       [bool]          [PseudoInteractive::new(PromptType::Confirm)];
+    [Input]         [PromptType::Input]         [String]        [PseudoInteractive::new(PromptType::Input)];
+    [Select]        [PromptType::Select]        [usize]         [PseudoInteractive::new(PromptType::Select)];
+    [Password]      [PromptType::Password]      [String]        [PseudoInteractive::new(PromptType::Password)];
+    [MultiSelect]   [PromptType::MultiSelect]   [Vec<usize>]    [PseudoInteractive::with_default(PromptType::MultiSelect, Vec::new())];
 )]
 impl handler {
     /// Create the prompt, returning an error if interactive context is incorrectly set.
     pub fn new() -> Result<Self, InteractionError> {
         Ok(Self(match crate::get_context()? {
+            #[cfg(feature = "terminal")]
             InteractiveContext::Terminal => Box::new(terminal::handler::with_theme(THEME.as_ref())),
-            InteractiveContext::NotInteractive => Box::new(PseudoInteractive::new(prompt_type)),
+            #[cfg(not(feature = "terminal"))]
+            InteractiveContext::Terminal => Box::new(pseudo_interactive),
+            InteractiveContext::NotInteractive => Box::new(pseudo_interactive),
+            InteractiveContext::Json => Box::new(JsonInteractive::new(prompt_type)),
         }))
     }
 
@@ -102,7 +149,8 @@ impl handler {
     handler         return_type;
     [Confirm]       [bool];
     [Input]         [String];
-    [Select]   [usize];
+    [Select]        [usize];
+    [MultiSelect]   [Vec<usize>];
 )]
 impl handler {
     /// Set the default selection. If the user does not input anything, this value will be used instead.
@@ -115,9 +163,81 @@ impl handler {
         self.set_default(value.into());
         self
     }
+
+    /// Stop waiting for the user after `timeout`, and use the default
+    /// value instead, or fail with [`InteractionError::NotInteractive`]
+    /// if there is none. Ignored in non-interactive contexts, where
+    /// prompts never wait.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.0.set_timeout(timeout);
+    }
+
+    /// Builder pattern for [`Self::set_timeout`]
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.set_timeout(timeout);
+        self
+    }
+}
+
+impl Confirm {
+    /// Answer with a single keypress (`y`, `n`, or Enter for the
+    /// default) rather than a line of input. Ignored in
+    /// non-interactive contexts. Default: `true`.
+    pub fn set_single_keypress(&mut self, single: bool) {
+        self.0.set_single_keypress(single);
+    }
+
+    /// Builder pattern for [`Self::set_single_keypress`]
+    pub fn with_single_keypress(&mut self, single: bool) -> &mut Self {
+        self.set_single_keypress(single);
+        self
+    }
 }
 
 impl Select {
+    /// Create a [`MultiSelect`] prompt instead, where the user may
+    /// choose several items.
+    pub fn with_multi() -> Result<MultiSelect, InteractionError> {
+        MultiSelect::new()
+    }
+
+    /// Add items to be displayed in the selection prompt.
+    pub fn add_items<S: ToString>(&mut self, items: &[S]) {
+        let string_items: Vec<String> = items.iter().map(ToString::to_string).collect();
+        self.0.add_items(string_items.as_slice());
+    }
+
+    /// Builder pattern for [`Self::add_items`].
+    ///
+    /// NOTE: if this function is called multiple times, it will add ALL items to the builder.
+    pub fn with_items<S: ToString>(&mut self, items: &[S]) -> &mut Self {
+        self.add_items(items);
+        self
+    }
+
+    /// Allow the user to enter a value that isn't in the list. In a
+    /// terminal, this adds an extra item which asks for text input
+    /// when chosen. Default: `false`.
+    ///
+    /// Custom values are only returned by [`Self::interact_custom`].
+    pub fn set_allow_custom(&mut self, allow: bool) {
+        self.0.set_allow_custom(allow);
+    }
+
+    /// Builder pattern for [`Self::set_allow_custom`]
+    pub fn with_allow_custom(&mut self, allow: bool) -> &mut Self {
+        self.set_allow_custom(allow);
+        self
+    }
+
+    /// Same as [`Self::interact`], but returns either the chosen item
+    /// or the custom value entered by the user.
+    pub fn interact_custom(&mut self) -> Result<SelectResult, InteractionError> {
+        self.0.interact_custom()
+    }
+}
+
+impl MultiSelect {
     /// Add items to be displayed in the selection prompt.
     pub fn add_items<S: ToString>(&mut self, items: &[S]) {
         let string_items: Vec<String> = items.iter().map(ToString::to_string).collect();
@@ -202,4 +322,43 @@ impl Input {
         self.set_inital_text(text);
         self
     }
+
+    /// Set the candidates offered for tab-completion, such as channel
+    /// or remote names. Ignored in non-interactive contexts.
+    pub fn set_completions(&mut self, candidates: Vec<String>) {
+        self.0.set_completions(candidates);
+    }
+
+    /// Builder pattern for [`Self::set_completions`]
+    pub fn with_completions(&mut self, candidates: Vec<String>) -> &mut Self {
+        self.set_completions(candidates);
+        self
+    }
+
+    /// Echo the input masked, except for its last `keep_visible`
+    /// characters, so that a long token can be checked to have been
+    /// pasted entirely without being displayed. `None` (the default)
+    /// disables masking. Ignored in non-interactive contexts.
+    pub fn set_partial_mask(&mut self, keep_visible: Option<usize>) {
+        self.0.set_partial_mask(keep_visible);
+    }
+
+    /// Builder pattern for [`Self::set_partial_mask`]
+    pub fn with_partial_mask(&mut self, keep_visible: usize) -> &mut Self {
+        self.set_partial_mask(Some(keep_visible));
+        self
+    }
+
+    /// Let the user recall previous answers from `history` with the
+    /// arrow keys, and add the answer to it. Ignored in
+    /// non-interactive contexts.
+    pub fn set_history(&mut self, history: Box<dyn HistoryStore>) {
+        self.0.set_history(history);
+    }
+
+    /// Builder pattern for [`Self::set_history`]
+    pub fn with_history(&mut self, history: Box<dyn HistoryStore>) -> &mut Self {
+        self.set_history(history);
+        self
+    }
 }
@@ -1,6 +1,6 @@
 use super::{
-    BasePrompt, DefaultPrompt, InteractionError, PasswordPrompt, PromptType, SelectionPrompt,
-    TextPrompt, ValidationPrompt,
+    BasePrompt, ConfirmPrompt, DefaultPrompt, HistoryStore, InteractionError, MultiSelectionPrompt,
+    PasswordPrompt, PromptType, SelectResult, SelectionPrompt, TextPrompt, ValidationPrompt,
 };
 use core::fmt::Debug;
 use log::{error, info, warn};
@@ -32,6 +32,14 @@ impl<T: Clone + Debug> PseudoInteractive<T> {
             initial_value: None,
         }
     }
+
+    /// Same as [`Self::new`], with a default value set.
+    pub fn with_default(prompt_type: PromptType, default: T) -> Self {
+        Self {
+            default: Some(default),
+            ..Self::new(prompt_type)
+        }
+    }
 }
 
 impl<T: Clone + Debug> BasePrompt<T> for PseudoInteractive<T> {
@@ -53,6 +61,25 @@ impl<T: Clone + Debug> BasePrompt<T> for PseudoInteractive<T> {
             None
         };
 
+        if super::prompt_logging() {
+            match (&default, self.prompt_type) {
+                (Some(_), PromptType::Password) => {
+                    info!(
+                        "{} prompt `{prompt}` answered with its default",
+                        self.prompt_type
+                    )
+                }
+                (Some(default), _) => info!(
+                    "{} prompt `{prompt}` answered with its default: {default:?}",
+                    self.prompt_type
+                ),
+                (None, _) => info!(
+                    "{} prompt `{prompt}` has no default to answer with",
+                    self.prompt_type
+                ),
+            }
+        }
+
         if let Some(default) = default {
             warn!(
                 "Non-interactive context. The {:?} prompt `{prompt}` will default to {default:#?} .",
@@ -92,9 +119,30 @@ impl<T: Clone + Debug> DefaultPrompt<T> for PseudoInteractive<T> {
     fn set_default(&mut self, value: T) {
         self.default = Some(value);
     }
+
+    /// The default is used right away, so there is nothing to wait for.
+    fn set_timeout(&mut self, _timeout: std::time::Duration) {}
+}
+
+impl ConfirmPrompt<bool> for PseudoInteractive<bool> {
+    fn set_single_keypress(&mut self, _single: bool) {}
 }
 
-impl<T: Clone + Debug> SelectionPrompt<T> for PseudoInteractive<T> {
+impl SelectionPrompt<usize> for PseudoInteractive<usize> {
+    fn add_items(&mut self, items: &[String]) {
+        self.items = Vec::from(items);
+    }
+
+    fn set_allow_custom(&mut self, _allow: bool) {}
+
+    /// Custom values can't be entered without interaction, so this
+    /// always returns the default item.
+    fn interact_custom(&mut self) -> Result<SelectResult, InteractionError> {
+        Ok(SelectResult::Item(self.interact()?))
+    }
+}
+
+impl MultiSelectionPrompt<Vec<usize>> for PseudoInteractive<Vec<usize>> {
     fn add_items(&mut self, items: &[String]) {
         self.items = Vec::from(items);
     }
@@ -120,4 +168,130 @@ impl TextPrompt<String> for PseudoInteractive<String> {
     fn set_inital_text(&mut self, text: String) {
         self.initial_value = Some(text);
     }
+
+    /// Completions need a user typing, so they are ignored.
+    fn set_completions(&mut self, _candidates: Vec<String>) {}
+
+    /// Nothing is echoed, so there is nothing to mask.
+    fn set_partial_mask(&mut self, _keep_visible: Option<usize>) {}
+
+    /// Previous answers can't be recalled without a user typing, and
+    /// defaults aren't worth remembering, so the history is ignored.
+    fn set_history(&mut self, _history: Box<dyn HistoryStore>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_value() {
+        let mut confirm = PseudoInteractive::<bool>::new(PromptType::Confirm);
+        confirm.set_prompt("Continue?".to_string());
+        assert!(confirm.interact().is_err());
+        confirm.set_default(true);
+        assert!(confirm.interact().unwrap());
+    }
+
+    #[test]
+    fn confirm_single_keypress_default() {
+        let mut confirm = PseudoInteractive::<bool>::new(PromptType::Confirm);
+        confirm.set_prompt("Continue?".to_string());
+        confirm.set_single_keypress(true);
+        confirm.set_default(false);
+        assert!(!confirm.interact().unwrap());
+    }
+
+    #[test]
+    fn multi_select_default() {
+        let mut select = PseudoInteractive::with_default(PromptType::MultiSelect, Vec::new());
+        select.set_prompt("Changes to unrecord".to_string());
+        select.add_items(&["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(select.interact().unwrap(), Vec::<usize>::new());
+        select.set_default(vec![0, 2]);
+        assert_eq!(select.interact().unwrap(), vec![0, 2]);
+    }
+
+    #[test]
+    fn timeout_ignored() {
+        let mut select = PseudoInteractive::<usize>::new(PromptType::Select);
+        select.set_prompt("Identity".to_string());
+        select.add_items(&["a".to_string(), "b".to_string()]);
+        select.set_timeout(std::time::Duration::from_secs(0));
+        assert!(select.interact().is_err());
+        select.set_default(1);
+        assert_eq!(select.interact().unwrap(), 1);
+    }
+
+    #[test]
+    fn input_completions_ignored() {
+        let mut input = PseudoInteractive::<String>::new(PromptType::Input);
+        input.set_prompt("Channel".to_string());
+        input.set_inital_text("main".to_string());
+        input.set_completions(vec!["main".to_string(), "feature".to_string()]);
+        assert_eq!(input.interact().unwrap(), "main");
+    }
+
+    #[test]
+    fn input_history_ignored() {
+        struct Remember(Vec<String>);
+        impl HistoryStore for Remember {
+            fn read(&self, pos: usize) -> Option<String> {
+                self.0.get(pos).cloned()
+            }
+            fn write(&mut self, _entry: &str) {
+                panic!("the history shouldn't be written")
+            }
+        }
+        let mut input = PseudoInteractive::<String>::new(PromptType::Input);
+        input.set_prompt("Message".to_string());
+        input.set_default("Fix the parser".to_string());
+        input.set_history(Box::new(Remember(vec!["Add tests".to_string()])));
+        assert_eq!(input.interact().unwrap(), "Fix the parser");
+    }
+
+    #[test]
+    fn input_partial_mask_ignored() {
+        let mut input = PseudoInteractive::<String>::new(PromptType::Input);
+        input.set_prompt("Token".to_string());
+        input.set_default("0123456789abcdef".to_string());
+        input.set_partial_mask(Some(4));
+        assert_eq!(input.interact().unwrap(), "0123456789abcdef");
+    }
+
+    #[test]
+    fn log_answers() {
+        crate::test_log::init();
+        super::super::set_prompt_logging(true);
+
+        let mut confirm = PseudoInteractive::<bool>::new(PromptType::Confirm);
+        confirm.set_prompt("Overwrite the changes?".to_string());
+        confirm.set_default(false);
+        assert!(!confirm.interact().unwrap());
+
+        let mut password = PseudoInteractive::<String>::new(PromptType::Password);
+        password.set_prompt("Password for secret key".to_string());
+        password.set_default("hunter2".to_string());
+        password.interact().unwrap();
+
+        super::super::set_prompt_logging(false);
+        assert_eq!(
+            crate::test_log::records("confirm prompt `Overwrite"),
+            vec!["confirm prompt `Overwrite the changes?` answered with its default: false"]
+        );
+        assert_eq!(
+            crate::test_log::records("password prompt"),
+            vec!["password prompt `Password for secret key` answered with its default"]
+        );
+    }
+
+    #[test]
+    fn select_custom_default() {
+        let mut select = PseudoInteractive::<usize>::new(PromptType::Select);
+        select.add_items(&["a".to_string(), "b".to_string()]);
+        select.set_allow_custom(true);
+        assert!(select.interact_custom().is_err());
+        select.set_default(1);
+        assert_eq!(select.interact_custom().unwrap(), SelectResult::Item(1));
+    }
 }
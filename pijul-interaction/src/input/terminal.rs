@@ -1,50 +1,630 @@
-use super::{BasePrompt, InteractionError, PasswordPrompt, TextPrompt, ValidationPrompt};
-use super::{DefaultPrompt, SelectionPrompt};
-pub use dialoguer::{Confirm, FuzzySelect as Select, Input, Password};
-use duplicate::duplicate_item;
-
-#[duplicate_item(
-    handler       with_generics         return_type;
-    [Confirm]     [Confirm<'_>]         [bool];
-    [Input]       [Input<'_, String>]   [String];
-    [Select] [Select<'_>]     [usize];
-    [Password]    [Password<'_>]        [String];
-)]
-impl BasePrompt<return_type> for with_generics {
+use super::{
+    BasePrompt, HistoryStore, InteractionError, PasswordPrompt, PromptType, TextPrompt,
+    ValidationPrompt,
+};
+use super::{ConfirmPrompt, DefaultPrompt, MultiSelectionPrompt, SelectResult, SelectionPrompt};
+use dialoguer::console::{Key, Term};
+use dialoguer::theme::Theme;
+pub use dialoguer::Password;
+use dialoguer::{Completion, FuzzySelect, History};
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// The item appended to selections allowing custom values
+const CUSTOM_ITEM: &str = "Enter a custom value…";
+
+/// Themes of the global terminal handler.
+type SyncTheme = dyn Theme + Send + Sync;
+
+/// Show `preview` (the lines of the prompt) and wait at most
+/// `timeout` for the user to press a key, then clear the preview and
+/// run `read`, which reads that key first. Returns `None` if no key
+/// came in time, in which case nothing was read from the terminal.
+fn read_with_timeout<T>(
+    timeout: Option<Duration>,
+    preview: impl FnOnce() -> Vec<String>,
+    read: impl FnOnce() -> Result<T, InteractionError>,
+) -> Result<Option<T>, InteractionError> {
+    let term = Term::stderr();
+    let timeout = match timeout {
+        Some(timeout) if term.is_term() => timeout,
+        _ => return read().map(Some),
+    };
+    let lines = preview();
+    for line in lines.iter() {
+        term.write_line(line)?;
+    }
+    let pressed = poll_key(Instant::now() + timeout);
+    term.clear_last_lines(lines.len())?;
+    if pressed? {
+        read().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Wait until a key is pressed or `deadline` passes, returning
+/// whether a key was pressed. The key is left for the prompt to
+/// read. Canonical mode and echo are turned off while waiting, so
+/// that single keypresses are seen, and the terminal is restored
+/// before returning, including on timeouts.
+#[cfg(unix)]
+fn poll_key(deadline: Instant) -> Result<bool, InteractionError> {
+    use std::os::unix::io::AsRawFd;
+    let fd = std::io::stdin().as_raw_fd();
+    let mut saved = std::mem::MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(fd, saved.as_mut_ptr()) } != 0 {
+        // Not a terminal, let the prompt deal with it.
+        return Ok(true);
+    }
+    let saved = unsafe { saved.assume_init() };
+    let mut raw = saved;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let pressed = loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let ms = left.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        match unsafe { libc::poll(&mut pollfd, 1, ms) } {
+            0 => break Ok(false),
+            n if n > 0 => break Ok(true),
+            _ => {
+                let e = std::io::Error::last_os_error();
+                if e.kind() != std::io::ErrorKind::Interrupted {
+                    break Err(e.into());
+                }
+            }
+        }
+    };
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+    pressed
+}
+
+/// The terminal can't be polled on this platform, so prompts wait
+/// for the user regardless of their timeout.
+#[cfg(not(unix))]
+fn poll_key(_deadline: Instant) -> Result<bool, InteractionError> {
+    Ok(true)
+}
+
+/// The value of a prompt whose timeout elapsed.
+fn timed_out<T>(
+    default: Option<T>,
+    prompt_type: PromptType,
+    prompt: &Option<String>,
+) -> Result<T, InteractionError> {
+    log::info!("{prompt_type} prompt timed out");
+    default.ok_or_else(|| {
+        InteractionError::NotInteractive(prompt_type, prompt.clone().unwrap_or_default())
+    })
+}
+
+/// A yes/no question. The underlying `dialoguer` prompt is only
+/// built when interacting, after the timeout, if any.
+pub struct Confirm {
+    theme: &'static SyncTheme,
+    prompt: Option<String>,
+    default: Option<bool>,
+    single_keypress: bool,
+    timeout: Option<Duration>,
+}
+
+impl Confirm {
+    pub fn with_theme(theme: &'static SyncTheme) -> Self {
+        Confirm {
+            theme,
+            prompt: None,
+            default: None,
+            single_keypress: true,
+            timeout: None,
+        }
+    }
+}
+
+/// A fuzzy selection, which may fall through to a text input
+pub struct Select {
+    theme: &'static SyncTheme,
+    prompt: Option<String>,
+    items: Vec<String>,
+    default: Option<usize>,
+    allow_custom: bool,
+    timeout: Option<Duration>,
+}
+
+impl Select {
+    pub fn with_theme(theme: &'static SyncTheme) -> Self {
+        Select {
+            theme,
+            prompt: None,
+            items: Vec::new(),
+            default: None,
+            allow_custom: false,
+            timeout: None,
+        }
+    }
+
+    /// Choose an item, which may be [`CUSTOM_ITEM`] if `custom` is
+    /// set. Returns `None` on timeouts.
+    fn read_item(&self, custom: bool) -> Result<Option<usize>, InteractionError> {
+        let theme = self.theme;
+        let prompt = self.prompt.clone();
+        let default = self.default;
+        let mut items = self.items.clone();
+        if custom {
+            items.push(CUSTOM_ITEM.to_string());
+        }
+        let preview = || {
+            let mut lines = vec![String::new()];
+            theme
+                .format_select_prompt(&mut lines[0], prompt.as_deref().unwrap_or(""))
+                .unwrap_or(());
+            for (i, item) in items.iter().enumerate() {
+                let mut line = String::new();
+                theme
+                    .format_select_prompt_item(&mut line, item, default.unwrap_or(0) == i)
+                    .unwrap_or(());
+                lines.push(line)
+            }
+            lines
+        };
+        read_with_timeout(self.timeout, preview, || {
+            let mut select = FuzzySelect::with_theme(theme);
+            if let Some(ref prompt) = prompt {
+                select.with_prompt(prompt.clone());
+            }
+            if let Some(default) = default {
+                select.default(default);
+            }
+            select.items(&items);
+            Ok(select.interact()?)
+        })
+    }
+}
+
+/// A selection of any number of items
+pub struct MultiSelect {
+    theme: &'static SyncTheme,
+    prompt: Option<String>,
+    items: Vec<String>,
+    default: Vec<usize>,
+    timeout: Option<Duration>,
+}
+
+impl MultiSelect {
+    pub fn with_theme(theme: &'static SyncTheme) -> Self {
+        MultiSelect {
+            theme,
+            prompt: None,
+            items: Vec::new(),
+            default: Vec::new(),
+            timeout: None,
+        }
+    }
+}
+
+/// A text input, validated after reading.
+pub struct Input {
+    read: TextRead,
+    validator: Option<Box<dyn Fn(&String) -> Result<(), String>>>,
+    timeout: Option<Duration>,
+}
+
+impl Input {
+    pub fn with_theme(theme: &'static SyncTheme) -> Self {
+        Input {
+            read: TextRead {
+                theme,
+                prompt: None,
+                default: None,
+                initial_text: None,
+                allow_empty: false,
+                completions: Completions(Vec::new()),
+                partial_mask: None,
+                history: None,
+            },
+            validator: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Everything needed to read the value of an [`Input`], without
+/// validating it.
+struct TextRead {
+    theme: &'static SyncTheme,
+    prompt: Option<String>,
+    default: Option<String>,
+    initial_text: Option<String>,
+    allow_empty: bool,
+    completions: Completions,
+    partial_mask: Option<usize>,
+    history: Option<RefCell<Box<dyn HistoryStore>>>,
+}
+
+impl TextRead {
+    /// The first line of the prompt, shown while waiting for a key.
+    fn preview(&self) -> Vec<String> {
+        let mut header = String::new();
+        self.theme
+            .format_input_prompt(
+                &mut header,
+                self.prompt.as_deref().unwrap_or(""),
+                self.default.as_deref(),
+            )
+            .unwrap_or(());
+        vec![header]
+    }
+
+    /// Read the value. If `read_keys` is set, the value is read key
+    /// by key even without completions or history, which keeps a key
+    /// typed before the prompt was shown visible and editable.
+    fn read(&self, read_keys: bool) -> Result<String, InteractionError> {
+        if let Some(keep_visible) = self.partial_mask {
+            return self.read_masked(keep_visible);
+        }
+        // The history must outlive the `dialoguer` prompt borrowing it.
+        let mut history = self.history.as_ref().map(|h| h.borrow_mut());
+        let mut adapter = history.as_mut().map(|h| HistoryAdapter(&mut ***h));
+        let mut input = dialoguer::Input::<String>::with_theme(self.theme);
+        if let Some(ref prompt) = self.prompt {
+            input.with_prompt(prompt.clone());
+        }
+        if let Some(ref default) = self.default {
+            input.default(default.clone());
+        }
+        if let Some(ref text) = self.initial_text {
+            input.with_initial_text(text.clone());
+        }
+        input.allow_empty(self.allow_empty);
+        if self.completions.0.is_empty() && adapter.is_none() && !read_keys {
+            return Ok(input.interact()?);
+        }
+        // Only `interact_text` reads keystrokes, and hence handles
+        // tab-completion and the arrow keys.
+        if !self.completions.0.is_empty() {
+            input.completion_with(&self.completions);
+        }
+        if let Some(ref mut adapter) = adapter {
+            input.history_with(adapter);
+        }
+        Ok(input.interact_text()?)
+    }
+
+    /// Read a line of input, echoing it with all but the last
+    /// `keep_visible` characters replaced by `*`. `dialoguer` has no
+    /// such prompt, hence this simple line editor.
+    fn read_masked(&self, keep_visible: usize) -> Result<String, InteractionError> {
+        let term = Term::stderr();
+        let prompt = self.prompt.as_deref().unwrap_or("");
+        let mut header = String::new();
+        self.theme
+            .format_input_prompt(&mut header, prompt, self.default.as_deref())
+            .unwrap_or(());
+        let mut text = self.initial_text.clone().unwrap_or_default();
+        loop {
+            loop {
+                term.clear_line()?;
+                term.write_str(&header)?;
+                term.write_str(&partial_mask(&text, keep_visible))?;
+                match term.read_key()? {
+                    Key::Enter => break,
+                    Key::Backspace => {
+                        text.pop();
+                    }
+                    Key::Char(c) if !c.is_control() => text.push(c),
+                    _ => {}
+                }
+            }
+            term.clear_line()?;
+            let value = match self.default {
+                Some(ref default) if text.is_empty() => default.clone(),
+                _ => std::mem::take(&mut text),
+            };
+            if value.is_empty() && !self.allow_empty {
+                continue;
+            }
+            let mut selection = String::new();
+            self.theme
+                .format_input_prompt_selection(
+                    &mut selection,
+                    prompt,
+                    &partial_mask(&value, keep_visible),
+                )
+                .unwrap_or(());
+            term.write_line(&selection)?;
+            return Ok(value);
+        }
+    }
+}
+
+/// Replace all but the last `keep_visible` characters of `text` by `*`.
+fn partial_mask(text: &str, keep_visible: usize) -> String {
+    let n = text.chars().count();
+    text.chars()
+        .enumerate()
+        .map(|(i, c)| if i + keep_visible < n { '*' } else { c })
+        .collect()
+}
+
+/// Tab-completion candidates for an [`Input`].
+#[derive(Clone)]
+struct Completions(Vec<String>);
+
+impl Completion for Completions {
+    /// Complete `input` to the longest prefix shared by all the
+    /// candidates starting with `input`.
+    fn get(&self, input: &str) -> Option<String> {
+        let mut candidates = self.0.iter().filter(|c| c.starts_with(input));
+        let mut prefix: &str = candidates.next()?;
+        for c in candidates {
+            let len = prefix
+                .char_indices()
+                .zip(c.chars())
+                .find(|((_, a), b)| a != b)
+                .map(|((i, _), _)| i)
+                .unwrap_or_else(|| prefix.len().min(c.len()));
+            prefix = &prefix[..len];
+        }
+        if prefix.len() > input.len() {
+            Some(prefix.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Expose a [`HistoryStore`] to `dialoguer`.
+struct HistoryAdapter<'a>(&'a mut dyn HistoryStore);
+
+impl History<String> for HistoryAdapter<'_> {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.0.read(pos)
+    }
+
+    fn write(&mut self, val: &String) {
+        self.0.write(val)
+    }
+}
+
+impl BasePrompt<String> for Input {
+    fn set_prompt(&mut self, prompt: String) {
+        self.read.prompt = Some(prompt);
+    }
+
+    fn interact(&mut self) -> Result<String, InteractionError> {
+        loop {
+            let read_keys = self.timeout.is_some();
+            let value = match read_with_timeout(
+                self.timeout,
+                || self.read.preview(),
+                || self.read.read(read_keys),
+            )? {
+                Some(value) => value,
+                None => {
+                    return timed_out(
+                        self.read.default.clone(),
+                        PromptType::Input,
+                        &self.read.prompt,
+                    )
+                }
+            };
+            if let Some(Err(e)) = self.validator.as_ref().map(|v| v(&value)) {
+                let mut err = String::new();
+                self.read.theme.format_error(&mut err, &e).unwrap_or(());
+                Term::stderr().write_line(&err)?;
+                continue;
+            }
+            return Ok(value);
+        }
+    }
+}
+
+impl DefaultPrompt<String> for Input {
+    fn set_default(&mut self, value: String) {
+        self.read.default = Some(value);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+}
+
+impl BasePrompt<String> for Password<'_> {
     fn set_prompt(&mut self, prompt: String) {
         self.with_prompt(prompt);
     }
 
-    fn interact(&mut self) -> Result<return_type, InteractionError> {
-        Ok(handler::interact(self)?)
+    fn interact(&mut self) -> Result<String, InteractionError> {
+        Ok(Password::interact(self)?)
+    }
+}
+
+impl BasePrompt<bool> for Confirm {
+    fn set_prompt(&mut self, prompt: String) {
+        self.prompt = Some(prompt);
+    }
+
+    fn interact(&mut self) -> Result<bool, InteractionError> {
+        let theme = self.theme;
+        let prompt = self.prompt.clone();
+        let default = self.default;
+        let single_keypress = self.single_keypress;
+        let preview = || {
+            let mut line = String::new();
+            theme
+                .format_confirm_prompt(&mut line, prompt.as_deref().unwrap_or(""), default)
+                .unwrap_or(());
+            vec![line]
+        };
+        let answer = read_with_timeout(self.timeout, preview, || {
+            let mut confirm = dialoguer::Confirm::with_theme(theme);
+            if let Some(ref prompt) = prompt {
+                confirm.with_prompt(prompt.clone());
+            }
+            if let Some(default) = default {
+                confirm.default(default);
+            }
+            // `dialoguer` reads the key in raw mode, and restores the
+            // terminal before returning, including on errors.
+            confirm.wait_for_newline(!single_keypress);
+            Ok(confirm.interact()?)
+        })?;
+        match answer {
+            Some(answer) => Ok(answer),
+            None => timed_out(self.default, PromptType::Confirm, &self.prompt),
+        }
+    }
+}
+
+impl DefaultPrompt<bool> for Confirm {
+    fn set_default(&mut self, value: bool) {
+        self.default = Some(value);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+}
+
+impl ConfirmPrompt<bool> for Confirm {
+    fn set_single_keypress(&mut self, single: bool) {
+        self.single_keypress = single;
+    }
+}
+
+impl BasePrompt<usize> for Select {
+    fn set_prompt(&mut self, prompt: String) {
+        self.prompt = Some(prompt);
+    }
+
+    fn interact(&mut self) -> Result<usize, InteractionError> {
+        match self.read_item(false)? {
+            Some(item) => Ok(item),
+            None => timed_out(self.default, PromptType::Select, &self.prompt),
+        }
+    }
+}
+
+impl DefaultPrompt<usize> for Select {
+    fn set_default(&mut self, value: usize) {
+        self.default = Some(value);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+}
+
+impl SelectionPrompt<usize> for Select {
+    fn add_items(&mut self, items: &[String]) {
+        self.items.extend(items.iter().cloned());
+    }
+
+    fn set_allow_custom(&mut self, allow: bool) {
+        self.allow_custom = allow;
+    }
+
+    fn interact_custom(&mut self) -> Result<SelectResult, InteractionError> {
+        if !self.allow_custom {
+            return Ok(SelectResult::Item(self.interact()?));
+        }
+        let selection = match self.read_item(true)? {
+            Some(selection) => selection,
+            None => timed_out(self.default, PromptType::Select, &self.prompt)?,
+        };
+        if selection < self.items.len() {
+            Ok(SelectResult::Item(selection))
+        } else {
+            // The user is there, no need for a timeout.
+            let mut input = dialoguer::Input::<String>::with_theme(self.theme);
+            if let Some(ref prompt) = self.prompt {
+                input.with_prompt(prompt.clone());
+            }
+            Ok(SelectResult::Custom(input.interact_text()?))
+        }
+    }
+}
+
+impl BasePrompt<Vec<usize>> for MultiSelect {
+    fn set_prompt(&mut self, prompt: String) {
+        self.prompt = Some(prompt);
+    }
+
+    fn interact(&mut self) -> Result<Vec<usize>, InteractionError> {
+        let theme = self.theme;
+        let prompt = self.prompt.clone();
+        let items = self.items.clone();
+        let mut checked = vec![false; items.len()];
+        for &i in self.default.iter() {
+            if let Some(c) = checked.get_mut(i) {
+                *c = true
+            }
+        }
+        let preview = || {
+            let mut lines = vec![String::new()];
+            theme
+                .format_multi_select_prompt(&mut lines[0], prompt.as_deref().unwrap_or(""))
+                .unwrap_or(());
+            for (i, item) in items.iter().enumerate() {
+                let mut line = String::new();
+                theme
+                    .format_multi_select_prompt_item(&mut line, item, checked[i], i == 0)
+                    .unwrap_or(());
+                lines.push(line)
+            }
+            lines
+        };
+        let selection = read_with_timeout(self.timeout, preview, || {
+            let mut select = dialoguer::MultiSelect::with_theme(theme);
+            if let Some(ref prompt) = prompt {
+                select.with_prompt(prompt.clone());
+            }
+            select.items(&items);
+            select.defaults(&checked);
+            Ok(select.interact()?)
+        })?;
+        match selection {
+            Some(selection) => Ok(selection),
+            None => timed_out(
+                Some(self.default.clone()),
+                PromptType::MultiSelect,
+                &self.prompt,
+            ),
+        }
     }
 }
 
-#[duplicate_item(
-    handler       with_generics         return_type;
-    [Confirm]     [Confirm<'_>]         [bool];
-    [Input]       [Input<'_, String>]   [String];
-    [Select] [Select<'_>]     [usize];
-)]
-impl DefaultPrompt<return_type> for with_generics {
-    fn set_default(&mut self, value: return_type) {
-        self.default(value);
+impl DefaultPrompt<Vec<usize>> for MultiSelect {
+    fn set_default(&mut self, value: Vec<usize>) {
+        self.default = value;
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
     }
 }
 
-impl SelectionPrompt<usize> for Select<'_> {
+impl MultiSelectionPrompt<Vec<usize>> for MultiSelect {
     fn add_items(&mut self, items: &[String]) {
-        Select::items(self, items);
+        self.items.extend(items.iter().cloned());
     }
 }
 
-impl ValidationPrompt<String> for Input<'_, String> {
+impl ValidationPrompt<String> for Input {
     fn allow_empty(&mut self, empty: bool) {
-        self.allow_empty(empty);
+        self.read.allow_empty = empty;
     }
 
     fn set_validator(&mut self, validator: Box<dyn Fn(&String) -> Result<(), String>>) {
-        self.validate_with(validator);
+        self.validator = Some(validator);
     }
 }
 
@@ -64,8 +644,44 @@ impl PasswordPrompt<String> for Password<'_> {
     }
 }
 
-impl TextPrompt<String> for Input<'_, String> {
+impl TextPrompt<String> for Input {
     fn set_inital_text(&mut self, text: String) {
-        self.with_initial_text(text);
+        self.read.initial_text = Some(text);
+    }
+
+    fn set_completions(&mut self, candidates: Vec<String>) {
+        self.read.completions = Completions(candidates);
+    }
+
+    fn set_partial_mask(&mut self, keep_visible: Option<usize>) {
+        self.read.partial_mask = keep_visible;
+    }
+
+    fn set_history(&mut self, history: Box<dyn HistoryStore>) {
+        self.read.history = Some(RefCell::new(history));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_timeout() {
+        // Without a timeout, the prompt is read right away.
+        let read = read_with_timeout(None, Vec::new, || Ok(1));
+        assert_eq!(read.unwrap(), Some(1));
+        // Without a terminal, there is nothing to wait for.
+        if !Term::stderr().is_term() {
+            assert_eq!(
+                read_with_timeout(Some(Duration::from_millis(10)), Vec::new, || Ok(2)).unwrap(),
+                Some(2)
+            );
+        }
+        assert!(timed_out(Some(true), PromptType::Confirm, &None).unwrap());
+        assert!(matches!(
+            timed_out::<bool>(None, PromptType::Confirm, &None),
+            Err(InteractionError::NotInteractive(..))
+        ));
     }
 }
@@ -3,9 +3,14 @@
 mod input;
 mod progress;
 
-use input::{DefaultPrompt, PasswordPrompt, SelectionPrompt, TextPrompt};
-use progress::{ProgressBarTrait, SpinnerTrait};
-use std::sync::OnceLock;
+pub use input::{prompt_logging, set_prompt_logging};
+use input::{ConfirmPrompt, MultiSelectionPrompt, PasswordPrompt, SelectionPrompt, TextPrompt};
+pub use input::{FileHistory, HistoryStore};
+pub use progress::{
+    progress_logging, progress_width, set_progress_logging, set_progress_width, Unit,
+};
+use progress::{MultiProgressTrait, ProgressBarTrait, SpinnerTrait};
+use std::sync::RwLock;
 
 // TODO: these should be replaced with a more sophisticated localization system
 pub const DOWNLOAD_MESSAGE: &str = "Downloading changes";
@@ -14,14 +19,15 @@ pub const UPLOAD_MESSAGE: &str = "Uploading changes";
 pub const COMPLETE_MESSAGE: &str = "Completing changes";
 pub const OUTPUT_MESSAGE: &str = "Outputting repository";
 
-/// Global state for setting interactivity. Should be set to `Option::None`
-/// if no interactivity is possible, for example running Pijul with `--no-prompt`.
-static INTERACTIVE_CONTEXT: OnceLock<InteractiveContext> = OnceLock::new();
+/// Global state for setting interactivity. Should be set to
+/// `InteractiveContext::NotInteractive` if no interactivity is possible,
+/// for example running Pijul with `--no-prompt`.
+static INTERACTIVE_CONTEXT: RwLock<Option<InteractiveContext>> = RwLock::new(None);
 
 /// Get the interactive context. If not set, returns an error.
 pub fn get_context() -> Result<InteractiveContext, InteractionError> {
-    if let Some(context) = INTERACTIVE_CONTEXT.get() {
-        Ok(*context)
+    if let Some(context) = *INTERACTIVE_CONTEXT.read().unwrap() {
+        Ok(context)
     } else {
         Err(InteractionError::NoContext)
     }
@@ -29,10 +35,38 @@ pub fn get_context() -> Result<InteractiveContext, InteractionError> {
 
 /// Set the interactive context, panicking if already set.
 pub fn set_context(value: InteractiveContext) {
+    try_set_context(value).expect("Interactive context is already set!");
+}
+
+/// Set the interactive context, or return an error if it is already
+/// set.
+pub fn try_set_context(value: InteractiveContext) -> Result<(), InteractionError> {
     // There probably isn't any reason for changing contexts at runtime
-    INTERACTIVE_CONTEXT
-        .set(value)
-        .expect("Interactive context is already set!");
+    let mut context = INTERACTIVE_CONTEXT.write().unwrap();
+    if context.is_some() {
+        return Err(InteractionError::ContextAlreadySet);
+    }
+    *context = Some(value);
+    Ok(())
+}
+
+/// Set the interactive context if it isn't set yet, returning whether
+/// it was set. An existing context is left untouched, which lets
+/// libraries provide a default without knowing whether the
+/// application already chose a context.
+pub fn init_context(value: InteractiveContext) -> bool {
+    let mut context = INTERACTIVE_CONTEXT.write().unwrap();
+    if context.is_some() {
+        return false;
+    }
+    *context = Some(value);
+    true
+}
+
+/// Unset the interactive context, so that it can be set again.
+#[cfg(test)]
+pub fn reset_context_for_testing() {
+    *INTERACTIVE_CONTEXT.write().unwrap() = None
 }
 
 /// The different kinds of available prompts
@@ -43,6 +77,7 @@ pub enum PromptType {
     Input,
     Select,
     Password,
+    MultiSelect,
 }
 
 impl core::fmt::Display for PromptType {
@@ -52,6 +87,7 @@ impl core::fmt::Display for PromptType {
             Self::Input => "input",
             Self::Select => "fuzzy selection",
             Self::Password => "password",
+            Self::MultiSelect => "multiple selection",
         };
 
         write!(f, "{name}")
@@ -64,6 +100,8 @@ impl core::fmt::Display for PromptType {
 pub enum InteractionError {
     #[error("mode of interactivity not set")]
     NoContext,
+    #[error("mode of interactivity already set")]
+    ContextAlreadySet,
     #[error("unable to provide interactivity in this context, and no valid default value for {0} prompt `{1}`")]
     NotInteractive(PromptType, String),
     #[error("I/O error while interacting with terminal")]
@@ -76,14 +114,56 @@ pub enum InteractionError {
 pub enum InteractiveContext {
     Terminal,
     NotInteractive,
+    /// Prompts are written to stdout as JSON objects, one per line,
+    /// and answered by JSON values read from stdin, one per line. This
+    /// is meant for programs driving Pijul.
+    Json,
+}
+
+impl InteractiveContext {
+    /// Decide the context from the environment: prompts are disabled if
+    /// `force_non_interactive` is set (for example by `--no-prompt`), if
+    /// stdin or stderr isn't a terminal, or if the `CI` environment
+    /// variable is set.
+    pub fn from_env(force_non_interactive: bool) -> InteractiveContext {
+        use std::io::IsTerminal;
+        Self::decide(
+            force_non_interactive,
+            std::env::var_os("CI").is_some(),
+            std::io::stdin().is_terminal() && std::io::stderr().is_terminal(),
+        )
+    }
+
+    /// [`Self::from_env`], given whether `CI` is set and whether both
+    /// stdin and stderr are terminals.
+    fn decide(force_non_interactive: bool, ci: bool, terminal: bool) -> InteractiveContext {
+        if force_non_interactive || ci || !terminal {
+            InteractiveContext::NotInteractive
+        } else {
+            InteractiveContext::Terminal
+        }
+    }
 }
 
 /// A prompt that asks the user to select yes or no
-pub struct Confirm(Box<dyn DefaultPrompt<bool>>);
+pub struct Confirm(Box<dyn ConfirmPrompt<bool>>);
 
 /// A prompt that asks the user to choose from a list of items.
 pub struct Select(Box<dyn SelectionPrompt<usize>>);
 
+/// A prompt that asks the user to choose any number of items from a
+/// list.
+pub struct MultiSelect(Box<dyn MultiSelectionPrompt<Vec<usize>>>);
+
+/// The result of a [`Select`] prompt allowing custom values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelectResult {
+    /// The index of the chosen item
+    Item(usize),
+    /// A value entered by the user
+    Custom(String),
+}
+
 /// A prompt that asks the user to enter text input
 pub struct Input(Box<dyn TextPrompt<String>>);
 
@@ -93,5 +173,123 @@ pub struct Password(Box<dyn PasswordPrompt<String>>);
 /// A progress bar that is controlled by code
 pub struct ProgressBar(Box<dyn ProgressBarTrait>);
 
+/// A group of progress bars drawn together, one per line, for
+/// operations with several phases running at the same time
+pub struct MultiProgress(Box<dyn MultiProgressTrait>);
+
 /// An animated progress bar to indicate activity
 pub struct Spinner(Box<dyn SpinnerTrait>);
+
+/// A logger recording every message, for tests checking what gets
+/// logged. Only one logger can be set per process, so all tests share
+/// this one.
+#[cfg(test)]
+pub(crate) mod test_log {
+    use std::sync::{Mutex, Once};
+
+    struct TestLogger(Mutex<Vec<String>>);
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(record.args().to_string())
+        }
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+    static INIT: Once = Once::new();
+
+    pub fn init() {
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Info);
+        })
+    }
+
+    /// The messages logged so far that start with `prefix`.
+    pub fn records(prefix: &str) -> Vec<String> {
+        LOGGER
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialises the tests that change the global context.
+    static CONTEXT_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn set_context_twice() {
+        let _lock = CONTEXT_LOCK.lock().unwrap();
+        reset_context_for_testing();
+        assert!(matches!(get_context(), Err(InteractionError::NoContext)));
+        try_set_context(InteractiveContext::NotInteractive).unwrap();
+        assert!(matches!(
+            try_set_context(InteractiveContext::Terminal),
+            Err(InteractionError::ContextAlreadySet)
+        ));
+        assert!(matches!(
+            get_context(),
+            Ok(InteractiveContext::NotInteractive)
+        ));
+        reset_context_for_testing();
+        try_set_context(InteractiveContext::Json).unwrap();
+        assert!(matches!(get_context(), Ok(InteractiveContext::Json)));
+        reset_context_for_testing();
+    }
+
+    #[test]
+    fn init_context_once() {
+        let _lock = CONTEXT_LOCK.lock().unwrap();
+        reset_context_for_testing();
+        assert!(init_context(InteractiveContext::Json));
+        assert!(matches!(get_context(), Ok(InteractiveContext::Json)));
+
+        // Already set, by `init_context` or `set_context`.
+        assert!(!init_context(InteractiveContext::Terminal));
+        assert!(matches!(get_context(), Ok(InteractiveContext::Json)));
+        reset_context_for_testing();
+        set_context(InteractiveContext::NotInteractive);
+        assert!(!init_context(InteractiveContext::Json));
+        assert!(matches!(
+            get_context(),
+            Ok(InteractiveContext::NotInteractive)
+        ));
+        reset_context_for_testing();
+    }
+
+    #[test]
+    fn context_from_env() {
+        assert!(matches!(
+            InteractiveContext::from_env(true),
+            InteractiveContext::NotInteractive
+        ));
+
+        assert!(matches!(
+            InteractiveContext::decide(false, false, true),
+            InteractiveContext::Terminal
+        ));
+        for (force, ci, terminal) in [
+            (true, false, true),
+            (false, true, true),
+            (false, false, false),
+            (true, true, false),
+        ] {
+            assert!(matches!(
+                InteractiveContext::decide(force, ci, terminal),
+                InteractiveContext::NotInteractive
+            ));
+        }
+    }
+}
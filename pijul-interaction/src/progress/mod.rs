@@ -1,20 +1,101 @@
+mod non_interactive;
+#[cfg(feature = "terminal")]
 mod terminal;
 
-use super::{ProgressBar, Spinner};
+use super::{MultiProgress, ProgressBar, Spinner};
 use crate::{InteractionError, InteractiveContext};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The width forced by `set_progress_width`, 0 meaning unset.
+static PROGRESS_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether progress is reported through `log`, see `set_progress_logging`.
+static PROGRESS_LOGGING: AtomicBool = AtomicBool::new(false);
+
+/// Progress lines are never drawn narrower than this, even if the
+/// terminal reports a width of 0 (as some CI terminals do).
+const MIN_PROGRESS_WIDTH: usize = 20;
+
+/// Force the width of progress bars, in columns. If unset (`None`,
+/// the default), the `COLUMNS` environment variable is used if it is
+/// set, and the width is detected automatically otherwise. Widths
+/// below 20 columns are rounded up.
+pub fn set_progress_width(width: Option<usize>) {
+    PROGRESS_WIDTH.store(
+        width.map(|w| w.max(MIN_PROGRESS_WIDTH)).unwrap_or(0),
+        Ordering::Relaxed,
+    )
+}
+
+/// The width progress bars should be drawn with, or `None` for
+/// automatic detection.
+pub fn progress_width() -> Option<usize> {
+    match PROGRESS_WIDTH.load(Ordering::Relaxed) {
+        0 => std::env::var("COLUMNS")
+            .ok()
+            .and_then(|c| c.trim().parse::<usize>().ok())
+            .map(|c| c.max(MIN_PROGRESS_WIDTH)),
+        width => Some(width),
+    }
+}
+
+/// Report progress through the `log` crate, as periodic `info!` lines
+/// with a percentage, instead of drawing progress bars and spinners on
+/// the terminal. This keeps the output readable when a logger writes
+/// to the same terminal.
+pub fn set_progress_logging(enabled: bool) {
+    PROGRESS_LOGGING.store(enabled, Ordering::Relaxed)
+}
+
+/// Whether progress is reported through the `log` crate.
+pub fn progress_logging() -> bool {
+    PROGRESS_LOGGING.load(Ordering::Relaxed)
+}
+
+/// What a progress bar counts, which determines how its position and
+/// rate are displayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Unit {
+    /// Discrete items, such as changes, shown as a count per second.
+    #[default]
+    Items,
+    /// Bytes, shown with binary prefixes (KiB, MiB…).
+    Bytes,
+}
 
 pub trait ProgressBarTrait: Send {
     fn inc(&self, delta: u64);
+    fn set_position(&self, pos: u64);
+    fn set_unit(&self, unit: Unit);
     fn finish(&self);
     fn boxed_clone(&self) -> Box<dyn ProgressBarTrait>;
 }
 
 impl ProgressBar {
     pub fn new<S: ToString>(len: u64, message: S) -> Result<ProgressBar, InteractionError> {
-        Ok(Self(match crate::get_context()? {
-            InteractiveContext::Terminal | InteractiveContext::NotInteractive => {
+        let context = crate::get_context()?;
+        if progress_logging() {
+            return Ok(Self(Box::new(non_interactive::new_progress(
+                len,
+                message.to_string(),
+                true,
+            ))));
+        }
+        Ok(Self(match context {
+            #[cfg(feature = "terminal")]
+            InteractiveContext::Terminal
+            | InteractiveContext::NotInteractive
+            | InteractiveContext::Json => {
                 Box::new(terminal::new_progress(len, message.to_string()))
             }
+            #[cfg(not(feature = "terminal"))]
+            InteractiveContext::Terminal
+            | InteractiveContext::NotInteractive
+            | InteractiveContext::Json => Box::new(non_interactive::new_progress(
+                len,
+                message.to_string(),
+                false,
+            )),
         }))
     }
 
@@ -22,6 +103,23 @@ impl ProgressBar {
         self.0.inc(delta);
     }
 
+    /// Move the progress bar to `pos`, for example after a resumed
+    /// download.
+    pub fn set_position(&self, pos: u64) {
+        self.0.set_position(pos);
+    }
+
+    /// Set what this progress bar counts. Default: [`Unit::Items`].
+    pub fn set_unit(&self, unit: Unit) {
+        self.0.set_unit(unit);
+    }
+
+    /// Builder pattern for [`Self::set_unit`]
+    pub fn with_unit(self, unit: Unit) -> Self {
+        self.set_unit(unit);
+        self
+    }
+
     fn finish(&self) {
         self.0.finish()
     }
@@ -39,6 +137,45 @@ impl Clone for ProgressBar {
     }
 }
 
+pub trait MultiProgressTrait: Send {
+    fn add(&self, len: u64, message: String) -> Box<dyn ProgressBarTrait>;
+    fn boxed_clone(&self) -> Box<dyn MultiProgressTrait>;
+}
+
+impl MultiProgress {
+    /// Create an empty group of progress bars. Outside of a terminal,
+    /// the bars added to it are never drawn.
+    pub fn new() -> Result<MultiProgress, InteractionError> {
+        let context = crate::get_context()?;
+        if progress_logging() {
+            return Ok(Self(Box::new(non_interactive::LogMultiProgress)));
+        }
+        Ok(Self(match context {
+            #[cfg(feature = "terminal")]
+            InteractiveContext::Terminal => Box::new(terminal::new_multi_progress(false)),
+            #[cfg(feature = "terminal")]
+            InteractiveContext::NotInteractive | InteractiveContext::Json => {
+                Box::new(terminal::new_multi_progress(true))
+            }
+            #[cfg(not(feature = "terminal"))]
+            InteractiveContext::Terminal
+            | InteractiveContext::NotInteractive
+            | InteractiveContext::Json => Box::new(non_interactive::LogMultiProgress),
+        }))
+    }
+
+    /// Add a progress bar below the ones already in this group.
+    pub fn add<S: ToString>(&self, len: u64, message: S) -> ProgressBar {
+        ProgressBar(self.0.add(len, message.to_string()))
+    }
+}
+
+impl Clone for MultiProgress {
+    fn clone(&self) -> Self {
+        Self(self.0.boxed_clone())
+    }
+}
+
 pub trait SpinnerTrait: Send {
     fn finish(&self);
     fn boxed_clone(&self) -> Box<dyn SpinnerTrait>;
@@ -46,9 +183,22 @@ pub trait SpinnerTrait: Send {
 
 impl Spinner {
     pub fn new<S: ToString>(message: S) -> Result<Spinner, InteractionError> {
-        Ok(Self(match crate::get_context()? {
-            InteractiveContext::Terminal | InteractiveContext::NotInteractive => {
-                Box::new(terminal::new_spinner(message.to_string()))
+        let context = crate::get_context()?;
+        if progress_logging() {
+            return Ok(Self(Box::new(non_interactive::new_spinner(
+                message.to_string(),
+            ))));
+        }
+        Ok(Self(match context {
+            #[cfg(feature = "terminal")]
+            InteractiveContext::Terminal
+            | InteractiveContext::NotInteractive
+            | InteractiveContext::Json => Box::new(terminal::new_spinner(message.to_string())),
+            #[cfg(not(feature = "terminal"))]
+            InteractiveContext::Terminal
+            | InteractiveContext::NotInteractive
+            | InteractiveContext::Json => {
+                Box::new(non_interactive::new_spinner(message.to_string()))
             }
         }))
     }
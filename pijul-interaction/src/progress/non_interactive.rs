@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{MultiProgressTrait, ProgressBarTrait, SpinnerTrait, Unit};
+use log::info;
+
+/// Progress reporting for contexts without a terminal, which logs
+/// the start and end of each task, and optionally every tenth of the
+/// way in between.
+pub struct LogProgress {
+    message: String,
+    len: Option<u64>,
+    pos: AtomicU64,
+    periodic: bool,
+}
+
+impl LogProgress {
+    pub fn position(&self) -> u64 {
+        self.pos.load(Ordering::Relaxed)
+    }
+
+    /// Log the position if it moved to another tenth of the way.
+    fn log_step(&self, old: u64, new: u64) {
+        match self.len {
+            Some(len) if self.periodic && len > 0 => {
+                if old * 10 / len != new * 10 / len {
+                    info!(
+                        "{}: {}/{} ({}%)",
+                        self.message,
+                        new,
+                        len,
+                        new.min(len) * 100 / len
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn new_progress(len: u64, message: String, periodic: bool) -> Arc<LogProgress> {
+    info!("{message}: 0/{len}");
+    Arc::new(LogProgress {
+        message,
+        len: Some(len),
+        pos: AtomicU64::new(0),
+        periodic,
+    })
+}
+
+impl ProgressBarTrait for Arc<LogProgress> {
+    fn inc(&self, delta: u64) {
+        let old = self.pos.fetch_add(delta, Ordering::Relaxed);
+        self.log_step(old, old + delta)
+    }
+
+    fn set_position(&self, pos: u64) {
+        let old = self.pos.swap(pos, Ordering::Relaxed);
+        self.log_step(old, pos)
+    }
+
+    /// Positions are logged as plain numbers, whatever the unit.
+    fn set_unit(&self, _unit: Unit) {}
+
+    fn finish(&self) {
+        // Only log the end of the task if it's the last reference
+        if Arc::strong_count(self) == 1 {
+            info!(
+                "{}: {}/{}",
+                self.message,
+                self.position(),
+                self.len.unwrap_or(0)
+            );
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn ProgressBarTrait> {
+        Box::new(self.clone())
+    }
+}
+
+/// A group of progress bars without a terminal: each bar is logged
+/// on its own, as if it had been created alone.
+#[derive(Clone, Copy)]
+pub struct LogMultiProgress;
+
+impl MultiProgressTrait for LogMultiProgress {
+    fn add(&self, len: u64, message: String) -> Box<dyn ProgressBarTrait> {
+        Box::new(new_progress(len, message, super::progress_logging()))
+    }
+
+    fn boxed_clone(&self) -> Box<dyn MultiProgressTrait> {
+        Box::new(*self)
+    }
+}
+
+pub fn new_spinner(message: String) -> Arc<LogProgress> {
+    info!("{message}...");
+    Arc::new(LogProgress {
+        message,
+        len: None,
+        pos: AtomicU64::new(0),
+        periodic: false,
+    })
+}
+
+impl SpinnerTrait for Arc<LogProgress> {
+    fn finish(&self) {
+        // Only log the end of the task if it's the last reference
+        if Arc::strong_count(self) == 1 {
+            info!("{}... done!", self.message);
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn SpinnerTrait> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_progress() {
+        let progress = new_progress(3, "Applying changes".to_string(), false);
+        let clone = ProgressBarTrait::boxed_clone(&progress);
+        progress.inc(1);
+        clone.inc(2);
+        assert_eq!(progress.position(), 3);
+        progress.set_unit(Unit::Bytes);
+        clone.set_position(1);
+        assert_eq!(progress.position(), 1);
+        progress.inc(2);
+        clone.finish();
+        drop(clone);
+        ProgressBarTrait::finish(&progress);
+
+        let spinner = new_spinner("Downloading changes".to_string());
+        SpinnerTrait::finish(&spinner);
+    }
+
+    #[test]
+    fn log_multi_progress() {
+        let multi = MultiProgressTrait::boxed_clone(&LogMultiProgress);
+        let download = multi.add(2, "Downloading changes".to_string());
+        let apply = multi.add(2, "Applying changes".to_string());
+        download.inc(2);
+        apply.inc(1);
+        download.finish();
+        apply.finish();
+    }
+
+    #[test]
+    fn periodic_log_progress() {
+        crate::test_log::init();
+
+        let progress = new_progress(20, "Periodic progress".to_string(), true);
+        for _ in 0..20 {
+            progress.inc(1)
+        }
+        let records = crate::test_log::records("Periodic progress");
+        assert_eq!(records.len(), 11);
+        assert_eq!(records[1], "Periodic progress: 2/20 (10%)");
+        assert_eq!(records[10], "Periodic progress: 20/20 (100%)");
+    }
+}
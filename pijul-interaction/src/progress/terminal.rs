@@ -1,33 +1,95 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use super::{ProgressBarTrait, SpinnerTrait};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use super::{MultiProgressTrait, ProgressBarTrait, SpinnerTrait, Unit};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use lazy_static::lazy_static;
 
 lazy_static! {
     static ref MULTI_PROGRESS: MultiProgress = MultiProgress::new();
 }
 
-pub fn new_progress(len: u64, message: String) -> Arc<ProgressBar> {
-    let style =
-        ProgressStyle::with_template("{msg:<20} [{bar:50}] {pos}/{len} [{elapsed_precise}]")
-            .unwrap()
-            .progress_chars("=> ");
+/// Width of the bar itself when the terminal width is detected automatically
+const DEFAULT_BAR_WIDTH: usize = 50;
+/// Space taken by everything but the bar on a progress line
+const BAR_OVERHEAD: usize = 70;
+/// Extra space taken by sizes and rates when counting bytes
+const BYTES_OVERHEAD: usize = 16;
+/// The bar is never made narrower than this
+const MIN_BAR_WIDTH: usize = 10;
+
+/// The template used for progress bars, given a total line width
+/// (`None` meaning that the width is detected automatically).
+pub(crate) fn progress_template(width: Option<usize>, unit: Unit) -> String {
+    let overhead = match unit {
+        Unit::Items => BAR_OVERHEAD,
+        Unit::Bytes => BAR_OVERHEAD + BYTES_OVERHEAD,
+    };
+    let bar_width = match width {
+        Some(width) => width.saturating_sub(overhead).max(MIN_BAR_WIDTH),
+        None => DEFAULT_BAR_WIDTH,
+    };
+    let (position, rate) = match unit {
+        Unit::Items => ("{pos}/{len}", "{per_sec}"),
+        Unit::Bytes => ("{bytes}/{total_bytes}", "{binary_bytes_per_sec}"),
+    };
+    format!("{{msg:<20}} [{{bar:{bar_width}}}] {position} {rate} ETA {{eta}} [{{elapsed_precise}}]")
+}
+
+fn progress_style(unit: Unit) -> ProgressStyle {
+    ProgressStyle::with_template(&progress_template(super::progress_width(), unit))
+        .unwrap()
+        .progress_chars("=> ")
+}
+
+/// Create a progress bar drawn by `multi_progress`.
+fn add_progress(multi_progress: &MultiProgress, len: u64, message: String) -> Arc<ProgressBar> {
     let progress_bar = ProgressBar::new(len)
-        .with_style(style)
+        .with_style(progress_style(Unit::Items))
         .with_message(message);
-    MULTI_PROGRESS.add(progress_bar.clone());
+    multi_progress.add(progress_bar.clone());
     progress_bar.enable_steady_tick(Duration::from_millis(15));
 
     Arc::new(progress_bar)
 }
 
+pub fn new_progress(len: u64, message: String) -> Arc<ProgressBar> {
+    add_progress(&MULTI_PROGRESS, len, message)
+}
+
+/// A group of progress bars drawn together on standard error, or
+/// never drawn if `hidden`.
+pub fn new_multi_progress(hidden: bool) -> MultiProgress {
+    if hidden {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
+
+impl MultiProgressTrait for MultiProgress {
+    fn add(&self, len: u64, message: String) -> Box<dyn ProgressBarTrait> {
+        Box::new(add_progress(self, len, message))
+    }
+
+    fn boxed_clone(&self) -> Box<dyn MultiProgressTrait> {
+        Box::new(self.clone())
+    }
+}
+
 impl ProgressBarTrait for Arc<ProgressBar> {
     fn inc(&self, delta: u64) {
         self.as_ref().inc(delta);
     }
 
+    fn set_position(&self, pos: u64) {
+        self.as_ref().set_position(pos);
+    }
+
+    fn set_unit(&self, unit: Unit) {
+        self.set_style(progress_style(unit));
+    }
+
     fn finish(&self) {
         // Only finish the progress bar if it's the last reference
         if Arc::strong_count(self) == 1 {
@@ -66,3 +128,61 @@ impl SpinnerTrait for Arc<ProgressBar> {
         Box::new(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        new_multi_progress, new_progress, progress_template, MultiProgressTrait, ProgressBarTrait,
+        Unit,
+    };
+
+    #[test]
+    fn explicit_width() {
+        assert!(progress_template(None, Unit::Items).contains("{bar:50}"));
+        assert!(progress_template(Some(100), Unit::Items).contains("{bar:30}"));
+        assert!(progress_template(Some(20), Unit::Items).contains("{bar:10}"));
+
+        crate::set_progress_width(Some(100));
+        assert!(progress_template(crate::progress_width(), Unit::Items).contains("{bar:30}"));
+
+        // A width of 0 is clamped, and drawing with it doesn't panic.
+        crate::set_progress_width(Some(0));
+        assert_eq!(crate::progress_width(), Some(20));
+        let bar = new_progress(10, "Zero width".to_string());
+        bar.inc(10);
+        ProgressBarTrait::finish(&bar);
+        crate::set_progress_width(None);
+    }
+
+    #[test]
+    fn units() {
+        let items = progress_template(Some(100), Unit::Items);
+        assert!(items.contains("{pos}/{len} {per_sec} ETA {eta}"));
+        let bytes = progress_template(Some(116), Unit::Bytes);
+        assert!(bytes.contains("{bar:30}"));
+        assert!(bytes.contains("{bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta}"));
+
+        let bar = new_progress(1 << 20, "Downloading".to_string());
+        bar.set_unit(Unit::Bytes);
+        bar.set_position(1 << 19);
+        assert_eq!(bar.position(), 1 << 19);
+        ProgressBarTrait::finish(&bar);
+    }
+
+    #[test]
+    fn multi_progress() {
+        assert!(new_multi_progress(true).is_hidden());
+
+        let multi = new_multi_progress(false);
+        let clone = MultiProgressTrait::boxed_clone(&multi);
+        let download = MultiProgressTrait::add(&multi, 3, "Downloading".to_string());
+        let apply = clone.add(3, "Applying".to_string());
+        let apply_clone = apply.boxed_clone();
+        download.set_position(3);
+        apply.inc(1);
+        apply_clone.inc(2);
+        download.finish();
+        drop(apply_clone);
+        apply.finish();
+    }
+}
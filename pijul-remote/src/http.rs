@@ -5,6 +5,8 @@ use log::{debug, error, trace};
 use std::collections::HashSet;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::CS;
 use pijul_interaction::ProgressBar;
@@ -17,12 +19,187 @@ pub struct Http {
     pub client: reqwest::Client,
     pub name: String,
     pub headers: Vec<(String, String)>,
+    /// Limits the download rate of all the changes downloaded from
+    /// this remote.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// The SHA-256 fingerprint of the certificate a server must present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CertPin(pub [u8; 32]);
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl CertPin {
+    /// Parse a fingerprint written in hexadecimal, optionally with
+    /// colons between bytes, as printed by `openssl x509 -fingerprint
+    /// -sha256`.
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        let s: String = s.trim().chars().filter(|c| *c != ':').collect();
+        let mut pin = [0; 32];
+        if s.len() != 64 || !s.is_ascii() {
+            bail!("Invalid SHA-256 fingerprint: {:?}", s)
+        }
+        for (i, b) in pin.iter_mut().enumerate() {
+            if let Ok(x) = u8::from_str_radix(&s[2 * i..2 * i + 2], 16) {
+                *b = x
+            } else {
+                bail!("Invalid SHA-256 fingerprint: {:?}", s)
+            }
+        }
+        Ok(CertPin(pin))
+    }
+
+    /// Check that the DER-encoded certificate `der` has this fingerprint.
+    pub fn check_der(&self, der: &[u8]) -> Result<(), anyhow::Error> {
+        use sha2::Digest;
+        let fingerprint = sha2::Sha256::digest(der);
+        if fingerprint[..] != self.0[..] {
+            bail!(
+                "Certificate fingerprint mismatch: expected SHA-256 {}, got {}",
+                to_hex(&self.0),
+                to_hex(&fingerprint)
+            )
+        }
+        Ok(())
+    }
+
+    /// A TLS configuration that accepts exactly the certificates
+    /// with this fingerprint, whatever the certificate authorities
+    /// say. The check happens during the handshake, before any
+    /// request is sent.
+    pub fn tls_config(&self) -> rustls::ClientConfig {
+        rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier(*self)))
+            .with_no_client_auth()
+    }
+}
+
+struct PinnedVerifier(CertPin);
+
+impl rustls::client::ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        if let Err(e) = self.0.check_der(&end_entity.0) {
+            return Err(rustls::Error::General(e.to_string()));
+        }
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+/// A bandwidth limit shared between concurrent downloads, as a token
+/// bucket holding at most one second worth of bytes, so that an idle
+/// period doesn't allow a burst above the limit afterwards.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    /// The last time the bucket was refilled, and the number of bytes
+    /// available then, negative if the downloads are ahead of the
+    /// limit.
+    state: Mutex<(Instant, f64)>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            bytes_per_sec: bytes_per_sec.max(1),
+            state: Mutex::new((Instant::now(), 0.)),
+        }
+    }
+
+    /// Account for `bytes` more bytes, and return how long to wait
+    /// before consuming anything else to stay under the limit.
+    pub fn delay(&self, bytes: u64) -> Duration {
+        self.delay_at(bytes, Instant::now())
+    }
+
+    fn delay_at(&self, bytes: u64, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let rate = self.bytes_per_sec as f64;
+        let refill = now.saturating_duration_since(state.0).as_secs_f64() * rate;
+        state.0 = now;
+        state.1 = (state.1 + refill).min(rate) - bytes as f64;
+        if state.1 >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.1 / rate)
+        }
+    }
+
+    /// Account for `bytes` more bytes, sleeping if needed.
+    pub async fn consume(&self, bytes: u64) {
+        let delay = self.delay(bytes);
+        if !delay.is_zero() {
+            trace!("rate limit: sleeping {:?}", delay);
+            tokio::time::sleep(delay).await
+        }
+    }
+}
+
+/// Headers that may appear several times in a request, and the
+/// separator used to combine their values into one.
+const MULTI_VALUED_HEADERS: &[(&str, &str)] = &[
+    ("accept", ", "),
+    ("accept-encoding", ", "),
+    ("accept-language", ", "),
+    ("cache-control", ", "),
+    ("cookie", "; "),
+];
+
+/// Extra headers sent with requests, with at most one entry per
+/// header name (compared case-insensitively). When a name is given
+/// several times, the last value wins, except for the headers of
+/// [`MULTI_VALUED_HEADERS`], whose values are combined.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub fn new(headers: &[(String, String)]) -> Self {
+        let mut result: Vec<(String, String)> = Vec::with_capacity(headers.len());
+        for (k, v) in headers.iter() {
+            let lower = k.to_lowercase();
+            if let Some(e) = result.iter_mut().find(|(k, _)| k.to_lowercase() == lower) {
+                if let Some((_, sep)) = MULTI_VALUED_HEADERS.iter().find(|(h, _)| *h == lower) {
+                    e.1.push_str(sep);
+                    e.1.push_str(v)
+                } else {
+                    e.1 = v.clone()
+                }
+            } else {
+                result.push((k.clone(), v.clone()))
+            }
+        }
+        Headers(result)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, String)> {
+        self.0.iter()
+    }
+
+    /// Add these headers to `req`.
+    pub fn apply(&self, mut req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        for (k, v) in self.0.iter() {
+            debug!("kv = {:?} {:?}", k, v);
+            req = req.header(k.as_str(), v.as_str());
+        }
+        req
+    }
 }
 
 async fn download_change(
     client: reqwest::Client,
     url: url::Url,
-    headers: Vec<(String, String)>,
+    headers: Headers,
+    rate_limiter: Option<Arc<RateLimiter>>,
     mut path: PathBuf,
     c: CS,
 ) -> Result<CS, anyhow::Error> {
@@ -70,14 +247,12 @@ async fn download_change(
 
     let mut done = false;
     while !done {
-        let mut req = client
-            .get(&url)
-            .query(&[(req, &c32)])
-            .header(reqwest::header::USER_AGENT, USER_AGENT);
-        for (k, v) in headers.iter() {
-            debug!("kv = {:?} {:?}", k, v);
-            req = req.header(k.as_str(), v.as_str());
-        }
+        let req = headers.apply(
+            client
+                .get(&url)
+                .query(&[(req, &c32)])
+                .header(reqwest::header::USER_AGENT, USER_AGENT),
+        );
         let mut res = if let Ok(res) = req.send().await {
             delay = 1f64;
             res
@@ -105,6 +280,9 @@ async fn download_change(
                     if let Some(ref mut s) = size {
                         *s -= chunk.len();
                     }
+                    if let Some(ref limiter) = rate_limiter {
+                        limiter.consume(chunk.len() as u64).await
+                    }
                     send.send(Some(chunk)).await?;
                 }
                 Ok(None) => match size {
@@ -153,6 +331,7 @@ impl Http {
         debug!("starting download_changes http");
         let mut pool: [Option<tokio::task::JoinHandle<Result<CS, _>>>; POOL_SIZE] =
             <[_; POOL_SIZE]>::default();
+        let headers = Headers::new(&self.headers);
         let mut cur = 0;
         loop {
             if let Some(t) = pool[cur].take() {
@@ -180,7 +359,8 @@ impl Http {
                     pool[cur] = Some(tokio::spawn(download_change(
                         self.client.clone(),
                         self.url.clone(),
-                        self.headers.clone(),
+                        headers.clone(),
+                        self.rate_limiter.clone(),
                         path.clone(),
                         c,
                     )));
@@ -196,7 +376,8 @@ impl Http {
                             pool[cur] = Some(tokio::spawn(download_change(
                                 self.client.clone(),
                                 self.url.clone(),
-                                self.headers.clone(),
+                                headers.clone(),
+                                self.rate_limiter.clone(),
                                 path.clone(),
                                 c,
                             )));
@@ -406,6 +587,48 @@ impl Http {
         }
     }
 
+    /// Ask the server how many changes of the remote channel are
+    /// missing from a channel whose states are `known_states`, for
+    /// instance to size a progress bar before downloading. Returns
+    /// `None` if the server can't answer.
+    ///
+    /// This is an extension of the HTTP protocol, which servers may
+    /// not implement: the request is a `GET` on
+    /// `.pijul?channel=<channel>&countMissing=<state>…`, with one
+    /// `countMissing` parameter per known state in base32 (or a single
+    /// empty one if there are none), and the expected response is the
+    /// number of missing changes, in decimal. Any other response,
+    /// including an HTTP error or a response that isn't a number, is
+    /// treated as the server not supporting the extension.
+    pub async fn count_missing(
+        &self,
+        known_states: &[libpijul::Merkle],
+    ) -> Result<Option<usize>, anyhow::Error> {
+        debug!("count_missing {:?}", self.url);
+        let url = format!("{}/{}", self.url, super::DOT_DIR);
+        let mut q = vec![("channel", self.channel.clone())];
+        if known_states.is_empty() {
+            q.push(("countMissing", String::new()))
+        }
+        for s in known_states {
+            q.push(("countMissing", s.to_base32()))
+        }
+        let req = self
+            .client
+            .get(&url)
+            .query(&q)
+            .header(reqwest::header::USER_AGENT, USER_AGENT);
+        let res = Headers::new(&self.headers).apply(req).send().await?;
+        if !res.status().is_success() {
+            debug!("count_missing: HTTP error {:?}", res.status());
+            return Ok(None);
+        }
+        let resp = res.bytes().await?;
+        Ok(std::str::from_utf8(&resp)
+            .ok()
+            .and_then(|s| s.trim().parse().ok()))
+    }
+
     pub async fn get_id(&self) -> Result<Option<libpijul::pristine::RemoteId>, anyhow::Error> {
         debug!("get_state {:?}", self.url);
         let url = format!("{}/{}", self.url, super::DOT_DIR);
@@ -579,3 +802,201 @@ impl Http {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CertPin, Headers, Http, RateLimiter};
+    use libpijul::pristine::Base32;
+    use std::time::Duration;
+
+    #[test]
+    fn cert_pin() {
+        // SHA-256 of "abc".
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let pin = CertPin::parse(abc).unwrap();
+        assert!(pin.check_der(b"abc").is_ok());
+
+        let colons = abc
+            .to_uppercase()
+            .as_bytes()
+            .chunks(2)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(CertPin::parse(&colons).unwrap(), pin);
+
+        let e = pin.check_der(b"abd").unwrap_err();
+        assert!(e.to_string().starts_with(&format!(
+            "Certificate fingerprint mismatch: expected SHA-256 {}, got ",
+            abc
+        )));
+
+        assert!(CertPin::parse("ba7816bf").is_err());
+        assert!(CertPin::parse(&abc.replace('b', "g")).is_err());
+    }
+
+    /// Accept a single TLS connection with a fresh self-signed
+    /// certificate, returning the address of the server, the
+    /// certificate, and the plaintext bytes the client sent.
+    fn tls_server() -> (
+        std::net::SocketAddr,
+        Vec<u8>,
+        std::thread::JoinHandle<Vec<u8>>,
+    ) {
+        use std::io::{Read, Write};
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let der = cert.serialize_der().unwrap();
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(der.clone())],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let t = std::thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            let mut conn = rustls::ServerConnection::new(std::sync::Arc::new(config)).unwrap();
+            let mut tls = rustls::Stream::new(&mut conn, &mut tcp);
+            let mut received = Vec::new();
+            let mut buf = [0; 4096];
+            while let Ok(n) = tls.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+                if received.windows(4).any(|w| w == b"\r\n\r\n") {
+                    tls.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .unwrap();
+                    break;
+                }
+            }
+            received
+        });
+        (addr, der, t)
+    }
+
+    #[tokio::test]
+    async fn cert_pin_handshake() {
+        use sha2::Digest;
+        let send = |pin: CertPin, addr: std::net::SocketAddr| {
+            reqwest::ClientBuilder::new()
+                .use_preconfigured_tls(pin.tls_config())
+                .build()
+                .unwrap()
+                .post(format!("https://{}/", addr))
+                .header(reqwest::header::AUTHORIZATION, "Bearer secret")
+                .body("secret body")
+                .send()
+        };
+
+        // The wrong certificate is rejected before the request is sent.
+        let (addr, _, t) = tls_server();
+        assert!(send(CertPin([0; 32]), addr).await.is_err());
+        assert!(t.join().unwrap().is_empty());
+
+        let (addr, der, t) = tls_server();
+        let mut pin = [0; 32];
+        pin.copy_from_slice(&sha2::Sha256::digest(&der));
+        let res = send(CertPin(pin), addr).await.unwrap();
+        assert!(res.status().is_success());
+        assert!(t.join().unwrap().starts_with(b"POST / HTTP/1.1\r\n"));
+    }
+
+    /// Serve a single HTTP request with `status` and `body`, returning
+    /// the address of the server and the request line it received.
+    fn mock_server(
+        status: &'static str,
+        body: &'static str,
+    ) -> (std::net::SocketAddr, std::thread::JoinHandle<String>) {
+        use std::io::{BufRead, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let t = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear()
+            }
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            request_line
+        });
+        (addr, t)
+    }
+
+    fn mock_remote(addr: std::net::SocketAddr) -> Http {
+        Http {
+            url: format!("http://{}", addr).parse().unwrap(),
+            channel: "main".to_string(),
+            client: reqwest::Client::new(),
+            name: "mock".to_string(),
+            headers: Vec::new(),
+            rate_limiter: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn count_missing() {
+        let state = libpijul::Merkle::zero();
+        let (addr, t) = mock_server("200 OK", "42\n");
+        let n = mock_remote(addr).count_missing(&[state]).await.unwrap();
+        assert_eq!(n, Some(42));
+        let request_line = t.join().unwrap();
+        assert!(request_line.contains(&format!("countMissing={}", state.to_base32())));
+
+        let (addr, t) = mock_server("404 Not Found", "");
+        assert_eq!(mock_remote(addr).count_missing(&[]).await.unwrap(), None);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn dedup_headers() {
+        let h = |k: &str, v: &str| (k.to_string(), v.to_string());
+        let headers = Headers::new(&[
+            h("Authorization", "Bearer old"),
+            h("Cookie", "a=1"),
+            h("authorization", "Bearer new"),
+            h("cookie", "b=2"),
+        ]);
+        assert_eq!(
+            headers.iter().cloned().collect::<Vec<_>>(),
+            vec![h("Authorization", "Bearer new"), h("Cookie", "a=1; b=2")]
+        );
+    }
+
+    #[test]
+    fn rate_limiter_budget() {
+        let limiter = RateLimiter::new(1000);
+        let d = limiter.delay(500);
+        assert!(d <= Duration::from_millis(500));
+        assert!(d > Duration::from_millis(400));
+        let d = limiter.delay(500);
+        assert!(d <= Duration::from_millis(1000));
+        assert!(d > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn rate_limiter_idle() {
+        // After a long idle period, only one second worth of bytes
+        // can be consumed without waiting.
+        let limiter = RateLimiter::new(1000);
+        let later = std::time::Instant::now() + Duration::from_secs(10);
+        assert_eq!(limiter.delay_at(3000, later), Duration::from_secs(2));
+        assert!(limiter.delay_at(1, later) > Duration::from_secs(2));
+    }
+}
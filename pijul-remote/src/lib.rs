@@ -144,26 +144,25 @@ impl ToRemote for RemoteConfig {
                 http,
                 headers,
                 name,
+                max_bytes_per_sec,
+                pinned_cert_sha256,
             } => {
                 let mut h = Vec::new();
                 for (k, v) in headers.iter() {
-                    match v {
-                        RemoteHttpHeader::String(s) => {
-                            h.push((k.clone(), s.clone()));
-                        }
-                        RemoteHttpHeader::Shell(shell) => {
-                            h.push((k.clone(), shell_cmd(&shell.shell)?));
-                        }
-                    }
+                    h.push((k.clone(), v.resolve()?));
+                }
+                let mut client =
+                    reqwest::ClientBuilder::new().danger_accept_invalid_certs(no_cert_check);
+                if let Some(pin) = pinned_cert_sha256 {
+                    client = client.use_preconfigured_tls(CertPin::parse(pin)?.tls_config());
                 }
                 return Ok(RemoteRepo::Http(Http {
                     url: http.parse().unwrap(),
                     channel: channel.to_string(),
-                    client: reqwest::ClientBuilder::new()
-                        .danger_accept_invalid_certs(no_cert_check)
-                        .build()?,
+                    client: client.build()?,
                     headers: h,
                     name: name.to_string(),
+                    rate_limiter: max_bytes_per_sec.map(|r| Arc::new(RateLimiter::new(r))),
                 }));
             }
         }
@@ -190,6 +189,7 @@ pub async fn unknown_remote(
                     .build()?,
                 headers: Vec::new(),
                 name: name.to_string(),
+                rate_limiter: None,
             }));
         } else if scheme == "ssh" {
             if let Some(mut ssh) = ssh_remote(user, name, with_path) {
@@ -184,7 +184,7 @@ impl Local {
             "",
             true,
             None,
-            std::thread::available_parallelism()?.get(),
+            libpijul::default_parallelism(),
             0,
         )?;
         txn.commit()?;
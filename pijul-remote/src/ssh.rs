@@ -284,8 +284,15 @@ impl<'a> Remote<'a> {
         let mut authenticated = false;
         let username = format!("{}@{}", self.config.user, self.config.host_name);
 
+        let use_keyring = pijul_identity::keyring_enabled();
+
         // Try authenticate using the user's keyring
-        if let Ok(password) = keyring::Entry::new("pijul", &username).and_then(|x| x.get_password())
+        if let Some(password) = use_keyring
+            .then(|| {
+                keyring::Entry::new(pijul_identity::keyring_service(), &username)
+                    .and_then(|x| x.get_password())
+            })
+            .and_then(Result::ok)
         {
             authenticated = h
                 .authenticate_password(self.config.user.to_string(), &password)
@@ -304,9 +311,9 @@ impl<'a> Remote<'a> {
                 .await?;
 
             // If the new password is valid, update the keyring to match
-            if authenticated {
-                if let Err(e) =
-                    keyring::Entry::new("pijul", &username).and_then(|x| x.set_password(&password))
+            if authenticated && use_keyring {
+                if let Err(e) = keyring::Entry::new(pijul_identity::keyring_service(), &username)
+                    .and_then(|x| x.set_password(&password))
                 {
                     warn!("Unable to set password: {e:?}");
                 }
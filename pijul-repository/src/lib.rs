@@ -1,5 +1,5 @@
 use std::env::current_dir;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use pijul_config as config;
 
@@ -31,7 +31,7 @@ const IGNORE_KINDS: &[(&[&str], &[&[u8]])] = &[
 #[cfg(unix)]
 pub fn max_files() -> std::io::Result<usize> {
     let n = if let Ok((n, _)) = rlimit::getrlimit(rlimit::Resource::NOFILE) {
-        (n as usize / (2 * std::thread::available_parallelism()?.get())).max(1)
+        (n as usize / (2 * libpijul::default_parallelism())).max(1)
     } else {
         256
     };
@@ -44,28 +44,43 @@ pub fn max_files() -> std::io::Result<usize> {
     Ok(1)
 }
 
+/// Find the root of the Pijul repository containing `start`, i.e. the
+/// closest ancestor of `start` (including `start` itself) containing
+/// a `.pijul` directory. Returns `None` if there is no such ancestor.
+pub fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    let mut dot_dir = find_dot_dir(start, DOT_DIR)?;
+    dot_dir.pop();
+    Some(dot_dir)
+}
+
+/// Find the closest `dot_dir` in `start` and its ancestors.
+fn find_dot_dir(start: &Path, dot_dir: &str) -> Option<PathBuf> {
+    let mut cur = start.to_path_buf();
+    loop {
+        cur.push(dot_dir);
+        debug!("{:?}", cur);
+        if std::fs::metadata(&cur).is_ok() {
+            return Some(cur);
+        }
+        cur.pop();
+        if !cur.pop() {
+            return None;
+        }
+    }
+}
+
 impl Repository {
     fn find_root_(cur: Option<PathBuf>, dot_dir: &str) -> Result<PathBuf, anyhow::Error> {
-        let mut cur = if let Some(cur) = cur {
+        let cur = if let Some(cur) = cur {
             cur
         } else {
             current_dir()?
         };
-        cur.push(dot_dir);
-        loop {
-            debug!("{:?}", cur);
-            if std::fs::metadata(&cur).is_err() {
-                cur.pop();
-                if cur.pop() {
-                    cur.push(DOT_DIR);
-                } else {
-                    bail!("No Pijul repository found")
-                }
-            } else {
-                break;
-            }
+        if let Some(cur) = find_dot_dir(&cur, dot_dir) {
+            Ok(cur)
+        } else {
+            bail!("No Pijul repository found")
         }
-        Ok(cur)
     }
 
     pub fn find_root(cur: Option<PathBuf>) -> Result<Self, anyhow::Error> {
@@ -93,11 +108,28 @@ impl Repository {
         } else {
             config::Config::default()
         };
+        config.validate()?;
+        // Try the most specific patterns first.
+        let mut file_encodings: Vec<_> = config.file_encodings.iter().collect();
+        file_encodings.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+        // The initial size only avoids remapping the pristine as it
+        // grows, it isn't a limit.
+        let pristine = if let Some(size) = config.pristine_initial_size()? {
+            libpijul::pristine::sanakirja::Pristine::new_with_size(&pristine_dir.join("db"), size)?
+        } else {
+            libpijul::pristine::sanakirja::Pristine::new(&pristine_dir.join("db"))?
+        };
         Ok(Repository {
-            pristine: libpijul::pristine::sanakirja::Pristine::new(&pristine_dir.join("db"))?,
+            pristine,
             working_copy: libpijul::working_copy::filesystem::FileSystem::from_root(
                 &working_copy_dir,
-            ),
+            )
+            .with_preserve_full_mode(config.preserve_full_mode.unwrap_or(false))
+            .with_encoding_hints(
+                file_encodings
+                    .into_iter()
+                    .map(|(pattern, label)| (pattern.as_str(), label.as_str())),
+            )?,
             changes: libpijul::changestore::filesystem::FileSystem::from_root(
                 &working_copy_dir,
                 max_files()?,
@@ -236,3 +268,41 @@ fn ignore_specific(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_repo_root_nested() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(root.path().join(DOT_DIR)).unwrap();
+
+        assert_eq!(find_repo_root(&nested).as_deref(), Some(root.path()));
+        assert_eq!(find_repo_root(root.path()).as_deref(), Some(root.path()));
+        assert_eq!(
+            find_dot_dir(&nested, DOT_DIR),
+            Some(root.path().join(DOT_DIR))
+        );
+
+        // The closest repository wins.
+        let inner = root.path().join("a");
+        std::fs::create_dir(inner.join(DOT_DIR)).unwrap();
+        assert_eq!(find_repo_root(&nested), Some(inner));
+    }
+
+    #[test]
+    fn find_repo_root_not_found() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a");
+        std::fs::create_dir(&nested).unwrap();
+        // A name that no ancestor of the temporary directory contains,
+        // unlike `.pijul` which might be in one of them.
+        let dot_dir = ".pijul-find-dot-dir-test";
+        assert_eq!(find_dot_dir(&nested, dot_dir), None);
+        let e = Repository::find_root_(Some(nested), dot_dir).unwrap_err();
+        assert_eq!(e.to_string(), "No Pijul repository found");
+    }
+}
@@ -158,7 +158,7 @@ impl Apply {
                         &repo.working_copy,
                         &repo.changes,
                         "",
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                     )?
                 } else {
                     use canonical_path::CanonicalPathBuf;
@@ -172,7 +172,7 @@ impl Apply {
                         CanonicalPathBuf::canonicalize(&repo.path)?,
                         &touched_files,
                         false,
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                         0,
                     )?;
                 }
@@ -194,7 +194,7 @@ impl Apply {
                         &path,
                         true,
                         None,
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                         0,
                     )?
                     .into_iter(),
@@ -210,7 +210,7 @@ impl Apply {
                         "",
                         true,
                         None,
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                         0,
                     )?
                     .into_iter(),
@@ -34,6 +34,9 @@ pub enum SubCommand {
         #[clap(long = "force", short = 'f')]
         force: bool,
     },
+    /// Show information about a channel (the current channel by default).
+    #[clap(name = "info")]
+    Info { channel: Option<String> },
     /// Create a new, empty channel.
     #[clap(name = "new")]
     New {
@@ -53,9 +56,9 @@ impl Channel {
                 let repo = Repository::find_root(self.repo_path)?;
                 let txn = repo.pristine.txn_begin()?;
                 let current = txn.current_channel().ok();
-                for channel in txn.channels("")? {
-                    let channel = channel.read();
-                    let name = txn.name(&*channel);
+                for name in txn.channel_names("")? {
+                    let name = name?;
+                    let name = name.as_str();
                     if current == Some(name) {
                         writeln!(stdout, "* {}", name)?;
                     } else {
@@ -75,6 +78,28 @@ impl Channel {
                 }
                 txn.commit()?;
             }
+            Some(SubCommand::Info { ref channel }) => {
+                let repo = Repository::find_root(self.repo_path)?;
+                let txn = repo.pristine.txn_begin()?;
+                let name = if let Some(ref channel) = channel {
+                    channel.as_str()
+                } else {
+                    txn.current_channel()?
+                };
+                let channel = if let Some(channel) = txn.load_channel(name)? {
+                    channel
+                } else {
+                    bail!("No such channel: {:?}", name)
+                };
+                let channel = channel.read();
+                writeln!(stdout, "Channel: {}", txn.name(&*channel))?;
+                if let Some(id) = txn.id(&*channel) {
+                    writeln!(stdout, "Id: {}", id)?;
+                }
+                if let Some(source) = txn.source_url(&*channel) {
+                    writeln!(stdout, "Source: {}", source)?;
+                }
+            }
             Some(SubCommand::Switch { to, force }) => {
                 (crate::commands::reset::Reset {
                     repo_path: self.repo_path,
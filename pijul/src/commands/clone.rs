@@ -84,6 +84,8 @@ impl Clone {
         let mut repo = Repository::init(Some(path), None, Some(&remote_normalised))?;
         let txn = repo.pristine.arc_txn_begin()?;
         let mut channel = txn.write().open_or_create_channel(&self.channel)?;
+        txn.write()
+            .set_source_url(&mut *channel.write(), Some(&*remote_normalised));
         if let Some(ref change) = self.change {
             let h = change.parse()?;
             remote
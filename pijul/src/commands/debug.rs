@@ -14,6 +14,9 @@ pub struct Debug {
     channel: Option<String>,
     #[clap(long = "sanakirja-only")]
     sanakirja_only: bool,
+    /// Only print the edges of the vertex containing this position (CHANGE.POS).
+    #[clap(long = "edges")]
+    edges: Option<String>,
     root: Option<String>,
 }
 
@@ -32,6 +35,22 @@ impl Debug {
         } else {
             bail!("No such channel: {:?}", channel_name)
         };
+        if let Some(ref edges) = self.edges {
+            let pos = if let Some(pos) = parse_pos(edges) {
+                pos
+            } else {
+                bail!("Invalid position: {:?}", edges)
+            };
+            use libpijul::{Base32, GraphTxnT};
+            let channel = channel.read();
+            let graph = txn.graph(&*channel);
+            let v = *txn.find_block(graph, pos)?;
+            println!("{:?}", v);
+            for (flag, dest, introduced_by) in libpijul::pristine::dump_adjacency(&txn, graph, v)? {
+                println!("  {:?} -> {:?} ({})", flag, dest, introduced_by.to_base32());
+            }
+            return Ok(());
+        }
         if !self.sanakirja_only {
             libpijul::pristine::debug_inodes(&txn);
             libpijul::pristine::debug_dep(&txn);
@@ -80,7 +80,7 @@ impl Diff {
                 &repo.working_copy,
                 &repo.changes,
                 "",
-                std::thread::available_parallelism()?.get(),
+                libpijul::default_parallelism(),
             )?
         } else {
             self.fill_relative_prefixes()?;
@@ -97,7 +97,7 @@ impl Diff {
                 CanonicalPathBuf::canonicalize(&repo.path)?,
                 &self.prefixes,
                 false,
-                std::thread::available_parallelism()?.get(),
+                libpijul::default_parallelism(),
                 0,
             )?;
         }
@@ -373,7 +373,7 @@ fn untracked<'a, T: TxnTExt>(
     txn: &'a T,
 ) -> Result<impl Iterator<Item = PathBuf> + 'a, anyhow::Error> {
     let repo_path = CanonicalPathBuf::canonicalize(&repo.path)?;
-    let threads = std::thread::available_parallelism()?.get();
+    let threads = libpijul::default_parallelism();
     Ok(repo
         .working_copy
         .iterate_prefix_rec(repo_path.clone(), repo_path.clone(), false, threads)?
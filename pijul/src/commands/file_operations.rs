@@ -126,7 +126,7 @@ impl Add {
     pub fn run(self) -> Result<(), anyhow::Error> {
         let repo = Repository::find_root(None)?;
         let txn = repo.pristine.arc_txn_begin()?;
-        let threads = std::thread::available_parallelism()?.get();
+        let threads = libpijul::default_parallelism();
         let repo_path = CanonicalPathBuf::canonicalize(&repo.path)?;
         let mut stderr = std::io::stderr();
         for path in self.paths.iter() {
@@ -1,6 +1,7 @@
 use anyhow::bail;
 use clap::Parser;
 use libpijul::pristine::*;
+use libpijul::working_copy::git::GitTreeWorkingCopy;
 use libpijul::*;
 use log::{debug, error, info, trace};
 use std::collections::{BTreeMap, BTreeSet, HashSet};
@@ -21,6 +22,18 @@ pub struct Git {
     /// Check only the first n commits processed.
     #[clap(default_value = "0", hide = true)]
     check: usize,
+    /// Format the messages of the imported changes using this template.
+    /// `{subject}`, `{body}`, `{oid}` and `{author}` are replaced by the
+    /// first line of the commit message, the rest of the message, the
+    /// commit hash and the commit author. The first line of the result
+    /// is the change message, and the rest its description. If the
+    /// template doesn't contain `{body}`, the rest of the commit message
+    /// is still appended to the description.
+    #[clap(long = "message-template")]
+    message_template: Option<String>,
+    /// Use at most this many threads to output the files of each commit. Defaults to the number of available CPUs.
+    #[clap(long = "jobs", short = 'j')]
+    jobs: Option<usize>,
 }
 
 struct OpenRepo {
@@ -29,6 +42,8 @@ struct OpenRepo {
     n: usize,
     check: usize,
     current_commit: Option<git2::Oid>,
+    message_template: Option<String>,
+    n_workers: usize,
 }
 
 #[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq)]
@@ -88,6 +103,11 @@ impl Git {
             n: 0,
             check: self.check,
             current_commit: None,
+            message_template: self.message_template,
+            n_workers: self
+                .jobs
+                .unwrap_or_else(libpijul::default_parallelism)
+                .max(1),
         };
         import(&git, &mut env_git, &mut repo, &dag)?;
 
@@ -103,7 +123,7 @@ impl Git {
                     "",
                     false,
                     None,
-                    std::thread::available_parallelism()?.get(),
+                    repo.n_workers,
                     0,
                 )?;
             }
@@ -340,8 +360,12 @@ fn import(
 
                 let mut stats = Stats::new(oid);
                 import_commit_parents(repo, dag, &txn, &channel, &oid, &mut ws, &mut stats)?;
-                let state = import_commit(git, repo, &txn, &channel, &oid, &mut stats)?;
-                save_state(env_git, &oid, state)?;
+                let imported = import_commit(git, repo, &txn, &channel, &oid, &mut stats)?;
+                debug!(
+                    "imported {:?} as {:?} ({} actions)",
+                    oid, imported.hash, imported.n_actions
+                );
+                save_state(env_git, &oid, imported.state)?;
                 dag.collect_dead_parents(&oid, &mut todo, &txn)?;
                 dag.insert_children_in_todo(&oid, &mut todo);
 
@@ -475,7 +499,7 @@ fn import_commit_parents<T: TxnTExt + MutTxnTExt + GraphIter + Send + Sync + 'st
             "",
             false,
             None,
-            std::thread::available_parallelism()?.get(),
+            repo.n_workers,
             0,
         )?;
         let t = output_time.elapsed();
@@ -624,51 +648,65 @@ fn git_reset<'a, T: TxnTExt + MutTxnTExt>(
     Ok((object, prefixes, pref))
 }
 
-#[derive(Clone)]
-struct Commit<'a> {
-    r: &'a git2::Repository,
-    c: git2::Commit<'a>,
-    pref: HashSet<String>,
-}
-
-impl<'a> libpijul::working_copy::WorkingCopyRead for Commit<'a> {
-    type Error = git2::Error;
-
-    fn file_metadata(&self, file: &str) -> Result<InodeMetadata, Self::Error> {
-        debug!("metadata {:?}", file);
-        let entry = self.c.tree()?.get_path(Path::new(file))?;
-        let is_dir = entry.kind() == Some(git2::ObjectType::Tree);
-        if is_dir {
-            Ok(InodeMetadata::new(0o100, true))
-        } else {
-            let permissions = entry.filemode();
-            debug!(
-                "permissions = {:o} {:o} {:?}",
-                permissions,
-                permissions & 0o100,
-                is_dir
-            );
-            Ok(InodeMetadata::new(permissions as usize & 0o100, false))
-        }
+/// Split a commit message into its first line and the rest.
+fn split_message(msg: &str) -> (String, String) {
+    let mut msg_lines = msg.lines();
+    let mut message = String::new();
+    if let Some(m) = msg_lines.next() {
+        message.push_str(m)
     }
-
-    fn read_file(&self, file: &str, buffer: &mut Vec<u8>) -> Result<(), Self::Error> {
-        debug!("read file {:?}", file);
-        let entry = self.c.tree()?.get_path(Path::new(file))?;
-        if let Ok(b) = entry.to_object(self.r)?.peel_to_blob() {
-            buffer.extend(b.content());
+    let mut description = String::new();
+    for m in msg_lines {
+        if !description.is_empty() {
+            description.push('\n')
         }
-        debug!("entry {:?}", entry.kind());
-        Ok(())
+        description.push_str(m);
     }
+    (message, description)
+}
 
-    fn modified_time(&self, x: &str) -> Result<std::time::SystemTime, Self::Error> {
-        if self.pref.contains(x) {
-            Ok(std::time::SystemTime::now())
+/// Replace the placeholders of a `--message-template` in a single
+/// pass, so that braces in the commit message are left untouched.
+fn expand_message_template(
+    template: &str,
+    subject: &str,
+    body: &str,
+    oid: &git2::Oid,
+    author: &str,
+) -> String {
+    let mut result = String::with_capacity(template.len() + subject.len() + body.len());
+    let mut rest = template;
+    while let Some(i) = rest.find('{') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (value, len) = if rest.starts_with("{subject}") {
+            (subject.to_string(), 9)
+        } else if rest.starts_with("{body}") {
+            (body.to_string(), 6)
+        } else if rest.starts_with("{oid}") {
+            (oid.to_string(), 5)
+        } else if rest.starts_with("{author}") {
+            (author.to_string(), 8)
         } else {
-            Ok(std::time::SystemTime::UNIX_EPOCH)
-        }
+            ("{".to_string(), 1)
+        };
+        result.push_str(&value);
+        rest = &rest[len..];
     }
+    result.push_str(rest);
+    result
+}
+
+/// The result of importing a Git commit.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportedCommit {
+    /// The state of the channel after importing the commit.
+    pub state: libpijul::Merkle,
+    /// The change recorded for the commit, if the commit changed
+    /// anything.
+    pub hash: Option<libpijul::Hash>,
+    /// The number of actions in that change.
+    pub n_actions: usize,
 }
 
 /// Reset to the Git commit specified as `child`, and record the
@@ -680,7 +718,7 @@ fn import_commit<T: TxnTExt + MutTxnTExt + GraphIter + Send + Sync + 'static>(
     channel: &ChannelRef<T>,
     child: &git2::Oid,
     stats: &mut Stats,
-) -> Result<libpijul::Merkle, anyhow::Error> {
+) -> Result<ImportedCommit, anyhow::Error> {
     let (object, prefixes, prefstr) = git_reset(git, repo, &txn, &channel, child, stats)?;
     debug!("prefixes = {:?}", prefixes);
     let mut txn_ = txn.write();
@@ -727,17 +765,26 @@ fn import_commit<T: TxnTExt + MutTxnTExt + GraphIter + Send + Sync + 'static>(
     }
     std::mem::drop(txn_);
     let msg = commit.message().unwrap();
-    let mut msg_lines = msg.lines();
-    let mut message = String::new();
-    if let Some(m) = msg_lines.next() {
-        message.push_str(m)
-    }
-    let mut description = String::new();
-    for m in msg_lines {
-        if !description.is_empty() {
-            description.push('\n')
+    let (mut message, mut description) = split_message(msg);
+    if let Some(ref template) = repo.message_template {
+        let expanded = expand_message_template(
+            template,
+            &message,
+            &description,
+            child,
+            signature.name().unwrap_or(""),
+        );
+        let (m, d) = split_message(&expanded);
+        message = m;
+        if template.contains("{body}") {
+            description = d
+        } else if !d.is_empty() {
+            description = if description.is_empty() {
+                d
+            } else {
+                format!("{}\n{}", d, description)
+            }
         }
-        description.push_str(m);
     }
     let mut author = BTreeMap::new();
     author.insert("name".to_string(), signature.name().unwrap().to_string());
@@ -746,11 +793,7 @@ fn import_commit<T: TxnTExt + MutTxnTExt + GraphIter + Send + Sync + 'static>(
         &txn,
         &channel,
         // &repo.repo.working_copy
-        &Commit {
-            r: git,
-            c: git.find_commit(*child)?,
-            pref: prefstr,
-        },
+        &GitTreeWorkingCopy::from_commit(git, &git.find_commit(*child)?)?.with_modified(prefstr),
         &repo.repo.changes,
         &prefixes_,
         libpijul::change::ChangeHeader {
@@ -779,7 +822,11 @@ fn import_commit<T: TxnTExt + MutTxnTExt + GraphIter + Send + Sync + 'static>(
         Ok(x) => x,
         Err(libpijul::LocalApplyError::ChangeAlreadyOnChannel { hash }) => {
             error!("change already on channel: {:?}", hash);
-            return Ok(txn.current_state(&channel.read())?);
+            return Ok(ImportedCommit {
+                state: txn.current_state(&channel.read())?,
+                hash: None,
+                n_actions: 0,
+            });
         }
         Err(e) => return Err(e.into()),
     };
@@ -795,7 +842,11 @@ fn import_commit<T: TxnTExt + MutTxnTExt + GraphIter + Send + Sync + 'static>(
         check_tree_inodes(&*txn, txn.graph(&channel.read()));
     }
     repo.n += 1;
-    Ok(state)
+    Ok(ImportedCommit {
+        state,
+        hash,
+        n_actions,
+    })
 }
 
 fn record_apply<
@@ -875,6 +926,7 @@ where
     )?;
     change.dependencies = dependencies;
     change.extra_known = extra_known;
+    libpijul::change::prune_empty_atoms(&mut change);
     debug!("saving change");
     let hash = changes
         .save_change(&mut change, |_, _| Ok::<_, anyhow::Error>(()))
@@ -995,3 +1047,148 @@ impl Stats {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libpijul::changestore::ChangeStore;
+
+    /// Commit `files` to `git` on top of its HEAD, if any.
+    fn commit(git: &git2::Repository, message: &str, files: &[(&str, &str)]) -> git2::Oid {
+        let root = git.workdir().unwrap();
+        let mut index = git.index().unwrap();
+        for (path, contents) in files {
+            std::fs::write(root.join(path), contents).unwrap();
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = git.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::new(
+            "Alice",
+            "alice@example.com",
+            &git2::Time::new(1_700_000_000, 0),
+        )
+        .unwrap();
+        let parent = git.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        git.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn split_commit_message() {
+        assert_eq!(split_message(""), (String::new(), String::new()));
+        assert_eq!(
+            split_message("Subject"),
+            ("Subject".to_string(), String::new())
+        );
+        assert_eq!(
+            split_message("Subject\n\nFirst line\nSecond line\n"),
+            ("Subject".to_string(), "First line\nSecond line".to_string())
+        );
+    }
+
+    #[test]
+    fn message_template() {
+        let oid = git2::Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap();
+        let expand =
+            |template| expand_message_template(template, "Fix {it}", "Body", &oid, "Alice");
+        assert_eq!(
+            expand("[{oid}] {subject} by {author}\n\n{body}"),
+            "[0123456789abcdef0123456789abcdef01234567] Fix {it} by Alice\n\nBody"
+        );
+        // Unknown placeholders and lone braces are kept as they are,
+        // and braces in the values aren't expanded again.
+        assert_eq!(expand("{unknown} {subject"), "{unknown} {subject");
+        assert_eq!(expand("{{subject}}"), "{Fix {it}}");
+
+        let (message, description) = split_message(&expand("{subject} ({author})\n{body}"));
+        assert_eq!(message, "Fix {it} (Alice)");
+        assert_eq!(description, "Body");
+    }
+
+    #[test]
+    fn imported_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(dir.path()).unwrap();
+        let first = commit(&git, "Add a\n\nThe first file", &[("a", "a\n")]);
+        let second = commit(&git, "Nothing", &[]);
+
+        let mut repo = OpenRepo {
+            repo: Repository::init(Some(dir.path().to_path_buf()), None, None).unwrap(),
+            stats: None,
+            n: 0,
+            check: 0,
+            current_commit: None,
+            message_template: None,
+            n_workers: 1,
+        };
+        let txn = repo.repo.pristine.arc_txn_begin().unwrap();
+
+        // A commit adding a file is imported as a change.
+        let channel = txn
+            .write()
+            .open_or_create_channel(&first.to_string())
+            .unwrap();
+        let mut stats = Stats::new(first);
+        let imported = import_commit(&git, &mut repo, &txn, &channel, &first, &mut stats).unwrap();
+        let hash = imported.hash.unwrap();
+        assert!(imported.n_actions > 0);
+        assert_eq!(stats.hash, Some(hash));
+        assert_eq!(stats.n_actions, imported.n_actions);
+        assert_eq!(
+            imported.state,
+            txn.read().current_state(&channel.read()).unwrap()
+        );
+        let header = repo.repo.changes.get_header(&hash).unwrap();
+        assert_eq!(header.message, "Add a");
+        assert_eq!(header.description.as_deref(), Some("The first file"));
+
+        // A commit that changes nothing records no change, and leaves
+        // the state untouched.
+        let channel = txn.write().fork(&channel, &second.to_string()).unwrap();
+        let mut stats = Stats::new(second);
+        let empty = import_commit(&git, &mut repo, &txn, &channel, &second, &mut stats).unwrap();
+        assert_eq!(empty.hash, None);
+        assert_eq!(empty.n_actions, 0);
+        assert_eq!(empty.state, imported.state);
+    }
+
+    #[test]
+    fn import_jobs() {
+        let git_dir = tempfile::tempdir().unwrap();
+        let git = git2::Repository::init(git_dir.path()).unwrap();
+        std::fs::create_dir(git_dir.path().join("d")).unwrap();
+        commit(&git, "First", &[("a", "a\n"), ("d/b", "b\n")]);
+        commit(&git, "Second", &[("a", "a\nA\n"), ("d/c", "c\n")]);
+
+        for jobs in ["1", "4"] {
+            let pijul_dir = tempfile::tempdir().unwrap();
+            let cmd = Git::try_parse_from([
+                std::ffi::OsStr::new("git"),
+                git_dir.path().as_os_str(),
+                pijul_dir.path().as_os_str(),
+                "--jobs".as_ref(),
+                jobs.as_ref(),
+            ])
+            .unwrap();
+            cmd.run().unwrap();
+            for (path, contents) in [("a", "a\nA\n"), ("d/b", "b\n"), ("d/c", "c\n")] {
+                assert_eq!(
+                    std::fs::read_to_string(pijul_dir.path().join(path)).unwrap(),
+                    contents,
+                    "{} with --jobs {}",
+                    path,
+                    jobs
+                );
+            }
+        }
+    }
+}
@@ -176,6 +176,12 @@ pub enum SubCommand {
         #[clap(long = "no-confirm")]
         no_confirm: bool,
     },
+    /// Delete the keyring passwords of identities that no longer exist on disk
+    Prune {
+        /// Names of the identities to check
+        #[clap(required = true)]
+        names: Vec<String>,
+    },
 }
 
 #[derive(Clone, Parser, Debug)]
@@ -349,7 +355,11 @@ impl IdentityCommand {
                                 match encryption {
                                     libpijul::key::Encryption::Aes128(_) => "AES 128-bit",
                                 },
-                                keyring::Entry::new("pijul", &identity.name)?
+                                identity::keyring_enabled()
+                                    && keyring::Entry::new(
+                                        identity::keyring_service(),
+                                        &identity.name
+                                    )?
                                     .get_password()
                                     .is_ok()
                             )
@@ -450,14 +460,21 @@ impl IdentityCommand {
                 std::fs::remove_dir_all(path)?;
                 writeln!(stderr, "Identity removed.")?;
 
-                if identity.secret_key().unwrap().encryption.is_some() {
-                    if let Err(e) =
-                        Entry::new("pijul", &identity.name).and_then(|x| x.delete_password())
+                if identity.secret_key().unwrap().encryption.is_some()
+                    && identity::keyring_enabled()
+                {
+                    if let Err(e) = Entry::new(identity::keyring_service(), &identity.name)
+                        .and_then(|x| x.delete_password())
                     {
                         warn!("Unable to delete password: {e:?}");
                     }
                 }
             }
+            SubCommand::Prune { names } => {
+                for name in identity::prune_keyring(&names)? {
+                    writeln!(stderr, "Deleted the password of {name}")?;
+                }
+            }
         }
 
         Ok(())
@@ -90,7 +90,7 @@ fn pending<T: libpijul::MutTxnTExt + libpijul::TxnT + Send + Sync + 'static>(
         &repo.working_copy,
         &repo.changes,
         "",
-        std::thread::available_parallelism()?.get(),
+        libpijul::default_parallelism(),
     )?;
     let recorded = builder.finish();
     if recorded.actions.is_empty() {
@@ -367,7 +367,7 @@ impl Protocol {
                 "",
                 true,
                 None,
-                std::thread::available_parallelism()?.get(),
+                libpijul::default_parallelism(),
                 0,
             )?;
         }
@@ -566,7 +566,7 @@ impl Pull {
                         path,
                         true,
                         None,
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                         0,
                     )?
                     .into_iter(),
@@ -56,6 +56,9 @@ pub struct Record {
     /// Use Patience diff instead of the default Myers diff
     #[clap(long = "patience")]
     pub patience: bool,
+    /// Store a checksum of the contents of each recorded file
+    #[clap(long = "checksums")]
+    pub checksums: bool,
 }
 
 pub(crate) fn parse_datetime_rfc2822(s: &str) -> Result<i64, &'static str> {
@@ -77,6 +80,10 @@ impl Record {
         for h in repo.config.hooks.record.iter() {
             h.run(repo.path.clone())?
         }
+        let mut hook_description = Vec::new();
+        for h in repo.config.hooks.description.iter() {
+            hook_description.push(h.run_captured(repo.path.clone())?)
+        }
         let txn = repo.pristine.arc_txn_begin()?;
         let cur = txn
             .read()
@@ -94,24 +101,20 @@ impl Record {
             bail!("Channel {:?} not found", channel);
         };
 
-        let mut extra = Vec::new();
-        for h in repo.config.extra_dependencies.iter() {
-            let (h, c) = txn.read().hash_from_prefix(h)?;
-            if txn
-                .read()
-                .get_changeset(txn.read().changes(&*channel.read()), &c)?
-                .is_none()
-            {
-                bail!(
-                    "Change {:?} (from .pijul/config) is not on channel {:?}",
-                    h,
-                    channel.read().name
-                )
-            }
-            extra.push(h)
-        }
+        let extra = match repo.config.validate_extra_dependencies(
+            &*txn.read(),
+            &*channel.read(),
+            &repo.changes,
+        ) {
+            Ok(extra) => extra,
+            Err(invalid) => bail!(
+                "Invalid extra dependencies (from .pijul/config) on channel {:?}: {}",
+                channel.read().name,
+                invalid.join(", ")
+            ),
+        };
 
-        let header = if let Some(ref amend) = self.amend {
+        let mut header = if let Some(ref amend) = self.amend {
             let h = if let Some(ref hash) = amend {
                 txn.read().hash_from_prefix(hash)?.0
             } else if let Some(h) = txn.read().reverse_log(&*channel.read(), None)?.next() {
@@ -138,6 +141,9 @@ impl Record {
         } else {
             self.header().await?
         };
+        for d in hook_description.iter() {
+            header.append_description(d)
+        }
         let no_prefixes =
             self.prefixes.is_empty() && !self.ignore_missing && self.working_copy.is_none();
         let (repo_path, working_copy) = if let Some(ref w) = self.working_copy {
@@ -168,6 +174,8 @@ impl Record {
         )?;
         match result {
             Either::A((txn, mut change, updates, oldest)) => {
+                let pruned = prune_empty_atoms(&mut change);
+                debug!("pruned {:?} empty atoms", pruned);
                 let hash = repo.changes.save_change(&mut change, |change, hash| {
                     change.unhashed = Some(serde_json::json!({
                         "signature": secret.sign_raw(&hash.to_bytes()).unwrap(),
@@ -201,6 +209,15 @@ impl Record {
                     txn_.touch_channel(&mut *channel.write(), Some((oldest / 1000) * 1000));
                 }
                 std::mem::drop(txn_);
+                if self.checksums {
+                    let n = libpijul::output::record_file_checksums(
+                        &repo.changes,
+                        &txn,
+                        &channel,
+                        &hash,
+                    )?;
+                    debug!("stored {:?} checksums", n);
+                }
                 txn.commit()?;
             }
             Either::B(txn) => {
@@ -395,15 +412,15 @@ impl Record {
         } else {
             unreachable!()
         };
-        let mut change =
-            LocalChange::make_change(&*txn_, &channel, actions, contents, header, Vec::new())?;
-
-        let current: HashSet<_> = change.dependencies.iter().cloned().collect();
-        for dep in extra_deps.iter() {
-            if !current.contains(dep) {
-                change.dependencies.push(*dep)
-            }
-        }
+        let change = LocalChange::make_change_with_dependencies(
+            &*txn_,
+            &channel,
+            actions,
+            contents,
+            header,
+            Vec::new(),
+            extra_deps,
+        )?;
 
         debug!("has_binary = {:?}", rec.has_binary_files);
         let mut change = if self.all {
@@ -215,7 +215,7 @@ impl Reset {
                         path,
                         true,
                         None,
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                         0,
                     )?
                     .into_iter(),
@@ -239,7 +239,7 @@ impl Reset {
                         &path,
                         true,
                         None,
-                        std::thread::available_parallelism()?.get(),
+                        libpijul::default_parallelism(),
                         0,
                     )?
                     .into_iter(),
@@ -310,7 +310,7 @@ fn has_unrecorded_changes(
         &repo.working_copy,
         &repo.changes,
         "",
-        std::thread::available_parallelism()?.get(),
+        libpijul::default_parallelism(),
     )?;
     let rec = state.finish();
     debug!("actions = {:?}", rec.actions);
@@ -190,7 +190,7 @@ impl Tag {
                     "",
                     true,
                     None,
-                    std::thread::available_parallelism()?.get(),
+                    libpijul::default_parallelism(),
                     0,
                 )?;
                 if let Ok(txn) = std::sync::Arc::try_unwrap(txn.0) {
@@ -328,7 +328,7 @@ fn try_record<T: ChannelMutTxnT + TxnT + Send + Sync + 'static>(
         &repo.working_copy,
         &repo.changes,
         "",
-        std::thread::available_parallelism()?.get(),
+        libpijul::default_parallelism(),
     )?;
     let rec = state.finish();
     if !rec.actions.is_empty() {
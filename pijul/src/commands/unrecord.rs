@@ -152,7 +152,7 @@ impl Unrecord {
                 "",
                 true,
                 None,
-                std::thread::available_parallelism()?.get(),
+                libpijul::default_parallelism(),
                 0,
             )?;
         }
@@ -17,6 +17,9 @@ pub struct Opts {
     /// Abort rather than prompt for input
     #[clap(long, global = true)]
     pub no_prompt: bool,
+    /// Write prompts to stdout as JSON lines, and read answers from stdin
+    #[clap(long, global = true, conflicts_with = "no_prompt")]
+    pub json_prompts: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -154,11 +157,13 @@ async fn main() {
     }
 
     let opts = Opts::parse();
-    if opts.no_prompt {
-        pijul_interaction::set_context(InteractiveContext::NotInteractive);
+    pijul_interaction::set_context(if opts.json_prompts {
+        InteractiveContext::Json
     } else {
-        pijul_interaction::set_context(InteractiveContext::Terminal);
-    }
+        InteractiveContext::from_env(opts.no_prompt)
+    });
+    // Live progress bars and log lines would fight over the terminal.
+    pijul_interaction::set_progress_logging(std::env::var_os("RUST_LOG").is_some());
 
     if let Err(e) = run(opts).await {
         // This will only activate with the following environment variables: